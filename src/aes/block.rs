@@ -0,0 +1,189 @@
+use super::{KeyExpander, RijndaelCryptor, RijndaelMode};
+use crate::secret::zeroize;
+
+/// number of independent blocks `Rijndael` pushes through the rounds together
+const INTERLEAVE: usize = 4;
+
+/// A keyed block cipher working on whole blocks.
+///
+/// The batch methods process independent blocks and default to calling the
+/// single-block methods in turn; backends may override them to interleave
+/// or pipeline several blocks.
+pub trait BlockCipher<M: RijndaelMode> {
+    fn encrypt_block(&self, block: &mut M::Block);
+    fn decrypt_block(&self, block: &mut M::Block);
+
+    fn encrypt_blocks(&self, blocks: &mut [M::Block]) {
+        for block in blocks.iter_mut() {
+            self.encrypt_block(block);
+        }
+    }
+    fn decrypt_blocks(&self, blocks: &mut [M::Block]) {
+        for block in blocks.iter_mut() {
+            self.decrypt_block(block);
+        }
+    }
+}
+
+/// Rijndael keyed with both the encryption and the decryption key schedule,
+/// which are wiped when it is dropped
+pub struct Rijndael<M: RijndaelMode> {
+    key: M::KeySchedule,
+    dkey: M::KeySchedule,
+}
+
+impl<M: RijndaelMode> Drop for Rijndael<M> {
+    fn drop(&mut self) {
+        zeroize(self.key.as_mut());
+        zeroize(self.dkey.as_mut());
+    }
+}
+
+impl<M: RijndaelMode> Clone for Rijndael<M> {
+    fn clone(&self) -> Self {
+        Self {
+            key: self.key,
+            dkey: self.dkey,
+        }
+    }
+}
+
+impl<M: RijndaelMode> Rijndael<M> {
+    pub fn new(key: &M::Key) -> Self {
+        let mut words = KeyExpander::<M>::convert_key(key);
+        let cipher = Self::new_with_ext_key(KeyExpander::<M>::key_expansion(&words));
+        zeroize(words.as_mut());
+        cipher
+    }
+
+    pub fn new_with_ext_key(key: M::KeySchedule) -> Self {
+        Self {
+            dkey: KeyExpander::<M>::inv_key_expansion(&key),
+            key,
+        }
+    }
+}
+
+impl<M: RijndaelMode> BlockCipher<M> for Rijndael<M> {
+    fn encrypt_block(&self, block: &mut M::Block) {
+        *block =
+            RijndaelCryptor::<M>::new_with_raw_data_key(block.as_ref(), &self.key).encrypt_to_arr();
+    }
+
+    fn decrypt_block(&self, block: &mut M::Block) {
+        *block = RijndaelCryptor::<M>::new_with_raw_data_key(block.as_ref(), &self.dkey)
+            .decrypt_equivalent_to_arr();
+    }
+
+    fn encrypt_blocks(&self, blocks: &mut [M::Block]) {
+        for group in blocks.chunks_mut(INTERLEAVE) {
+            // one lane per block of the group, all borrowing the schedule
+            let mut lanes: [_; INTERLEAVE] = core::array::from_fn(|i| {
+                group
+                    .get(i)
+                    .map(|b| RijndaelCryptor::<M>::new_with_raw_data_key(b.as_ref(), &self.key))
+            });
+            each(&mut lanes, |c| c.add_round_key(0));
+            for i in 0..M::NR {
+                each(&mut lanes, |c| c.round(i + 1));
+            }
+            each(&mut lanes, |c| c.final_round(M::NR + 1));
+            for (block, c) in group.iter_mut().zip(lanes.iter().flatten()) {
+                *block = c.state_to_arr();
+            }
+        }
+    }
+
+    fn decrypt_blocks(&self, blocks: &mut [M::Block]) {
+        for group in blocks.chunks_mut(INTERLEAVE) {
+            let mut lanes: [_; INTERLEAVE] = core::array::from_fn(|i| {
+                group
+                    .get(i)
+                    .map(|b| RijndaelCryptor::<M>::new_with_raw_data_key(b.as_ref(), &self.dkey))
+            });
+            each(&mut lanes, |c| c.add_round_key(M::NR + 1));
+            for i in (0..M::NR).rev() {
+                each(&mut lanes, |c| c.inv_round(i + 1));
+            }
+            each(&mut lanes, |c| c.inv_final_round(0));
+            for (block, c) in group.iter_mut().zip(lanes.iter().flatten()) {
+                *block = c.state_to_arr();
+            }
+        }
+    }
+}
+
+/// Runs a step of the rounds over the lanes in use.
+fn each<M: RijndaelMode>(
+    lanes: &mut [Option<RijndaelCryptor<'_, M>>],
+    step: impl Fn(&mut RijndaelCryptor<'_, M>),
+) {
+    lanes.iter_mut().flatten().for_each(step);
+}
+
+#[cfg(test)]
+macro_rules! impl_test_batch {
+    ($mode: ty, $key: literal) => {{
+        use rand::prelude::*;
+        use std::convert::TryInto;
+
+        let key = hex::decode($key).unwrap();
+        let cipher = Rijndael::<$mode>::new(key.as_slice().try_into().unwrap());
+        // cover empty input, a partial interleave group and several full ones
+        for n in 0..=(INTERLEAVE * 3 + 1) {
+            let mut plain = vec![[0u8; 16]; n];
+            plain
+                .iter_mut()
+                .for_each(|b| rand::thread_rng().fill_bytes(b));
+
+            let mut single = plain.clone();
+            single.iter_mut().for_each(|b| cipher.encrypt_block(b));
+            let mut batch = plain.clone();
+            cipher.encrypt_blocks(&mut batch);
+            assert_eq!(batch, single);
+
+            cipher.decrypt_blocks(&mut batch);
+            single.iter_mut().for_each(|b| cipher.decrypt_block(b));
+            assert_eq!(batch, single);
+            assert_eq!(batch, plain);
+        }
+    }};
+}
+
+#[cfg(test)]
+#[test]
+fn test_batch() {
+    impl_test_batch!(super::AES128, "2b7e151628aed2a6abf7158809cf4f3c");
+    impl_test_batch!(
+        super::AES192,
+        "8e73b0f7da0e6452c810f32b809079e562f8ead2522c6b7b"
+    );
+    impl_test_batch!(
+        super::AES256,
+        "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_drop_wipes_keys() {
+    use core::mem::{size_of, MaybeUninit};
+
+    /// Drops `value` where it lies, and returns the bytes left behind.
+    fn remains<T>(value: T) -> Vec<u8> {
+        let mut slot = MaybeUninit::new(value);
+        // SAFETY: the value is dropped once and its storage only read after
+        unsafe {
+            core::ptr::drop_in_place(slot.as_mut_ptr());
+            core::slice::from_raw_parts(slot.as_ptr() as *const u8, size_of::<T>()).to_vec()
+        }
+    }
+
+    let cipher = Rijndael::<super::AES256>::new(&[0x2b; 32]);
+    assert!(remains(cipher.clone()).iter().all(|&b| b == 0));
+    // a cryptor borrows the schedule, so there is no copy of it to wipe
+    assert_eq!(
+        size_of::<RijndaelCryptor<'_, super::AES256>>(),
+        size_of::<super::State<super::AES256>>() + size_of::<&[u32; 60]>()
+    );
+}
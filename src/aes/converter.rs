@@ -1,4 +1,4 @@
-use super::{encrypt::State, sbox_get, RijndaelMode};
+use super::{encrypt::galois_mul, sbox_get, Array, RijndaelMode, State};
 
 pub fn byte_to_word(bs: &[u8; 4]) -> u32 {
     ((bs[0] as u32) << 24) | ((bs[1] as u32) << 16) | ((bs[2] as u32) << 8) | bs[3] as u32
@@ -11,7 +11,7 @@ fn test_byte_to_word() {
 }
 
 pub fn rot_word(w: u32) -> u32 {
-    (w << 8) | (w >> 24)
+    w.rotate_left(8)
 }
 
 #[cfg(test)]
@@ -34,6 +34,28 @@ fn test_sub_word() {
     assert_eq!(sub_word(0x12345678), 0xC918B1BC);
 }
 
+/// InvMixColumns applied to a single column word
+pub fn inv_mix_word(w: u32) -> u32 {
+    use galois_mul as gm;
+    let (s0, s1, s2, s3) = word_to_bytes(w);
+    byte_to_word(&[
+        gm(0x0e, s0) ^ gm(0x0b, s1) ^ gm(0x0d, s2) ^ gm(0x09, s3),
+        gm(0x09, s0) ^ gm(0x0e, s1) ^ gm(0x0b, s2) ^ gm(0x0d, s3),
+        gm(0x0d, s0) ^ gm(0x09, s1) ^ gm(0x0e, s2) ^ gm(0x0b, s3),
+        gm(0x0b, s0) ^ gm(0x0d, s1) ^ gm(0x09, s2) ^ gm(0x0e, s3),
+    ])
+}
+
+#[cfg(test)]
+#[test]
+fn test_inv_mix_word() {
+    // the examples are from https://en.wikipedia.org/wiki/Rijndael_MixColumns#Test_vectors_for_MixColumn()
+    assert_eq!(inv_mix_word(0x8e4da1bc), 0xdb135345);
+    assert_eq!(inv_mix_word(0x9fdc589d), 0xf20a225c);
+    assert_eq!(inv_mix_word(0x01010101), 0x01010101);
+    assert_eq!(inv_mix_word(0xd5d5d7d6), 0xd4d4d4d5);
+}
+
 pub fn word_to_bytes(w: u32) -> (u8, u8, u8, u8) {
     (
         (0xFF & (w >> 24)) as u8,
@@ -50,10 +72,10 @@ fn test_word_to_bytes() {
     assert_eq!(word_to_bytes(0x89ABCDEF), (0x89, 0xAB, 0xCD, 0xEF));
 }
 
-pub fn words_to_matrix<M: RijndaelMode>(input: &[u32; M::NB_WORDS]) -> State<M> {
+pub fn words_to_matrix<M: RijndaelMode>(input: &M::BlockWords) -> State<M> {
     let mut state = State::<M>::zeros();
-    for i in 0..M::NB_WORDS {
-        let (b0, b1, b2, b3) = word_to_bytes(input[i]);
+    for (i, word) in input.as_ref().iter().enumerate() {
+        let (b0, b1, b2, b3) = word_to_bytes(*word);
         state[(0, i)] = b0;
         state[(1, i)] = b1;
         state[(2, i)] = b2;
@@ -77,10 +99,10 @@ fn test_words_to_matrix() {
     );
 }
 
-pub fn matrix_to_words<M: RijndaelMode>(state: &State<M>) -> [u32; M::NB_WORDS] {
-    let mut output = [0; M::NB_WORDS];
-    for i in 0..M::NB_WORDS {
-        output[i] = byte_to_word(&[state[(0, i)], state[(1, i)], state[(2, i)], state[(3, i)]]);
+pub fn matrix_to_words<M: RijndaelMode>(state: &State<M>) -> M::BlockWords {
+    let mut output = M::BlockWords::zeroed();
+    for (i, word) in output.as_mut().iter_mut().enumerate() {
+        *word = byte_to_word(&[state[(0, i)], state[(1, i)], state[(2, i)], state[(3, i)]]);
     }
     output
 }
@@ -2,9 +2,8 @@ use super::consts::{inv_sbox_get, sbox_get};
 use super::converter::{byte_to_word, word_to_bytes};
 #[cfg(test)]
 use super::key_expansion::KeyExpander;
-use super::{matrix_to_words, words_to_matrix, RijndaelMode};
-use nalgebra::SMatrix;
-use std::convert::TryInto;
+use super::{matrix_to_words, words_to_matrix, Array, RijndaelMode, State};
+use crate::secret::zeroize;
 
 pub fn galois_mul(mut a: u8, mut b: u8) -> u8 {
     // Galois Field (256) Multiplication of two Bytes
@@ -26,70 +25,32 @@ pub fn galois_mul(mut a: u8, mut b: u8) -> u8 {
     p
 }
 
-#[allow(type_alias_bounds)]
-pub type State<M: RijndaelMode> = SMatrix<u8, 4, { <M as RijndaelMode>::NB_WORDS }>;
-
-pub struct RijndaelCryptor<M: RijndaelMode>
-where
-    [(); M::NB_WORDS]:,
-    [(); M::NR_KEY]:,
-{
+/// One block going through the rounds, under a key schedule it borrows
+/// rather than copies, so any number of blocks can share one
+pub struct RijndaelCryptor<'k, M: RijndaelMode> {
     state: State<M>,
-    keys: [State<M>; M::NR_KEY],
+    keys: &'k M::KeySchedule,
 }
 
-impl<M: RijndaelMode> RijndaelCryptor<M>
-where
-    [(); M::NB_WORDS]:,
-    [(); M::NR_KEY]:,
-    [(); M::NR_KEY * M::NB_WORDS]:,
-    [(); M::NK_WORDS]:,
-    [(); M::NK_WORDS * 4]:,
-{
-    pub fn new(input: &[u32; M::NB_WORDS], key: &[u32; M::NR_KEY * M::NB_WORDS]) -> Self {
-        let state = words_to_matrix::<M>(input);
-        let mut keys = [SMatrix::zeros(); M::NR_KEY];
-        for i in 0..M::NR_KEY {
-            keys[i] =
-                words_to_matrix::<M>(&key[(i * 4)..(i * 4 + M::NB_WORDS)].try_into().unwrap());
-        }
-        Self { state, keys }
+impl<M: RijndaelMode> Drop for RijndaelCryptor<'_, M> {
+    fn drop(&mut self) {
+        zeroize(self.state.columns_mut());
     }
+}
 
-    #[cfg(test)]
-    pub fn new_with_raw_data(input: &[u8], key: &[u8]) -> Self {
-        assert_eq!(input.len(), M::NB_WORDS * 4);
-        assert_eq!(key.len(), M::NK_WORDS * 4);
-        let mut input_arr = [0; M::NB_WORDS];
-        let mut key_arr = [0; M::NB_WORDS];
-        for i in 0..M::NB_WORDS {
-            input_arr[i] = byte_to_word(&[
-                input[i * 4],
-                input[i * 4 + 1],
-                input[i * 4 + 2],
-                input[i * 4 + 3],
-            ]);
-        }
-        for i in 0..M::NB_WORDS {
-            key_arr[i] =
-                byte_to_word(&[key[i * 4], key[i * 4 + 1], key[i * 4 + 2], key[i * 4 + 3]]);
+impl<'k, M: RijndaelMode> RijndaelCryptor<'k, M> {
+    pub fn new(input: &M::BlockWords, key: &'k M::KeySchedule) -> Self {
+        Self {
+            state: words_to_matrix::<M>(input),
+            keys: key,
         }
-        let key_arr = KeyExpander::<M>::key_expansion(&KeyExpander::<M>::convert_key(
-            key.try_into().unwrap(),
-        ));
-        Self::new(&input_arr, &key_arr)
     }
 
-    pub fn new_with_raw_data_key(input: &[u8], key: &[u32; M::NR_KEY * M::NB_WORDS]) -> Self {
+    pub fn new_with_raw_data_key(input: &[u8], key: &'k M::KeySchedule) -> Self {
         assert_eq!(input.len(), M::NB_WORDS * 4);
-        let mut input_arr = [0; M::NB_WORDS];
-        for i in 0..M::NB_WORDS {
-            input_arr[i] = byte_to_word(&[
-                input[i * 4],
-                input[i * 4 + 1],
-                input[i * 4 + 2],
-                input[i * 4 + 3],
-            ]);
+        let mut input_arr = M::BlockWords::zeroed();
+        for (word, bytes) in input_arr.as_mut().iter_mut().zip(input.chunks_exact(4)) {
+            *word = byte_to_word(&[bytes[0], bytes[1], bytes[2], bytes[3]]);
         }
         Self::new(&input_arr, key)
     }
@@ -100,46 +61,40 @@ where
     }
 
     pub fn add_round_key(&mut self, key_idx: usize) {
-        let key = &self.keys[key_idx];
-        for (i, mut row) in self.state.row_iter_mut().enumerate() {
-            for (j, cell) in row.iter_mut().enumerate() {
-                *cell ^= key[(i, j)];
+        let key = &self.keys.as_ref()[(key_idx * M::NB_WORDS)..((key_idx + 1) * M::NB_WORDS)];
+        for (col, key_word) in self.state.columns_mut().iter_mut().zip(key) {
+            for (cell, k) in col.iter_mut().zip(key_word.to_be_bytes().iter()) {
+                *cell ^= k;
             }
         }
     }
 
     pub fn sub_bytes(&mut self) {
-        for mut row in self.state.row_iter_mut() {
-            for cell in row.iter_mut() {
-                *cell = sbox_get(*cell);
-            }
+        for cell in self.state.columns_mut().iter_mut().flatten() {
+            *cell = sbox_get(*cell);
         }
     }
 
     pub fn inv_sub_bytes(&mut self) {
-        for mut row in self.state.row_iter_mut() {
-            for cell in row.iter_mut() {
-                *cell = inv_sbox_get(*cell);
-            }
+        for cell in self.state.columns_mut().iter_mut().flatten() {
+            *cell = inv_sbox_get(*cell);
         }
     }
 
     pub fn shift_row(&mut self, row_id: usize, count: usize) {
-        let row_len = self.state.row(row_id).len();
-        for _ in 0..count {
-            for i in 0..(row_len - 1) {
-                self.state.swap((row_id, i), (row_id, (i + 1) % row_len));
-            }
+        // Rijndael blocks have at most 8 columns
+        let mut row = [0; 8];
+        let columns = self.state.columns_mut();
+        for (cell, col) in row.iter_mut().zip(columns.iter()) {
+            *cell = col[row_id];
+        }
+        for (c, col) in columns.iter_mut().enumerate() {
+            col[row_id] = row[(c + count) % M::NB_WORDS];
         }
     }
 
     pub fn inv_shift_row(&mut self, row_id: usize, count: usize) {
-        let row_len = self.state.row(row_id).len();
-        for _ in 0..count {
-            for i in (0..(row_len - 1)).rev() {
-                self.state.swap((row_id, i), (row_id, (i + 1) % row_len));
-            }
-        }
+        self.shift_row(row_id, M::NB_WORDS - count % M::NB_WORDS);
     }
 
     pub fn shift_rows(&mut self) {
@@ -180,35 +135,64 @@ where
     }
 
     pub fn mix_columns(&mut self) {
-        for col in 0..self.state.column_iter().len() {
+        for col in 0..M::NB_WORDS {
             self.mix_column(col);
         }
     }
 
     pub fn inv_mix_columns(&mut self) {
-        for col in 0..self.state.column_iter().len() {
+        for col in 0..M::NB_WORDS {
             self.inv_mix_column(col);
         }
     }
 
-    pub fn encrypt(mut self) -> [u32; M::NB_WORDS] {
+    /// a full cipher round
+    pub fn round(&mut self, key_idx: usize) {
+        self.sub_bytes();
+        self.shift_rows();
+        self.mix_columns();
+        self.add_round_key(key_idx);
+    }
+
+    /// a full round of the equivalent inverse cipher, which has the same
+    /// structure as [`Self::round`] and expects a decryption key schedule
+    /// from [`crate::aes::KeyExpander::inv_key_expansion`]
+    pub fn inv_round(&mut self, key_idx: usize) {
+        self.inv_sub_bytes();
+        self.inv_shift_rows();
+        self.inv_mix_columns();
+        self.add_round_key(key_idx);
+    }
+
+    /// the final cipher round, which has no MixColumns
+    pub fn final_round(&mut self, key_idx: usize) {
+        self.sub_bytes();
+        self.shift_rows();
+        self.add_round_key(key_idx);
+    }
+
+    /// the final round of the equivalent inverse cipher, which has no InvMixColumns
+    pub fn inv_final_round(&mut self, key_idx: usize) {
+        self.inv_sub_bytes();
+        self.inv_shift_rows();
+        self.add_round_key(key_idx);
+    }
+
+    pub fn encrypt(mut self) -> M::BlockWords {
         self.add_round_key(0);
 
         for i in 0..M::NR {
-            self.sub_bytes();
-            self.shift_rows();
-            self.mix_columns();
-            self.add_round_key(i + 1);
+            self.round(i + 1);
         }
 
-        self.sub_bytes();
-        self.shift_rows();
-        self.add_round_key(M::NR + 1);
+        self.final_round(M::NR + 1);
 
         matrix_to_words::<M>(&self.state)
     }
 
-    pub fn decrypt(mut self) -> [u32; M::NB_WORDS] {
+    /// straightforward inverse cipher, kept as a reference for
+    /// [`Self::decrypt_equivalent`]
+    pub fn decrypt(mut self) -> M::BlockWords {
         self.add_round_key(M::NR + 1);
 
         for i in (0..M::NR).rev() {
@@ -225,44 +209,68 @@ where
         matrix_to_words::<M>(&self.state)
     }
 
-    pub fn encrypt_to_arr(self) -> [u8; M::NB_WORDS * 4] {
-        let res = self.encrypt();
-        let mut ret = [0; M::NB_WORDS * 4];
-        for i in 0..res.len() {
-            let (r0, r1, r2, r3) = word_to_bytes(res[i]);
-            ret[i * 4] = r0;
-            ret[i * 4 + 1] = r1;
-            ret[i * 4 + 2] = r2;
-            ret[i * 4 + 3] = r3;
+    /// equivalent inverse cipher, the cryptor must be created with a
+    /// decryption key schedule from [`crate::aes::KeyExpander::inv_key_expansion`]
+    pub fn decrypt_equivalent(mut self) -> M::BlockWords {
+        self.add_round_key(M::NR + 1);
+
+        for i in (0..M::NR).rev() {
+            self.inv_round(i + 1);
         }
-        ret
+
+        self.inv_final_round(0);
+
+        matrix_to_words::<M>(&self.state)
     }
 
-    pub fn decrypt_to_arr(self) -> [u8; M::NB_WORDS * 4] {
-        let res = self.decrypt();
-        let mut ret = [0; M::NB_WORDS * 4];
-        for i in 0..res.len() {
-            let (r0, r1, r2, r3) = word_to_bytes(res[i]);
-            ret[i * 4] = r0;
-            ret[i * 4 + 1] = r1;
-            ret[i * 4 + 2] = r2;
-            ret[i * 4 + 3] = r3;
+    fn words_to_arr(res: M::BlockWords) -> M::Block {
+        let mut ret = M::Block::zeroed();
+        for (bytes, w) in ret.as_mut().chunks_exact_mut(4).zip(res.as_ref()) {
+            let (r0, r1, r2, r3) = word_to_bytes(*w);
+            bytes.copy_from_slice(&[r0, r1, r2, r3]);
         }
         ret
     }
+
+    pub fn state_to_arr(&self) -> M::Block {
+        Self::words_to_arr(matrix_to_words::<M>(&self.state))
+    }
+
+    pub fn encrypt_to_arr(self) -> M::Block {
+        Self::words_to_arr(self.encrypt())
+    }
+
+    pub fn decrypt_to_arr(self) -> M::Block {
+        Self::words_to_arr(self.decrypt())
+    }
+
+    pub fn decrypt_equivalent_to_arr(self) -> M::Block {
+        Self::words_to_arr(self.decrypt_equivalent())
+    }
+}
+
+/// The key schedule of the raw `key`.
+#[cfg(test)]
+fn schedule<M: RijndaelMode>(key: &[u8]) -> M::KeySchedule {
+    assert_eq!(key.len(), M::NK_WORDS * 4);
+    KeyExpander::<M>::key_expansion(&KeyExpander::<M>::convert_key(&M::Key::from_slice(key)))
 }
 
 macro_rules! _make_test {
     ($mode:ty, $key:literal, $val:literal, $enc:literal) => {
         let plain = hex::decode($val).unwrap();
         let enc = hex::decode($enc).unwrap();
-        let key = hex::decode($key).unwrap();
-        let cryptor = RijndaelCryptor::<$mode>::new_with_raw_data(&plain, &key);
+        let key = schedule::<$mode>(&hex::decode($key).unwrap());
+        let cryptor = RijndaelCryptor::<$mode>::new_with_raw_data_key(&plain, &key);
         let ciphertext = cryptor.encrypt_to_arr();
         assert_eq!(hex::encode(ciphertext), $enc);
-        let cryptor = RijndaelCryptor::<$mode>::new_with_raw_data(&enc, &key);
+        let cryptor = RijndaelCryptor::<$mode>::new_with_raw_data_key(&enc, &key);
         let plaintext = cryptor.decrypt_to_arr();
         assert_eq!(hex::encode(plaintext), $val);
+        let dkey = KeyExpander::<$mode>::inv_key_expansion(&key);
+        let cryptor = RijndaelCryptor::<$mode>::new_with_raw_data_key(&enc, &dkey);
+        let plaintext = cryptor.decrypt_equivalent_to_arr();
+        assert_eq!(hex::encode(plaintext), $val);
     };
 }
 
@@ -270,8 +278,8 @@ macro_rules! _make_test {
 #[test]
 pub fn test_rijndael_iter() {
     let plain = hex::decode("f34481ec3cc627bacd5dc3fbdb135345").unwrap();
-    let key = hex::decode("00000000000000000000000000000000").unwrap();
-    let mut cryptor = RijndaelCryptor::<super::AES128>::new_with_raw_data(&plain, &key);
+    let key = schedule::<super::AES128>(&[0; 16]);
+    let mut cryptor = RijndaelCryptor::<super::AES128>::new_with_raw_data_key(&plain, &key);
     cryptor.add_round_key(0);
     assert_eq!(
         cryptor._test_get_state(),
@@ -1,13 +1,13 @@
-use super::{byte_to_word, rcon_get, rot_word, sub_word, RijndaelMode};
-use std::marker::PhantomData;
+use super::{byte_to_word, inv_mix_word, rcon_get, rot_word, sub_word, Array, RijndaelMode};
+use core::marker::PhantomData;
 
 pub struct KeyExpander<M: RijndaelMode>(PhantomData<M>);
 
 impl<M: RijndaelMode> KeyExpander<M> {
-    pub fn convert_key(raw: &[u8; M::NK_WORDS * 4]) -> [u32; M::NK_WORDS] {
-        let mut r = [0; M::NK_WORDS];
-        for i in 0..M::NK_WORDS {
-            r[i] = byte_to_word(&[raw[i * 4], raw[i * 4 + 1], raw[i * 4 + 2], raw[i * 4 + 3]]);
+    pub fn convert_key(raw: &M::Key) -> M::KeyWords {
+        let mut r = M::KeyWords::zeroed();
+        for (word, bytes) in r.as_mut().iter_mut().zip(raw.as_ref().chunks_exact(4)) {
+            *word = byte_to_word(&[bytes[0], bytes[1], bytes[2], bytes[3]]);
         }
         r
     }
@@ -18,12 +18,14 @@ impl<M: RijndaelMode> KeyExpander<M> {
     /// AES key schedule algorithm
     /// # See
     /// See [AES Key Schedule](https://en.wikipedia.org/wiki/AES_key_expansion#The_key_expansion)
-    pub fn key_expansion(k: &[u32; M::NK_WORDS]) -> [u32; M::NR_KEY * M::NB_WORDS] {
+    pub fn key_expansion(k: &M::KeyWords) -> M::KeySchedule {
         let n = M::NK_WORDS;
         let b = M::NB_WORDS;
         let r = M::NR_KEY;
 
-        let mut w = [0; M::NR_KEY * M::NB_WORDS];
+        let k = k.as_ref();
+        let mut schedule = M::KeySchedule::zeroed();
+        let w = schedule.as_mut();
         for i in 0..(b * r) {
             if i < n {
                 w[i] = k[i];
@@ -36,7 +38,20 @@ impl<M: RijndaelMode> KeyExpander<M> {
             }
         }
 
-        w
+        schedule
+    }
+
+    /// Decryption key schedule for the equivalent inverse cipher, which is
+    /// the encryption key schedule with InvMixColumns applied to every round
+    /// key except the first and the last one
+    /// # See
+    /// See FIPS-197 §5.3.5 [Equivalent Inverse Cipher](https://nvlpubs.nist.gov/nistpubs/FIPS/NIST.FIPS.197.pdf)
+    pub fn inv_key_expansion(w: &M::KeySchedule) -> M::KeySchedule {
+        let mut dw = *w;
+        for word in dw.as_mut()[M::NB_WORDS..((M::NR_KEY - 1) * M::NB_WORDS)].iter_mut() {
+            *word = inv_mix_word(*word);
+        }
+        dw
     }
 }
 
@@ -60,3 +75,24 @@ fn test_key_expansion() {
         ]
     );
 }
+
+#[cfg(test)]
+#[test]
+fn test_inv_key_expansion() {
+    type Expander = KeyExpander<super::AES128>;
+
+    let k = b"\x2B\x7E\x15\x16\x28\xAE\xD2\xA6\xAB\xF7\x15\x88\x09\xCF\x4F\x3C";
+    let dw = Expander::inv_key_expansion(&Expander::key_expansion(&Expander::convert_key(k)));
+    assert_eq!(
+        dw,
+        [
+            0x2b7e1516, 0x28aed2a6, 0xabf71588, 0x09cf4f3c, 0x2b3708a7, 0xf262d405, 0xbc3ebdbf,
+            0x4b617d62, 0xcc7505eb, 0x3e17d1ee, 0x82296c51, 0xc9481133, 0x7c1f13f7, 0x4208c219,
+            0xc021ae48, 0x0969bf7b, 0x90884413, 0xd280860a, 0x12a12842, 0x1bc89739, 0x6ea30afc,
+            0xbc238cf6, 0xae82a4b4, 0xb54a338d, 0x6efcd876, 0xd2df5480, 0x7c5df034, 0xc917c3b9,
+            0x12c07647, 0xc01f22c7, 0xbc42d2f3, 0x7555114a, 0xdf7d925a, 0x1f62b09d, 0xa320626e,
+            0xd6757324, 0x0c7b5a63, 0x1319eafe, 0xb0398890, 0x664cfbb4, 0xd014f9a8, 0xc9ee2589,
+            0xe13f0cc8, 0xb6630ca6
+        ]
+    );
+}
@@ -5,13 +5,19 @@ mod key_expansion;
 pub use key_expansion::KeyExpander;
 
 mod mode;
-pub use mode::RijndaelMode;
+pub use mode::{Array, RijndaelMode};
 pub use mode::{AES128, AES192, AES256};
 
+mod state;
+pub use state::State;
+
 mod encrypt;
-pub use encrypt::{RijndaelCryptor, State};
+pub use encrypt::RijndaelCryptor;
+
+mod block;
+pub use block::{BlockCipher, Rijndael};
 
 mod converter;
-pub use converter::{
-    byte_to_word, matrix_to_words, rot_word, sub_word, word_to_bytes, words_to_matrix,
+pub(crate) use converter::{
+    byte_to_word, inv_mix_word, matrix_to_words, rot_word, sub_word, words_to_matrix,
 };
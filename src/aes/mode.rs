@@ -1,4 +1,31 @@
-pub trait RijndaelMode {
+use core::fmt::Debug;
+
+/// A fixed-size array, which carries the compile-time sizes of a
+/// `RijndaelMode` as associated types
+pub trait Array<T: Copy>:
+    AsRef<[T]> + AsMut<[T]> + Copy + Debug + PartialEq + Eq + Send + Sync + 'static
+{
+    /// an array filled with `T::default()`
+    fn zeroed() -> Self;
+
+    /// copies the elements of `data`, which must have exactly `LEN` elements
+    fn from_slice(data: &[T]) -> Self {
+        let mut ret = Self::zeroed();
+        ret.as_mut().copy_from_slice(data);
+        ret
+    }
+}
+
+impl<T, const N: usize> Array<T> for [T; N]
+where
+    T: Copy + Default + Debug + Eq + Send + Sync + 'static,
+{
+    fn zeroed() -> Self {
+        [T::default(); N]
+    }
+}
+
+pub trait RijndaelMode: 'static {
     /// key length
     const NK_WORDS: usize;
     /// block size
@@ -7,6 +34,19 @@ pub trait RijndaelMode {
     const NR_KEY: usize;
     /// round count (which equals `max(Nk, Nb) + 5`)
     const NR: usize;
+
+    /// raw key, `[u8; NK_WORDS * 4]`
+    type Key: Array<u8>;
+    /// key in words, `[u32; NK_WORDS]`
+    type KeyWords: Array<u32>;
+    /// expanded key schedule, `[u32; NR_KEY * NB_WORDS]`
+    type KeySchedule: Array<u32>;
+    /// raw block, `[u8; NB_WORDS * 4]`
+    type Block: Array<u8>;
+    /// block in words, `[u32; NB_WORDS]`
+    type BlockWords: Array<u32>;
+    /// state columns, `[[u8; 4]; NB_WORDS]`
+    type Columns: Array<[u8; 4]>;
 }
 
 macro_rules! impl_length_mode {
@@ -20,6 +60,13 @@ macro_rules! impl_length_mode {
             // https://github.com/rust-lang/rust/issues/29661
             const NR_KEY: usize = crate::max($nk, $nb) + 7;
             const NR: usize = crate::max($nk, $nb) + 5;
+
+            type Key = [u8; $nk * 4];
+            type KeyWords = [u32; $nk];
+            type KeySchedule = [u32; (crate::max($nk, $nb) + 7) * $nb];
+            type Block = [u8; $nb * 4];
+            type BlockWords = [u32; $nb];
+            type Columns = [[u8; 4]; $nb];
         }
     };
 }
@@ -0,0 +1,98 @@
+use super::{Array, RijndaelMode};
+use core::{
+    fmt,
+    ops::{Index, IndexMut},
+};
+
+/// Cipher state, stored as `NB_WORDS` columns of 4 bytes and indexed by
+/// `(row, column)`
+pub struct State<M: RijndaelMode>(M::Columns);
+
+impl<M: RijndaelMode> State<M> {
+    pub fn zeros() -> Self {
+        Self(M::Columns::zeroed())
+    }
+
+    pub fn from_column_slice(data: &[u8]) -> Self {
+        assert_eq!(data.len(), M::NB_WORDS * 4);
+        let mut state = Self::zeros();
+        for (col, bytes) in state.0.as_mut().iter_mut().zip(data.chunks_exact(4)) {
+            col.copy_from_slice(bytes);
+        }
+        state
+    }
+
+    pub fn from_row_slice(data: &[u8]) -> Self {
+        assert_eq!(data.len(), M::NB_WORDS * 4);
+        let mut state = Self::zeros();
+        for (r, row) in data.chunks_exact(M::NB_WORDS).enumerate() {
+            for (c, byte) in row.iter().enumerate() {
+                state[(r, c)] = *byte;
+            }
+        }
+        state
+    }
+
+    pub fn columns_mut(&mut self) -> &mut [[u8; 4]] {
+        self.0.as_mut()
+    }
+}
+
+impl<M: RijndaelMode> Clone for State<M> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<M: RijndaelMode> Copy for State<M> {}
+
+impl<M: RijndaelMode> PartialEq for State<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<M: RijndaelMode> Eq for State<M> {}
+
+impl<M: RijndaelMode> fmt::Debug for State<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("State").field(&self.0).finish()
+    }
+}
+
+impl<M: RijndaelMode> Index<(usize, usize)> for State<M> {
+    type Output = u8;
+
+    fn index(&self, (row, col): (usize, usize)) -> &u8 {
+        &self.0.as_ref()[col][row]
+    }
+}
+
+impl<M: RijndaelMode> IndexMut<(usize, usize)> for State<M> {
+    fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut u8 {
+        &mut self.0.as_mut()[col][row]
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_layout() {
+    use super::AES128;
+    let state = State::<AES128>::from_column_slice(&[
+        0x00, 0x01, 0x02, 0x03, // col 0
+        0x10, 0x11, 0x12, 0x13, // col 1
+        0x20, 0x21, 0x22, 0x23, // col 2
+        0x30, 0x31, 0x32, 0x33, // col 3
+    ]);
+    assert_eq!(state[(1, 2)], 0x21);
+    assert_eq!(state[(3, 0)], 0x03);
+    assert_eq!(
+        state,
+        State::<AES128>::from_row_slice(&[
+            0x00, 0x10, 0x20, 0x30, // row 0
+            0x01, 0x11, 0x21, 0x31, // row 1
+            0x02, 0x12, 0x22, 0x32, // row 2
+            0x03, 0x13, 0x23, 0x33, // row 3
+        ])
+    );
+}
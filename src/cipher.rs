@@ -0,0 +1,447 @@
+//! Ciphers selected at runtime by a spec such as `aes-128-cbc-pkcs7`, in the
+//! form `<algo>-<keybits>-<mode>[-<padding>]`
+
+use std::{fmt, str::FromStr};
+
+use crate::aes::{Array, RijndaelMode, AES128, AES192, AES256};
+use crate::error::{Error, Result};
+use crate::padding::{NoPadding, Padding, ZeroPadding, ISO10126, ISO7816, PKCS7, X923};
+use crate::secret::zeroize;
+use crate::stream::{
+    CipherBlockChaining, Decryptor, ElectronicCodeBook, Encryptor, ParallelEngine, Streamer,
+};
+
+pub trait StreamCipher {
+    fn new(key: &[u8], iv: &[u8]) -> Result<Self>
+    where
+        Self: Sized;
+    fn set_threads(&mut self, threads: usize);
+
+    /// Encrypts the next chunk of a message, appending the ciphertext of the
+    /// whole blocks so far to `out`.
+    fn encrypt_update(&mut self, data: &[u8], out: &mut Vec<u8>);
+    /// Pads and encrypts the end of the message, appending it to `out`.
+    fn encrypt_finalize(&mut self, out: &mut Vec<u8>) -> Result<()>;
+    /// Decrypts the next chunk of a message, appending the plaintext of the
+    /// whole blocks so far but the last one to `out`.
+    fn decrypt_update(&mut self, data: &[u8], out: &mut Vec<u8>);
+    /// Decrypts and unpads the end of the message, appending it to `out`.
+    fn decrypt_finalize(&mut self, out: &mut Vec<u8>) -> Result<()>;
+
+    fn encrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(data.len() + 32);
+        self.encrypt_update(data, &mut out);
+        self.encrypt_finalize(&mut out)?;
+        Ok(out)
+    }
+    fn decrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(data.len());
+        self.decrypt_update(data, &mut out);
+        self.decrypt_finalize(&mut out)?;
+        Ok(out)
+    }
+}
+
+/// A streamer `S` of `M` and `P`, run through a [`ParallelEngine`]
+struct Cipher<M: RijndaelMode, P: Padding, S: Streamer<M, P>> {
+    enc: Encryptor<M, P, S>,
+    dec: Decryptor<M, P, S>,
+    engine: ParallelEngine,
+}
+
+/// Runs `update` on `room` bytes at the end of `out`, keeping what it wrote.
+fn append(
+    out: &mut Vec<u8>,
+    room: usize,
+    update: impl FnOnce(&mut [u8]) -> Result<usize>,
+) -> Result<()> {
+    let start = out.len();
+    out.resize(start + room, 0);
+    let written = update(&mut out[start..]);
+    out.truncate(start + *written.as_ref().unwrap_or(&0));
+    written.map(|_| ())
+}
+
+impl<M, P, S> StreamCipher for Cipher<M, P, S>
+where
+    M: RijndaelMode,
+    P: Padding,
+    S: Streamer<M, P> + Send,
+{
+    fn new(key: &[u8], iv: &[u8]) -> Result<Self> {
+        if key.len() != M::NK_WORDS * 4 {
+            return Err(Error::InvalidKeyLength {
+                expected: M::NK_WORDS * 4,
+                actual: key.len(),
+            });
+        }
+        if iv.len() != M::NB_WORDS * 4 {
+            return Err(Error::InvalidIvLength {
+                expected: M::NB_WORDS * 4,
+                actual: iv.len(),
+            });
+        }
+        let (iv, mut key): (_, M::Key) = (Array::from_slice(iv), Array::from_slice(key));
+        let cipher = Self {
+            enc: Encryptor::new(S::new(iv, key)),
+            dec: Decryptor::new(S::new(iv, key)),
+            engine: ParallelEngine::new(1),
+        };
+        zeroize(key.as_mut());
+        Ok(cipher)
+    }
+
+    fn set_threads(&mut self, threads: usize) {
+        self.engine.threads = threads;
+    }
+
+    fn encrypt_update(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        let (enc, engine) = (&mut self.enc, &self.engine);
+        let room = data.len() + M::NB_WORDS * 4;
+        append(out, room, |out| {
+            Ok(enc.update_with(data, out, |s, d| engine.encrypt_in_place(s, d)))
+        })
+        .unwrap();
+    }
+
+    fn encrypt_finalize(&mut self, out: &mut Vec<u8>) -> Result<()> {
+        append(out, M::NB_WORDS * 4, |out| self.enc.finalize(out))
+    }
+
+    fn decrypt_update(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        let (dec, engine) = (&mut self.dec, &self.engine);
+        let room = data.len() + M::NB_WORDS * 4;
+        append(out, room, |out| {
+            // `update_with` only hands over whole blocks, which cannot fail
+            Ok(dec.update_with(data, out, |s, d| engine.decrypt_in_place(s, d).unwrap()))
+        })
+        .unwrap();
+    }
+
+    fn decrypt_finalize(&mut self, out: &mut Vec<u8>) -> Result<()> {
+        append(out, M::NB_WORDS * 4, |out| self.dec.finalize(out))
+    }
+}
+
+/// Declares a component of a cipher spec along with its names.
+macro_rules! impl_component {
+    ($(#[$meta: meta])* $name: ident, $what: literal { $($variant: ident => $s: literal,)+ }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum $name {
+            $($variant,)+
+        }
+
+        impl $name {
+            pub const ALL: &'static [$name] = &[$($name::$variant,)+];
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $($name::$variant => $s,)+
+                }
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = String;
+
+            fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+                Self::ALL
+                    .iter()
+                    .copied()
+                    .find(|c| c.name() == s)
+                    .ok_or_else(|| {
+                        let names = Self::ALL.iter().map(|c| c.name()).collect::<Vec<_>>();
+                        format!(
+                            "unsupported {} `{}`, expecting one of {}",
+                            $what,
+                            s,
+                            names.join(", ")
+                        )
+                    })
+            }
+        }
+    };
+}
+
+impl_component!(
+    /// block cipher
+    Algorithm, "algorithm" {
+        Aes => "aes",
+    }
+);
+
+impl_component!(
+    /// key size of the block cipher
+    KeySize, "key size" {
+        Bits128 => "128",
+        Bits192 => "192",
+        Bits256 => "256",
+    }
+);
+
+impl_component!(
+    /// block cipher mode of operation
+    Mode, "mode" {
+        Cbc => "cbc",
+        Ecb => "ecb",
+    }
+);
+
+impl_component!(
+    /// padding scheme, see [`crate::padding`]
+    PaddingScheme, "padding" {
+        Pkcs7 => "pkcs7",
+        X923 => "x923",
+        Iso10126 => "iso10126",
+        Iso7816 => "iso7816",
+        Zero => "zero",
+        NoPad => "nopad",
+    }
+);
+
+impl Algorithm {
+    /// the key sizes the algorithm supports
+    pub fn key_sizes(self) -> &'static [KeySize] {
+        match self {
+            Algorithm::Aes => KeySize::ALL,
+        }
+    }
+
+    /// block size in bytes
+    pub fn block_size(self) -> usize {
+        match self {
+            Algorithm::Aes => 16,
+        }
+    }
+}
+
+impl Mode {
+    /// whether the mode takes an IV
+    pub fn needs_iv(self) -> bool {
+        match self {
+            Mode::Cbc => true,
+            Mode::Ecb => false,
+        }
+    }
+}
+
+/// A parsed cipher spec, `<algo>-<keybits>-<mode>[-<padding>]`.
+///
+/// The padding defaults to PKCS7 for the block modes. `Display` prints the
+/// canonical spec, which always includes the padding of block modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CipherSpec {
+    pub algorithm: Algorithm,
+    pub key_size: KeySize,
+    pub mode: Mode,
+    /// every mode encrypts whole blocks, and so takes a padding
+    pub padding: PaddingScheme,
+}
+
+impl CipherSpec {
+    /// Validates a combination of components.
+    pub fn new(
+        algorithm: Algorithm,
+        key_size: KeySize,
+        mode: Mode,
+        padding: PaddingScheme,
+    ) -> std::result::Result<Self, String> {
+        if !algorithm.key_sizes().contains(&key_size) {
+            return Err(format!(
+                "{} does not support {} bit keys",
+                algorithm.name(),
+                key_size.name()
+            ));
+        }
+        Ok(Self {
+            algorithm,
+            key_size,
+            mode,
+            padding,
+        })
+    }
+
+    /// Every supported cipher spec, in canonical form.
+    pub fn all() -> impl Iterator<Item = CipherSpec> {
+        Algorithm::ALL.iter().flat_map(|&algorithm| {
+            algorithm.key_sizes().iter().flat_map(move |&key_size| {
+                Mode::ALL.iter().flat_map(move |&mode| {
+                    PaddingScheme::ALL
+                        .iter()
+                        .map(move |&padding| Self::new(algorithm, key_size, mode, padding).unwrap())
+                })
+            })
+        })
+    }
+
+    /// Length of the key taken by the spec.
+    pub fn key_len(&self) -> usize {
+        match self.key_size {
+            KeySize::Bits128 => 16,
+            KeySize::Bits192 => 24,
+            KeySize::Bits256 => 32,
+        }
+    }
+
+    /// Length of the IV taken by the spec, `0` if it does not take one.
+    pub fn iv_len(&self) -> usize {
+        match self.mode.needs_iv() {
+            true => self.algorithm.block_size(),
+            false => 0,
+        }
+    }
+
+    /// Generates a fresh IV for the spec from the operating system CSPRNG.
+    pub fn random_iv(&self) -> Vec<u8> {
+        use rand::RngCore;
+
+        let mut iv = vec![0; self.iv_len()];
+        rand::rngs::OsRng.fill_bytes(&mut iv);
+        iv
+    }
+
+    /// Constructs the cipher described by the spec. `iv` may be empty for
+    /// modes which do not take one.
+    pub fn build(&self, key: &[u8], iv: &[u8]) -> Result<Box<dyn StreamCipher>> {
+        match (self.algorithm, self.key_size) {
+            (Algorithm::Aes, KeySize::Bits128) => self.with_padding::<AES128>(key, iv),
+            (Algorithm::Aes, KeySize::Bits192) => self.with_padding::<AES192>(key, iv),
+            (Algorithm::Aes, KeySize::Bits256) => self.with_padding::<AES256>(key, iv),
+        }
+    }
+
+    fn with_padding<M: RijndaelMode>(
+        &self,
+        key: &[u8],
+        iv: &[u8],
+    ) -> Result<Box<dyn StreamCipher>> {
+        let unused = M::Block::zeroed();
+        let iv = match iv.is_empty() && !self.mode.needs_iv() {
+            true => unused.as_ref(),
+            false => iv,
+        };
+        match self.padding {
+            PaddingScheme::Pkcs7 => self.with_mode::<M, PKCS7>(key, iv),
+            PaddingScheme::X923 => self.with_mode::<M, X923>(key, iv),
+            PaddingScheme::Iso10126 => self.with_mode::<M, ISO10126>(key, iv),
+            PaddingScheme::Iso7816 => self.with_mode::<M, ISO7816>(key, iv),
+            PaddingScheme::Zero => self.with_mode::<M, ZeroPadding>(key, iv),
+            PaddingScheme::NoPad => self.with_mode::<M, NoPadding>(key, iv),
+        }
+    }
+
+    fn with_mode<M, P>(&self, key: &[u8], iv: &[u8]) -> Result<Box<dyn StreamCipher>>
+    where
+        M: RijndaelMode,
+        P: Padding + Send + 'static,
+    {
+        Ok(match self.mode {
+            Mode::Cbc => Box::new(Cipher::<M, P, CipherBlockChaining<M, P>>::new(key, iv)?),
+            Mode::Ecb => Box::new(Cipher::<M, P, ElectronicCodeBook<M, P>>::new(key, iv)?),
+        })
+    }
+}
+
+impl FromStr for CipherSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let lower = s.to_lowercase();
+        let mut parts = lower.split('-');
+        let mut next = |what: &str| {
+            parts.next().ok_or_else(|| {
+                format!(
+                    "missing {} in `{}`, expecting `<algo>-<keybits>-<mode>[-<padding>]`",
+                    what, s
+                )
+            })
+        };
+        let algorithm = next("algorithm")?.parse()?;
+        let key_size = next("key size")?.parse()?;
+        let mode = next("mode")?.parse()?;
+        let padding = match parts.next() {
+            Some(padding) => padding.parse()?,
+            None => PaddingScheme::Pkcs7,
+        };
+        if let Some(rest) = parts.next() {
+            return Err(format!(
+                "unexpected `{}` after the padding in `{}`",
+                rest, s
+            ));
+        }
+        Self::new(algorithm, key_size, mode, padding)
+    }
+}
+
+impl fmt::Display for CipherSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}-{}-{}",
+            self.algorithm.name(),
+            self.key_size.name(),
+            self.mode.name(),
+            self.padding.name()
+        )
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_spec() {
+    let spec = "AES-192-ecb".parse::<CipherSpec>().unwrap();
+    assert_eq!(spec.key_size, KeySize::Bits192);
+    assert_eq!(spec.padding, PaddingScheme::Pkcs7);
+    assert_eq!(spec.to_string(), "aes-192-ecb-pkcs7");
+    for spec in CipherSpec::all() {
+        assert_eq!(spec.to_string().parse::<CipherSpec>(), Ok(spec));
+    }
+    assert_eq!(CipherSpec::all().count(), 36);
+
+    assert_eq!(
+        "des-128-cbc".parse::<CipherSpec>(),
+        Err("unsupported algorithm `des`, expecting one of aes".to_string())
+    );
+    assert_eq!(
+        "aes-512-cbc".parse::<CipherSpec>(),
+        Err("unsupported key size `512`, expecting one of 128, 192, 256".to_string())
+    );
+    assert_eq!(
+        "aes-128-xts".parse::<CipherSpec>(),
+        Err("unsupported mode `xts`, expecting one of cbc, ecb".to_string())
+    );
+    assert!("aes-128-cbc-pkcs5".parse::<CipherSpec>().is_err());
+    assert!("aes-128".parse::<CipherSpec>().is_err());
+    assert!("aes-128-cbc-pkcs7-x".parse::<CipherSpec>().is_err());
+}
+
+#[cfg(test)]
+#[test]
+fn test_iv() {
+    let key = [0x2b; 24];
+    let cbc = "aes-192-cbc".parse::<CipherSpec>().unwrap();
+    assert_eq!(cbc.key_len(), 24);
+    assert_eq!(cbc.iv_len(), 16);
+    let iv = cbc.random_iv();
+    assert_eq!(iv.len(), 16);
+    assert_ne!(iv, cbc.random_iv());
+    assert!(matches!(
+        cbc.build(&key, &[]),
+        Err(Error::InvalidIvLength {
+            expected: 16,
+            actual: 0
+        })
+    ));
+
+    let ecb = "aes-192-ecb".parse::<CipherSpec>().unwrap();
+    assert_eq!(ecb.iv_len(), 0);
+    assert!(ecb.random_iv().is_empty());
+    let cipher = ecb.build(&key, &[]).unwrap().encrypt(b"hello").unwrap();
+    let with_iv = ecb
+        .build(&key, &[0; 16])
+        .unwrap()
+        .encrypt(b"hello")
+        .unwrap();
+    assert_eq!(cipher, with_iv);
+}
@@ -0,0 +1,285 @@
+//! The subcommands besides encryption and decryption
+
+use aes::{
+    aes::{Array, RijndaelMode, AES128, AES192, AES256},
+    cipher::CipherSpec,
+    encoding::Base64,
+    kcv,
+    keystore::{Entry, Keystore},
+    secret::{zeroize, SecretKey},
+};
+use anyhow::{anyhow, bail, Context};
+use rand::{rngs::OsRng, RngCore};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{stdout, Write},
+    path::Path,
+};
+
+use crate::{
+    opt::{
+        Kcv, KeyAction, KeyAdd, KeyCommand, KeyExport, KeyFormat, KeyOpts, Keygen, KeystoreOpts,
+        StoreSecret,
+    },
+    tree,
+};
+
+/// Writes a random key for the spec, followed by an IV if asked for.
+pub fn keygen(args: &Keygen) -> anyhow::Result<()> {
+    let mut values = vec![random_key(args.mode.key_len())];
+    if args.iv {
+        if args.mode.iv_len() == 0 {
+            bail!("{} takes no IV", args.mode);
+        }
+        values.push(random_key(args.mode.iv_len()));
+    }
+    write_keys(&values, args.format, &args.output)
+}
+
+/// Writes `values` in `format` to the file `output`, or with `-` to the
+/// standard output.
+fn write_keys(values: &[SecretKey], format: KeyFormat, output: &str) -> anyhow::Result<()> {
+    // reserved up front, as growing it would leave copies of the key behind
+    let mut out = Vec::with_capacity(256);
+    for value in values {
+        let text = match format {
+            KeyFormat::Hex => SecretKey::new(hex::encode(&**value).into_bytes()),
+            KeyFormat::Base64 => SecretKey::new(Base64::STANDARD.encode(value).into_bytes()),
+            KeyFormat::Raw => value.clone(),
+        };
+        out.extend_from_slice(&text);
+        if format != KeyFormat::Raw {
+            out.push(b'\n');
+        }
+    }
+    let out = SecretKey::new(out);
+
+    let mut output: Box<dyn Write> = if output != "-" {
+        Box::new(create_private(output)?)
+    } else {
+        Box::new(stdout())
+    };
+    output.write_all(&out)?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Creates or truncates the file at `path`, readable by its owner only.
+fn create_private(path: impl AsRef<Path>) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let file = options.open(path)?;
+    // the mode only applies to a file created here, so a file that already
+    // existed is made private too, unless it is a device or a pipe
+    #[cfg(unix)]
+    if file.metadata()?.is_file() {
+        file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    }
+    Ok(file)
+}
+
+fn random_key(len: usize) -> SecretKey {
+    let mut key = SecretKey::new(vec![0; len]);
+    OsRng.fill_bytes(&mut key);
+    key
+}
+
+/// Prints the classic and the CMAC key check values of the key.
+pub fn kcv(args: &Kcv) -> anyhow::Result<()> {
+    let key = args.key.read_any(&[16, 24, 32])?;
+    let (kcv, cmac) = match key.len() {
+        16 => check_values::<AES128>(&key),
+        24 => check_values::<AES192>(&key),
+        _ => check_values::<AES256>(&key),
+    };
+    println!("KCV:      {}", hex::encode_upper(kcv));
+    println!("CMAC KCV: {}", hex::encode_upper(cmac));
+    Ok(())
+}
+
+fn check_values<M: RijndaelMode>(key: &[u8]) -> ([u8; 3], [u8; 5]) {
+    let mut key = M::Key::from_slice(key);
+    let values = (kcv::kcv::<M>(&key), kcv::cmac_kcv::<M>(&key));
+    zeroize(key.as_mut());
+    values
+}
+
+/// Runs the `key` subcommands on the keystore.
+pub fn key(args: &KeyCommand) -> anyhow::Result<()> {
+    match &args.action {
+        KeyAction::Add(args) => key_add(args),
+        KeyAction::List(args) => {
+            let keystore = open_keystore(&args.keystore)?;
+            let entries = keystore.entries();
+            let width = entries.iter().map(|e| e.name.len()).max().unwrap_or(0);
+            let width = width.max(4);
+            println!(
+                "{:<16}  {:<width$}  {:>7}  {:<20}  {:<20}  STATUS",
+                "ID",
+                "NAME",
+                "VERSION",
+                "CIPHER",
+                "CREATED",
+                width = width
+            );
+            for e in entries {
+                println!(
+                    "{:<16}  {:<width$}  {:>7}  {:<20}  {:<20}  {}",
+                    hex::encode(e.id),
+                    e.name,
+                    e.version,
+                    e.spec.to_string(),
+                    format_time(e.created),
+                    e.status,
+                    width = width
+                );
+            }
+            Ok(())
+        }
+        KeyAction::Rotate(args) => {
+            let path = args.keystore.path()?;
+            let mut keystore = open_keystore(&args.keystore)?;
+            let spec = keystore
+                .active(&args.name)
+                .or_else(|| {
+                    keystore
+                        .entries()
+                        .iter()
+                        .rev()
+                        .find(|e| e.name == args.name)
+                })
+                .map(|e| e.spec)
+                .ok_or_else(|| anyhow!("There is no key named {}", args.name))?;
+            let key = new_key(&args.key, &spec)?;
+            let entry = keystore
+                .rotate(&args.name, key)
+                .with_context(|| format!("Cannot rotate the key {}", args.name))?;
+            let message = format!("Rotated {} to {}", args.name, describe(entry));
+            save_keystore(&path, &keystore)?;
+            println!("{}", message);
+            Ok(())
+        }
+        KeyAction::Retire(args) => {
+            let path = args.keystore.path()?;
+            let mut keystore = open_keystore(&args.keystore)?;
+            let entry = keystore
+                .retire(&args.name)
+                .with_context(|| format!("Cannot retire the key {}", args.name))?;
+            let message = format!("Retired {} {}", args.name, describe(entry));
+            save_keystore(&path, &keystore)?;
+            println!("{}", message);
+            Ok(())
+        }
+        KeyAction::Export(args) => key_export(args),
+    }
+}
+
+fn key_add(args: &KeyAdd) -> anyhow::Result<()> {
+    let path = args.keystore.path()?;
+    let mut keystore = if Path::new(&path).exists() {
+        open_keystore(&args.keystore)?
+    } else {
+        match args.keystore.secret(true)? {
+            StoreSecret::MasterKey(key) => Keystore::with_master_key(&key)?,
+            StoreSecret::Passphrase(pass) => {
+                let kdf = match args.kdf_cost {
+                    Some(cost) => args.kdf.with_cost(cost)?,
+                    None => args.kdf,
+                };
+                let mut salt = vec![0; crate::SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                Keystore::with_passphrase(&pass, kdf, salt)?
+            }
+        }
+    };
+    let key = new_key(&args.key, &args.mode)?;
+    let entry = keystore
+        .add(&args.name, args.mode, key)
+        .with_context(|| format!("Cannot add the key {}", args.name))?;
+    let message = format!("Added {} {}", args.name, describe(entry));
+    save_keystore(&path, &keystore)?;
+    println!("{}", message);
+    Ok(())
+}
+
+fn key_export(args: &KeyExport) -> anyhow::Result<()> {
+    let keystore = open_keystore(&args.keystore)?;
+    let entry = match args.version {
+        Some(version) => keystore
+            .entries()
+            .iter()
+            .find(|e| e.name == args.name && e.version == version)
+            .ok_or_else(|| anyhow!("There is no version {} of the key {}", version, args.name))?,
+        None => keystore
+            .active(&args.name)
+            .ok_or_else(|| anyhow!("There is no active key named {}", args.name))?,
+    };
+    write_keys(std::slice::from_ref(&entry.key), args.format, &args.output)
+}
+
+/// The key given with the `--key` options, or a random one for `spec`.
+fn new_key(key: &KeyOpts, spec: &CipherSpec) -> anyhow::Result<SecretKey> {
+    match key.source()? {
+        Some(_) => key.read(spec),
+        None => Ok(random_key(spec.key_len())),
+    }
+}
+
+fn describe(entry: &Entry) -> String {
+    format!("version {}, id {}", entry.version, hex::encode(entry.id))
+}
+
+/// Opens the keystore with the master key or the passphrase given.
+pub fn open_keystore(opts: &KeystoreOpts) -> anyhow::Result<Keystore> {
+    let path = opts.path()?;
+    let data = fs::read(&path).with_context(|| format!("Cannot read the keystore {}", path))?;
+    Keystore::open(&data, opts.secret(false)?.unlock())
+        .with_context(|| format!("Cannot open the keystore {}", path))
+}
+
+fn save_keystore(path: &str, keystore: &Keystore) -> anyhow::Result<()> {
+    // written aside and renamed over the keystore, which a failed write then
+    // leaves as it was; a new keystore is readable by its owner only
+    tree::replace(Path::new(path), |file| {
+        #[cfg(unix)]
+        file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+        file.write_all(&keystore.to_bytes())?;
+        Ok(())
+    })
+    .with_context(|| format!("Cannot write the keystore {}", path))
+}
+
+/// Formats seconds since the Unix epoch as an RFC 3339 UTC time.
+fn format_time(secs: u64) -> String {
+    let (days, time) = ((secs / 86400) as i64, secs % 86400);
+    // the civil calendar from days since the epoch, after Howard Hinnant's
+    // `civil_from_days`
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+#[test]
+fn test_format_time() {
+    assert_eq!(format_time(0), "1970-01-01T00:00:00Z");
+    assert_eq!(format_time(951_782_400), "2000-02-29T00:00:00Z");
+    assert_eq!(format_time(1_700_000_000), "2023-11-14T22:13:20Z");
+}
@@ -0,0 +1,372 @@
+//! A self-describing container for ciphertext, carrying everything but the
+//! key needed to decrypt it. See `docs/container.md` for the format.
+
+use std::io::{self, Read, Write};
+
+use crate::cipher::CipherSpec;
+use crate::error::Error;
+
+/// the bytes every container starts with
+pub const MAGIC: [u8; 4] = *b"AESC";
+/// the format version written by [`Header::write_to`]
+pub const VERSION: u8 = 1;
+
+/// field types, see `docs/container.md`
+const END: u8 = 0x00;
+const SPEC: u8 = 0x01;
+const IV: u8 = 0x02;
+const SALT: u8 = 0x03;
+const KDF: u8 = 0x04;
+const TAG_LEN: u8 = 0x05;
+/// set on the types of fields a reader may skip if it does not know them
+const OPTIONAL: u8 = 0x80;
+const KEY_ID: u8 = OPTIONAL | 0x06;
+
+/// The header of a container, which the ciphertext follows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub spec: CipherSpec,
+    /// IV or nonce, empty if the spec does not take one
+    pub iv: Vec<u8>,
+    /// salt the key was derived from a passphrase with
+    pub salt: Option<Vec<u8>>,
+    /// parameters of the key derivation
+    pub kdf: Option<Vec<u8>>,
+    /// length of the authentication tag following the ciphertext, `0` if
+    /// there is none
+    pub tag_len: usize,
+    /// id of the keystore key the data was encrypted with
+    pub key_id: Option<Vec<u8>>,
+}
+
+impl Header {
+    pub fn new(spec: CipherSpec, iv: Vec<u8>) -> Self {
+        Self {
+            spec,
+            iv,
+            salt: None,
+            kdf: None,
+            tag_len: 0,
+            key_id: None,
+        }
+    }
+
+    /// Whether `data` starts like a container.
+    pub fn detect(data: &[u8]) -> bool {
+        data.starts_with(&MAGIC)
+    }
+
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        w.write_all(&self.to_bytes())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        let mut field = |ty: u8, value: &[u8]| {
+            out.push(ty);
+            out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            out.extend_from_slice(value);
+        };
+        field(SPEC, self.spec.to_string().as_bytes());
+        if !self.iv.is_empty() {
+            field(IV, &self.iv);
+        }
+        if let Some(salt) = &self.salt {
+            field(SALT, salt);
+        }
+        if let Some(kdf) = &self.kdf {
+            field(KDF, kdf);
+        }
+        if self.tag_len > 0 {
+            field(TAG_LEN, &[self.tag_len as u8]);
+        }
+        if let Some(key_id) = &self.key_id {
+            field(KEY_ID, key_id);
+        }
+        out.push(END);
+        out
+    }
+
+    /// Reads a header, leaving `r` at the start of the ciphertext. Malformed
+    /// headers are reported as [`io::ErrorKind::InvalidData`] wrapping an
+    /// [`Error`].
+    pub fn read_from<R: Read>(mut r: R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(Error::InvalidHeader("bad magic bytes").into());
+        }
+        match read_u8(&mut r)? {
+            VERSION => {}
+            version => return Err(Error::UnsupportedVersion(version).into()),
+        }
+
+        let mut fields = Fields::default();
+        loop {
+            let ty = read_u8(&mut r)?;
+            if ty == END {
+                break;
+            }
+            let mut len = [0; 2];
+            r.read_exact(&mut len)?;
+            let mut value = vec![0; u16::from_be_bytes(len) as usize];
+            r.read_exact(&mut value)?;
+            let duplicate = match ty {
+                SPEC => {
+                    let s = std::str::from_utf8(&value)
+                        .ok()
+                        .and_then(|s| s.parse::<CipherSpec>().ok())
+                        .ok_or(Error::InvalidHeader("unknown cipher spec"))?;
+                    fields.spec.replace(s).is_some()
+                }
+                IV => fields.iv.replace(value).is_some(),
+                SALT => fields.salt.replace(value).is_some(),
+                KDF => fields.kdf.replace(value).is_some(),
+                TAG_LEN => match value[..] {
+                    [len] => fields.tag_len.replace(len as usize).is_some(),
+                    _ => return Err(Error::InvalidHeader("bad tag length").into()),
+                },
+                KEY_ID => fields.key_id.replace(value).is_some(),
+                ty if ty & OPTIONAL != 0 => false,
+                _ => return Err(Error::InvalidHeader("unknown field").into()),
+            };
+            if duplicate {
+                return Err(Error::InvalidHeader("duplicate field").into());
+            }
+        }
+
+        Ok(fields.validate()?)
+    }
+}
+
+impl Header {
+    /// The fields as the headers of [armor](crate::encoding::Encoding::Armor),
+    /// which carries them instead of a container header.
+    pub fn to_armor_headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = vec![("Cipher", self.spec.to_string())];
+        if !self.iv.is_empty() {
+            headers.push(("IV", hex::encode(&self.iv)));
+        }
+        if let Some(salt) = &self.salt {
+            headers.push(("Salt", hex::encode(salt)));
+        }
+        if let Some(kdf) = &self.kdf {
+            headers.push(("KDF", hex::encode(kdf)));
+        }
+        if self.tag_len > 0 {
+            headers.push(("Tag-Length", self.tag_len.to_string()));
+        }
+        if let Some(key_id) = &self.key_id {
+            headers.push(("Key-Id", hex::encode(key_id)));
+        }
+        headers
+    }
+
+    /// Reads the fields from armor headers written by
+    /// [`Self::to_armor_headers`].
+    pub fn from_armor_headers(headers: &[(String, String)]) -> crate::error::Result<Self> {
+        let hex =
+            |value: &str| hex::decode(value).map_err(|_| Error::InvalidHeader("bad hex value"));
+        let mut fields = Fields::default();
+        for (name, value) in headers {
+            let duplicate = match name.to_lowercase().as_str() {
+                "cipher" => {
+                    let s = value
+                        .parse::<CipherSpec>()
+                        .map_err(|_| Error::InvalidHeader("unknown cipher spec"))?;
+                    fields.spec.replace(s).is_some()
+                }
+                "iv" => fields.iv.replace(hex(value)?).is_some(),
+                "salt" => fields.salt.replace(hex(value)?).is_some(),
+                "kdf" => fields.kdf.replace(hex(value)?).is_some(),
+                "key-id" => fields.key_id.replace(hex(value)?).is_some(),
+                "tag-length" => {
+                    let len = value
+                        .parse::<u8>()
+                        .map_err(|_| Error::InvalidHeader("bad tag length"))?;
+                    fields.tag_len.replace(len as usize).is_some()
+                }
+                _ => return Err(Error::InvalidHeader("unknown field")),
+            };
+            if duplicate {
+                return Err(Error::InvalidHeader("duplicate field"));
+            }
+        }
+        fields.validate()
+    }
+}
+
+/// The fields read from either form of the header, each at most once
+#[derive(Default)]
+struct Fields {
+    spec: Option<CipherSpec>,
+    iv: Option<Vec<u8>>,
+    salt: Option<Vec<u8>>,
+    kdf: Option<Vec<u8>>,
+    tag_len: Option<usize>,
+    key_id: Option<Vec<u8>>,
+}
+
+impl Fields {
+    /// Checks the fields, and puts them together into a header.
+    fn validate(self) -> crate::error::Result<Header> {
+        let spec = self
+            .spec
+            .ok_or(Error::InvalidHeader("missing cipher spec"))?;
+        let iv = self.iv.unwrap_or_default();
+        if iv.len() != spec.iv_len() {
+            return Err(Error::InvalidIvLength {
+                expected: spec.iv_len(),
+                actual: iv.len(),
+            });
+        }
+        Ok(Header {
+            spec,
+            iv,
+            salt: self.salt,
+            kdf: self.kdf,
+            tag_len: self.tag_len.unwrap_or(0),
+            key_id: self.key_id,
+        })
+    }
+}
+
+fn read_u8<R: Read>(r: &mut R) -> io::Result<u8> {
+    let mut b = [0];
+    r.read_exact(&mut b)?;
+    Ok(b[0])
+}
+
+#[cfg(test)]
+#[test]
+fn test_round_trip() {
+    let mut header = Header::new("aes-256-cbc-x923".parse().unwrap(), vec![0x24; 16]);
+    let bytes = header.to_bytes();
+    assert_eq!(
+        hex::encode(&bytes),
+        concat!(
+            "4145534301",
+            "010010",
+            "6165732d3235362d6362632d78393233",
+            "020010",
+            "24242424242424242424242424242424",
+            "00"
+        )
+    );
+    assert!(Header::detect(&bytes));
+    let mut r = &bytes[..];
+    assert_eq!(Header::read_from(&mut r).unwrap(), header);
+    assert!(r.is_empty());
+
+    header.salt = Some(vec![1, 2, 3]);
+    header.kdf = Some(vec![4]);
+    header.tag_len = 16;
+    header.key_id = Some(vec![5; 8]);
+    assert_eq!(Header::read_from(&header.to_bytes()[..]).unwrap(), header);
+
+    let ecb = Header::new("aes-128-ecb".parse().unwrap(), Vec::new());
+    assert_eq!(Header::read_from(&ecb.to_bytes()[..]).unwrap(), ecb);
+}
+
+#[cfg(test)]
+#[test]
+fn test_armor_headers() {
+    let mut header = Header::new("aes-256-cbc-x923".parse().unwrap(), vec![0x24; 16]);
+    let headers = header.to_armor_headers();
+    assert_eq!(
+        headers,
+        [
+            ("Cipher", "aes-256-cbc-x923".to_string()),
+            ("IV", "24".repeat(16))
+        ]
+    );
+    let owned = |headers: Vec<(&str, String)>| {
+        headers
+            .into_iter()
+            .map(|(n, v)| (n.to_string(), v))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        Header::from_armor_headers(&owned(headers)),
+        Ok(header.clone())
+    );
+
+    header.salt = Some(vec![1, 2, 3]);
+    header.tag_len = 16;
+    header.key_id = Some(vec![5; 8]);
+    let headers = owned(header.to_armor_headers());
+    assert_eq!(Header::from_armor_headers(&headers), Ok(header));
+
+    let bad = |name: &str, value: &str| {
+        Header::from_armor_headers(&[(name.to_string(), value.to_string())]).unwrap_err()
+    };
+    assert_eq!(
+        bad("Cipher", "aes-128"),
+        Error::InvalidHeader("unknown cipher spec")
+    );
+    assert_eq!(bad("Comment", "hi"), Error::InvalidHeader("unknown field"));
+    assert_eq!(
+        bad("Cipher", "aes-128-cbc"),
+        Error::InvalidIvLength {
+            expected: 16,
+            actual: 0
+        }
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_malformed() {
+    fn error(bytes: &[u8]) -> String {
+        Header::read_from(bytes).unwrap_err().to_string()
+    }
+
+    let mut bytes = Header::new("aes-128-cbc".parse().unwrap(), vec![0; 16]).to_bytes();
+    for len in 0..bytes.len() {
+        assert_eq!(
+            Header::read_from(&bytes[..len]).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+    assert_eq!(
+        error(b"AESD\x01\x00"),
+        "invalid container header: bad magic bytes"
+    );
+    assert_eq!(error(b"AESC\x02\x00"), "unsupported container version 2");
+    assert_eq!(
+        error(b"AESC\x01\x00"),
+        "invalid container header: missing cipher spec"
+    );
+    assert_eq!(
+        error(b"AESC\x01\x01\x00\x03des\x00"),
+        "invalid container header: unknown cipher spec"
+    );
+
+    // the IV must suit the spec
+    let end = bytes.len() - 1;
+    bytes[end - 16 - 1] = 15;
+    bytes.remove(end - 1);
+    assert_eq!(
+        error(&bytes),
+        "invalid IV length 15 bytes, expected 16 bytes"
+    );
+
+    let mut bytes = Header::new("aes-128-ecb".parse().unwrap(), Vec::new()).to_bytes();
+    bytes.pop();
+    let with = |field: &[u8]| {
+        let mut bytes = bytes.clone();
+        bytes.extend_from_slice(field);
+        bytes.push(END);
+        bytes
+    };
+    assert!(Header::read_from(&with(b"\x81\x00\x01x")[..]).is_ok());
+    assert_eq!(
+        error(&with(b"\x06\x00\x01x")),
+        "invalid container header: unknown field"
+    );
+    assert_eq!(
+        error(&with(b"\x03\x00\x00\x03\x00\x00")),
+        "invalid container header: duplicate field"
+    );
+}
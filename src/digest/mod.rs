@@ -0,0 +1,161 @@
+//! Message digests, as needed by the key derivation functions of
+//! [`crate::kdf`]
+
+use crate::aes::Array;
+use core::convert::TryInto;
+
+mod md5;
+pub use md5::Md5;
+mod sha256;
+pub use sha256::Sha256;
+
+pub trait Digest: Clone {
+    /// the input block of the compression function
+    type Block: Array<u8>;
+    /// the digest
+    type Output: Array<u8>;
+
+    fn new() -> Self;
+    fn update(&mut self, data: &[u8]);
+    fn finalize(self) -> Self::Output;
+
+    /// Digests `data` in one go.
+    fn digest(data: &[u8]) -> Self::Output {
+        let mut d = Self::new();
+        d.update(data);
+        d.finalize()
+    }
+}
+
+/// Buffers the input of a Merkle–Damgård hash with 64 byte blocks and a
+/// 64 bit length, such as MD5 and SHA-256.
+#[derive(Clone)]
+struct BlockBuffer {
+    buf: [u8; 64],
+    len: usize,
+    total: u64,
+}
+
+impl BlockBuffer {
+    fn new() -> Self {
+        Self {
+            buf: [0; 64],
+            len: 0,
+            total: 0,
+        }
+    }
+
+    /// Runs `compress` on every block completed by `data`.
+    fn update(&mut self, mut data: &[u8], mut compress: impl FnMut(&[u8; 64])) {
+        self.total = self.total.wrapping_add(data.len() as u64);
+        if self.len > 0 {
+            let n = data.len().min(64 - self.len);
+            self.buf[self.len..self.len + n].copy_from_slice(&data[..n]);
+            self.len += n;
+            data = &data[n..];
+            if self.len < 64 {
+                return;
+            }
+            compress(&self.buf);
+            self.len = 0;
+        }
+        let mut blocks = data.chunks_exact(64);
+        for block in &mut blocks {
+            compress(block.try_into().unwrap());
+        }
+        let rest = blocks.remainder();
+        self.buf[..rest.len()].copy_from_slice(rest);
+        self.len = rest.len();
+    }
+
+    /// Pads the message with a one bit, zeros and `length`, the encoded
+    /// message length in bits.
+    fn finalize(
+        mut self,
+        length: impl FnOnce(u64) -> [u8; 8],
+        mut compress: impl FnMut(&[u8; 64]),
+    ) {
+        let bits = length(self.total.wrapping_mul(8));
+        self.buf[self.len] = 0x80;
+        self.buf[self.len + 1..].fill(0);
+        if self.len >= 56 {
+            compress(&self.buf);
+            self.buf = [0; 64];
+        }
+        self.buf[56..].copy_from_slice(&bits);
+        compress(&self.buf);
+    }
+}
+
+/// HMAC (RFC 2104) of `D`
+#[derive(Clone)]
+pub struct Hmac<D: Digest> {
+    inner: D,
+    outer: D,
+}
+
+impl<D: Digest> Hmac<D> {
+    pub fn new(key: &[u8]) -> Self {
+        let mut block = D::Block::zeroed();
+        if key.len() > block.as_ref().len() {
+            let hashed = D::digest(key);
+            block.as_mut()[..hashed.as_ref().len()].copy_from_slice(hashed.as_ref());
+        } else {
+            block.as_mut()[..key.len()].copy_from_slice(key);
+        }
+        let (mut inner, mut outer) = (D::new(), D::new());
+        let mut pad = block;
+        pad.as_mut().iter_mut().for_each(|b| *b ^= 0x36);
+        inner.update(pad.as_ref());
+        let mut pad = block;
+        pad.as_mut().iter_mut().for_each(|b| *b ^= 0x5c);
+        outer.update(pad.as_ref());
+        Self { inner, outer }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    pub fn finalize(self) -> D::Output {
+        let mut outer = self.outer;
+        outer.update(self.inner.finalize().as_ref());
+        outer.finalize()
+    }
+
+    /// The MAC of `data` under `key`.
+    pub fn mac(key: &[u8], data: &[u8]) -> D::Output {
+        let mut h = Self::new(key);
+        h.update(data);
+        h.finalize()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_hmac() {
+    // the following test cases are from RFC 4231
+    assert_eq!(
+        hex::encode(Hmac::<Sha256>::mac(&[0x0b; 20], b"Hi There")),
+        "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+    );
+    assert_eq!(
+        hex::encode(Hmac::<Sha256>::mac(
+            b"Jefe",
+            b"what do ya want for nothing?"
+        )),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+    assert_eq!(
+        hex::encode(Hmac::<Sha256>::mac(
+            &[0xaa; 131],
+            b"Test Using Larger Than Block-Size Key - Hash Key First"
+        )),
+        "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+    );
+    // RFC 2202
+    assert_eq!(
+        hex::encode(Hmac::<Md5>::mac(b"Jefe", b"what do ya want for nothing?")),
+        "750c783e6ab0b503eaa86e310a5db738"
+    );
+}
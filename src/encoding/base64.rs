@@ -0,0 +1,256 @@
+use crate::error::{Error, Result};
+
+const STANDARD_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const URL_SAFE_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Base64 (RFC 4648) of a given flavour.
+///
+/// Decoding accepts either alphabet, with or without padding, and skips
+/// whitespace, whatever the flavour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Base64 {
+    /// whether to use the URL and file name safe alphabet, `-_` instead of `+/`
+    pub url_safe: bool,
+    /// whether to pad the output to a multiple of 4 characters with `=`
+    pub pad: bool,
+    /// length of the output lines, `0` for a single line
+    pub wrap: usize,
+}
+
+impl Base64 {
+    pub const STANDARD: Base64 = Base64 {
+        url_safe: false,
+        pad: true,
+        wrap: 0,
+    };
+    pub const URL_SAFE: Base64 = Base64 {
+        url_safe: true,
+        pad: true,
+        wrap: 0,
+    };
+
+    pub fn encode(&self, data: &[u8]) -> String {
+        let mut out = Vec::with_capacity(data.len().div_ceil(3) * 4);
+        let mut encoder = Encoder::new(*self);
+        encoder.update(data, &mut out);
+        encoder.finalize(&mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    pub fn decode(&self, text: &[u8]) -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(text.len() / 4 * 3);
+        let mut decoder = Decoder::new();
+        decoder.update(text, &mut out)?;
+        decoder.finalize(&mut out)?;
+        Ok(out)
+    }
+}
+
+/// Encodes data handed over in chunks
+pub struct Encoder {
+    config: Base64,
+    carry: [u8; 3],
+    len: usize,
+    /// characters on the current line
+    column: usize,
+}
+
+impl Encoder {
+    pub fn new(config: Base64) -> Self {
+        Self {
+            config,
+            carry: [0; 3],
+            len: 0,
+            column: 0,
+        }
+    }
+
+    fn push(&mut self, c: u8, out: &mut Vec<u8>) {
+        if self.config.wrap > 0 && self.column == self.config.wrap {
+            out.push(b'\n');
+            self.column = 0;
+        }
+        out.push(c);
+        self.column += 1;
+    }
+
+    /// Encodes `n` bytes of `group`, padded if the flavour says so.
+    fn group(&mut self, group: [u8; 3], n: usize, out: &mut Vec<u8>) {
+        let alphabet = match self.config.url_safe {
+            true => URL_SAFE_ALPHABET,
+            false => STANDARD_ALPHABET,
+        };
+        let bits = u32::from_be_bytes([0, group[0], group[1], group[2]]);
+        for i in 0..4 {
+            if i <= n {
+                self.push(alphabet[(bits >> (18 - 6 * i) & 0x3F) as usize], out);
+            } else if self.config.pad {
+                self.push(b'=', out);
+            }
+        }
+    }
+
+    /// Appends the encoding of the whole groups of 3 bytes so far to `out`.
+    pub fn update(&mut self, mut data: &[u8], out: &mut Vec<u8>) {
+        while !data.is_empty() {
+            let n = data.len().min(3 - self.len);
+            self.carry[self.len..self.len + n].copy_from_slice(&data[..n]);
+            self.len += n;
+            data = &data[n..];
+            if self.len == 3 {
+                self.group(self.carry, 3, out);
+                self.len = 0;
+            }
+        }
+    }
+
+    /// Appends the encoding of the rest, ending wrapped output with a line
+    /// break.
+    pub fn finalize(&mut self, out: &mut Vec<u8>) {
+        if self.len > 0 {
+            let mut group = [0; 3];
+            group[..self.len].copy_from_slice(&self.carry[..self.len]);
+            self.group(group, self.len, out);
+            self.len = 0;
+        }
+        if self.config.wrap > 0 && self.column > 0 {
+            out.push(b'\n');
+        }
+        self.column = 0;
+    }
+}
+
+/// Decodes text handed over in chunks
+pub struct Decoder {
+    bits: u32,
+    len: usize,
+    /// whether padding was seen, which only padding may follow
+    padded: bool,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self {
+            bits: 0,
+            len: 0,
+            padded: false,
+        }
+    }
+
+    /// Appends the bytes decoded from `text` so far to `out`.
+    pub fn update(&mut self, text: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        for &c in text {
+            let value = match c {
+                b'A'..=b'Z' => c - b'A',
+                b'a'..=b'z' => c - b'a' + 26,
+                b'0'..=b'9' => c - b'0' + 52,
+                b'+' | b'-' => 62,
+                b'/' | b'_' => 63,
+                b'=' => {
+                    self.padded = true;
+                    continue;
+                }
+                c if c.is_ascii_whitespace() => continue,
+                _ => return Err(Error::InvalidEncoding("invalid Base64 character")),
+            };
+            if self.padded {
+                return Err(Error::InvalidEncoding("Base64 data after padding"));
+            }
+            self.bits = self.bits << 6 | value as u32;
+            self.len += 1;
+            if self.len == 4 {
+                out.extend_from_slice(&self.bits.to_be_bytes()[1..]);
+                self.bits = 0;
+                self.len = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends the bytes of a trailing partial group to `out`.
+    pub fn finalize(&mut self, out: &mut Vec<u8>) -> Result<()> {
+        let len = core::mem::take(&mut self.len);
+        let bits = core::mem::take(&mut self.bits);
+        self.padded = false;
+        match len {
+            0 => Ok(()),
+            1 => Err(Error::InvalidEncoding("truncated Base64 data")),
+            n => {
+                let bits = bits << (6 * (4 - n));
+                out.extend_from_slice(&bits.to_be_bytes()[1..n]);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test() {
+    // the following test cases are from RFC 4648
+    for (data, text) in [
+        ("", ""),
+        ("f", "Zg=="),
+        ("fo", "Zm8="),
+        ("foo", "Zm9v"),
+        ("foob", "Zm9vYg=="),
+        ("fooba", "Zm9vYmE="),
+        ("foobar", "Zm9vYmFy"),
+    ] {
+        assert_eq!(Base64::STANDARD.encode(data.as_bytes()), text);
+        assert_eq!(
+            Base64::STANDARD.decode(text.as_bytes()).unwrap(),
+            data.as_bytes()
+        );
+        let unpadded = text.trim_end_matches('=');
+        let config = Base64 {
+            pad: false,
+            ..Base64::STANDARD
+        };
+        assert_eq!(config.encode(data.as_bytes()), unpadded);
+        assert_eq!(config.decode(unpadded.as_bytes()).unwrap(), data.as_bytes());
+    }
+
+    let data = [0xfb, 0xff, 0xbf];
+    assert_eq!(Base64::STANDARD.encode(&data), "+/+/");
+    assert_eq!(Base64::URL_SAFE.encode(&data), "-_-_");
+    assert_eq!(Base64::STANDARD.decode(b"-_+/").unwrap(), data);
+
+    let wrapped = Base64 {
+        wrap: 4,
+        ..Base64::STANDARD
+    };
+    assert_eq!(wrapped.encode(b"foobar"), "Zm9v\nYmFy\n");
+    assert_eq!(wrapped.encode(b"fooba"), "Zm9v\nYmE=\n");
+    assert_eq!(
+        Base64::STANDARD.decode(b" Zm9v\r\nYmE=\n").unwrap(),
+        b"fooba"
+    );
+
+    // fed in odd chunks
+    let data = (0..=255).collect::<Vec<u8>>();
+    let mut text = Vec::new();
+    let mut encoder = Encoder::new(wrapped);
+    data.chunks(7).for_each(|c| encoder.update(c, &mut text));
+    encoder.finalize(&mut text);
+    assert_eq!(text, wrapped.encode(&data).as_bytes());
+    let mut decoded = Vec::new();
+    let mut decoder = Decoder::new();
+    for c in text.chunks(5) {
+        decoder.update(c, &mut decoded).unwrap();
+    }
+    decoder.finalize(&mut decoded).unwrap();
+    assert_eq!(decoded, data);
+
+    assert!(Base64::STANDARD.decode(b"Zm9v!").is_err());
+    assert!(Base64::STANDARD.decode(b"Zg==Zg==").is_err());
+    assert!(Base64::STANDARD.decode(b"Zm9vY").is_err());
+}
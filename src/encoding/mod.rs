@@ -0,0 +1,466 @@
+//! Text encodings of binary data, for ciphertext that has to travel as text
+
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Write};
+use std::str::FromStr;
+
+use crate::error::Error;
+
+mod base64;
+pub use base64::{Base64, Decoder as Base64Decoder, Encoder as Base64Encoder};
+
+/// the first line of armored data
+pub const ARMOR_BEGIN: &str = "-----BEGIN AES ENCRYPTED MESSAGE-----";
+/// the last line of armored data
+pub const ARMOR_END: &str = "-----END AES ENCRYPTED MESSAGE-----";
+/// line length of the Base64 body of armored data
+const ARMOR_WRAP: usize = 64;
+
+/// size of the chunks read from the inner reader
+const CHUNK: usize = 1 << 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Binary,
+    /// lowercase hex digits
+    Hex,
+    Base64(Base64),
+    /// a Base64 body between [`ARMOR_BEGIN`] and [`ARMOR_END`] lines,
+    /// preceded by `Name: value` header lines and an empty line
+    Armor,
+}
+
+impl Encoding {
+    /// Guesses the encoding of data starting with `prefix`.
+    ///
+    /// Data only made of hex digits is taken for hex, though it is valid
+    /// Base64 too, and anything which is neither hex, Base64 nor armor for
+    /// binary.
+    pub fn detect(prefix: &[u8]) -> Self {
+        let start = prefix.iter().position(|c| !c.is_ascii_whitespace());
+        if prefix[start.unwrap_or(0)..].starts_with(ARMOR_BEGIN.as_bytes()) {
+            return Encoding::Armor;
+        }
+        let text = prefix
+            .iter()
+            .copied()
+            .filter(|c| !c.is_ascii_whitespace())
+            .collect::<Vec<_>>();
+        if text.is_empty() {
+            Encoding::Binary
+        } else if text.iter().all(u8::is_ascii_hexdigit) {
+            Encoding::Hex
+        } else if text
+            .iter()
+            .all(|&c| c.is_ascii_alphanumeric() || b"+/-_=".contains(&c))
+        {
+            Encoding::Base64(Base64 {
+                url_safe: text.iter().any(|c| b"-_".contains(c)),
+                ..Base64::STANDARD
+            })
+        } else {
+            Encoding::Binary
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "binary" => Ok(Encoding::Binary),
+            "hex" => Ok(Encoding::Hex),
+            "base64" => Ok(Encoding::Base64(Base64::STANDARD)),
+            "base64url" => Ok(Encoding::Base64(Base64::URL_SAFE)),
+            "armor" => Ok(Encoding::Armor),
+            _ => Err(format!(
+                "unsupported encoding `{}`, expecting one of binary, hex, base64, base64url, armor",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::Binary => "binary",
+            Encoding::Hex => "hex",
+            Encoding::Base64(b) if b.url_safe => "base64url",
+            Encoding::Base64(_) => "base64",
+            Encoding::Armor => "armor",
+        })
+    }
+}
+
+/// Writes the encoding of the data written to it into `W`.
+///
+/// [`Self::finish`] must be called once all data is written, to write the
+/// end of the encoding.
+pub struct EncodeWriter<W: Write> {
+    inner: W,
+    encoding: Encoding,
+    base64: Base64Encoder,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> EncodeWriter<W> {
+    /// Starts writing `encoding` into `inner`. Armor starts with the
+    /// `headers`, which other encodings ignore.
+    pub fn new(mut inner: W, encoding: Encoding, headers: &[(&str, String)]) -> io::Result<Self> {
+        let base64 = match encoding {
+            Encoding::Base64(config) => config,
+            _ => Base64 {
+                wrap: ARMOR_WRAP,
+                ..Base64::STANDARD
+            },
+        };
+        if encoding == Encoding::Armor {
+            writeln!(inner, "{}", ARMOR_BEGIN)?;
+            for (name, value) in headers {
+                writeln!(inner, "{}: {}", name, value)?;
+            }
+            if !headers.is_empty() {
+                writeln!(inner)?;
+            }
+        }
+        Ok(Self {
+            inner,
+            encoding,
+            base64: Base64Encoder::new(base64),
+            buf: Vec::new(),
+        })
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Writes the end of the encoding and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.buf.clear();
+        self.base64.finalize(&mut self.buf);
+        if self.encoding == Encoding::Armor {
+            self.buf.extend_from_slice(ARMOR_END.as_bytes());
+            self.buf.push(b'\n');
+        }
+        match self.encoding {
+            Encoding::Base64(_) | Encoding::Armor => self.inner.write_all(&self.buf)?,
+            _ => {}
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for EncodeWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.clear();
+        match self.encoding {
+            Encoding::Binary => return self.inner.write(data),
+            Encoding::Hex => self.buf.extend_from_slice(hex::encode(data).as_bytes()),
+            Encoding::Base64(_) | Encoding::Armor => self.base64.update(data, &mut self.buf),
+        }
+        self.inner.write_all(&self.buf)?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Where a [`DecodeReader`] is in its input
+enum State {
+    Body,
+    /// past the body of armor, the rest has to be the end line
+    Trailer,
+    Done,
+}
+
+/// Decodes the encoded data read from `R`
+pub struct DecodeReader<R: Read> {
+    inner: R,
+    encoding: Encoding,
+    headers: Vec<(String, String)>,
+    /// read but not decoded yet
+    input: Vec<u8>,
+    output: Vec<u8>,
+    pos: usize,
+    base64: Base64Decoder,
+    /// a trailing odd hex digit
+    carry: Option<u8>,
+    state: State,
+}
+
+impl<R: Read> DecodeReader<R> {
+    /// Starts decoding `encoding` from `inner`, reading the headers of armor.
+    pub fn new(inner: R, encoding: Encoding) -> io::Result<Self> {
+        let mut reader = Self {
+            inner,
+            encoding,
+            headers: Vec::new(),
+            input: Vec::new(),
+            output: Vec::new(),
+            pos: 0,
+            base64: Base64Decoder::new(),
+            carry: None,
+            state: State::Body,
+        };
+        if encoding == Encoding::Armor {
+            reader.read_armor_headers()?;
+        }
+        Ok(reader)
+    }
+
+    /// Detects the encoding from the start of `inner`, see
+    /// [`Encoding::detect`].
+    pub fn detect(mut inner: R) -> io::Result<DecodeReader<io::Chain<io::Cursor<Vec<u8>>, R>>> {
+        let mut prefix = Vec::new();
+        (&mut inner)
+            .take(ARMOR_BEGIN.len() as u64 * 16)
+            .read_to_end(&mut prefix)?;
+        let encoding = Encoding::detect(&prefix);
+        DecodeReader::new(io::Cursor::new(prefix).chain(inner), encoding)
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// The header lines of armor, as `(name, value)`
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// Reads more input, returning whether there was any.
+    fn read_input(&mut self) -> io::Result<bool> {
+        let start = self.input.len();
+        self.input.resize(start + CHUNK, 0);
+        let n = loop {
+            match self.inner.read(&mut self.input[start..]) {
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                res => break res,
+            }
+        };
+        self.input.truncate(start + *n.as_ref().unwrap_or(&0));
+        Ok(n? > 0)
+    }
+
+    /// Takes the next line off the input, without its line break.
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        loop {
+            if let Some(end) = self.input.iter().position(|&c| c == b'\n') {
+                let line = self.input.drain(..=end).collect::<Vec<_>>();
+                return String::from_utf8(line)
+                    .map(|line| Some(line.trim_end().to_string()))
+                    .map_err(|_| Error::InvalidEncoding("armor is not UTF-8").into());
+            }
+            if !self.read_input()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn read_armor_headers(&mut self) -> io::Result<()> {
+        let mut line = None;
+        while let Some(l) = self.read_line()? {
+            if !l.is_empty() {
+                line = Some(l);
+                break;
+            }
+        }
+        if line.as_deref() != Some(ARMOR_BEGIN) {
+            return Err(Error::InvalidEncoding("missing armor begin line").into());
+        }
+        while let Some(line) = self.read_line()? {
+            match line.split_once(':') {
+                Some((name, value)) => self
+                    .headers
+                    .push((name.trim().to_string(), value.trim().to_string())),
+                None if line.is_empty() => break,
+                None => {
+                    // no headers, the body starts right away
+                    let mut rest = line.into_bytes();
+                    rest.push(b'\n');
+                    rest.append(&mut self.input);
+                    self.input = rest;
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes `self.input` into `self.output`.
+    fn decode(&mut self, end: bool) -> io::Result<()> {
+        let input = std::mem::take(&mut self.input);
+        match self.encoding {
+            Encoding::Binary => self.output.extend_from_slice(&input),
+            Encoding::Hex => {
+                for &c in input.iter().filter(|c| !c.is_ascii_whitespace()) {
+                    let digit = (c as char)
+                        .to_digit(16)
+                        .ok_or(Error::InvalidEncoding("invalid hex digit"))?
+                        as u8;
+                    match self.carry.take() {
+                        Some(high) => self.output.push(high << 4 | digit),
+                        None => self.carry = Some(digit),
+                    }
+                }
+                if end && self.carry.is_some() {
+                    return Err(Error::InvalidEncoding("odd number of hex digits").into());
+                }
+            }
+            Encoding::Base64(_) => {
+                self.base64.update(&input, &mut self.output)?;
+                if end {
+                    self.base64.finalize(&mut self.output)?;
+                }
+            }
+            Encoding::Armor => match self.state {
+                State::Body => {
+                    // the body is standard Base64, so a `-` starts the end line
+                    let body = input.iter().position(|&c| c == b'-');
+                    let body_end = body.unwrap_or(input.len());
+                    self.base64.update(&input[..body_end], &mut self.output)?;
+                    self.input = input[body_end..].to_vec();
+                    if body.is_some() {
+                        self.base64.finalize(&mut self.output)?;
+                        self.state = State::Trailer;
+                        return self.decode(end);
+                    } else if end {
+                        return Err(Error::InvalidEncoding("missing armor end line").into());
+                    }
+                }
+                State::Trailer | State::Done => {
+                    // kept until the end of the input, which should come soon
+                    self.input = input;
+                    if self.input.len() > 2 * ARMOR_END.len()
+                        || end && String::from_utf8_lossy(&self.input).trim() != ARMOR_END
+                    {
+                        return Err(Error::InvalidEncoding("bad armor end line").into());
+                    }
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecodeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.output.len() && !matches!(self.state, State::Done) {
+            self.output.clear();
+            self.pos = 0;
+            let more = self.read_input()?;
+            self.decode(!more)?;
+            if !more {
+                self.state = State::Done;
+            }
+        }
+        let n = buf.len().min(self.output.len() - self.pos);
+        buf[..n].copy_from_slice(&self.output[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+fn encode(data: &[u8], encoding: Encoding, headers: &[(&str, String)]) -> Vec<u8> {
+    let mut w = EncodeWriter::new(Vec::new(), encoding, headers).unwrap();
+    for part in data.chunks(7) {
+        w.write_all(part).unwrap();
+    }
+    w.finish().unwrap()
+}
+
+#[cfg(test)]
+#[test]
+fn test_round_trip() {
+    let data = (0..1000).map(|i| (i * 7) as u8).collect::<Vec<_>>();
+    let wrapped = Base64 {
+        wrap: 76,
+        ..Base64::URL_SAFE
+    };
+    for encoding in [
+        Encoding::Binary,
+        Encoding::Hex,
+        Encoding::Base64(Base64::STANDARD),
+        Encoding::Base64(wrapped),
+        Encoding::Armor,
+    ] {
+        let text = encode(&data, encoding, &[("Cipher", "aes-128-ecb-pkcs7".into())]);
+        let mut r = DecodeReader::detect(&text[..]).unwrap();
+        assert_eq!(r.encoding().to_string(), encoding.to_string());
+        let mut decoded = Vec::new();
+        let mut buf = [0; 13];
+        loop {
+            match r.read(&mut buf).unwrap() {
+                0 => break,
+                n => decoded.extend_from_slice(&buf[..n]),
+            }
+        }
+        assert_eq!(decoded, data, "{}", encoding);
+        if encoding == Encoding::Armor {
+            assert_eq!(
+                r.headers(),
+                [("Cipher".to_string(), "aes-128-ecb-pkcs7".to_string())]
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_armor() {
+    let text = encode(b"foobar", Encoding::Armor, &[("IV", "00ff".into())]);
+    assert_eq!(
+        String::from_utf8(text).unwrap(),
+        "-----BEGIN AES ENCRYPTED MESSAGE-----\n\
+         IV: 00ff\n\
+         \n\
+         Zm9vYmFy\n\
+         -----END AES ENCRYPTED MESSAGE-----\n"
+    );
+
+    let decode = |text: &str| -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        DecodeReader::new(text.as_bytes(), Encoding::Armor)?.read_to_end(&mut out)?;
+        Ok(out)
+    };
+    // without headers, and with CRLF line breaks
+    assert_eq!(
+        decode("\r\n-----BEGIN AES ENCRYPTED MESSAGE-----\r\nZm9v\r\nYmE=\r\n-----END AES ENCRYPTED MESSAGE-----\r\n")
+            .unwrap(),
+        b"fooba"
+    );
+    assert!(decode("-----BEGIN AES ENCRYPTED MESSAGE-----\nZm9vYmFy\n").is_err());
+    assert!(decode("-----BEGIN AES MESSAGE-----\nZm9vYmFy\n").is_err());
+    assert!(
+        decode("-----BEGIN AES ENCRYPTED MESSAGE-----\nZm9vYmFy\n-----END AES-----\n").is_err()
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_detect() {
+    assert_eq!(Encoding::detect(b""), Encoding::Binary);
+    assert_eq!(Encoding::detect(b"AESC\x01\x01"), Encoding::Binary);
+    assert_eq!(Encoding::detect(b"00ff\n"), Encoding::Hex);
+    assert_eq!(
+        Encoding::detect(b"QUVTQw==\n"),
+        Encoding::Base64(Base64::STANDARD)
+    );
+    assert_eq!(
+        Encoding::detect(b"QU_TQw"),
+        Encoding::Base64(Base64::URL_SAFE)
+    );
+    assert_eq!(
+        Encoding::detect(b"\n-----BEGIN AES ENCRYPTED MESSAGE-----\n"),
+        Encoding::Armor
+    );
+    assert_eq!("base64url".parse(), Ok(Encoding::Base64(Base64::URL_SAFE)));
+    assert!("base32".parse::<Encoding>().is_err());
+}
@@ -0,0 +1,123 @@
+//! Errors returned by the ciphers, modes and paddings
+
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// the key is not `expected` bytes long
+    InvalidKeyLength { expected: usize, actual: usize },
+    /// the key is not 16, 24 or 32 bytes long, where any AES key will do
+    UnsupportedKeyLength(usize),
+    /// the IV is not `expected` bytes long
+    InvalidIvLength { expected: usize, actual: usize },
+    /// the ciphertext is not a multiple of the `block` size
+    InvalidCiphertextLength { block: usize, actual: usize },
+    /// the plaintext is not a multiple of the `block` size, and the padding
+    /// cannot make up for it
+    InvalidPlaintextLength { block: usize, actual: usize },
+    /// the padding of the decrypted data is malformed
+    BadPadding,
+    /// the authentication tag does not match the data
+    AuthenticationFailed,
+    /// the header of an encrypted container is malformed, for the reason
+    /// given
+    InvalidHeader(&'static str),
+    /// the container is of a format version this crate does not read
+    UnsupportedVersion(u8),
+    /// text is not in the encoding it is decoded from, for the reason given
+    InvalidEncoding(&'static str),
+    /// the parameters of a key derivation are out of range or malformed, for
+    /// the reason given
+    InvalidKdfParams(&'static str),
+    /// a keystore is malformed, or cannot do what was asked of it, for the
+    /// reason given
+    Keystore(&'static str),
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidKeyLength { expected, actual } => write!(
+                f,
+                "invalid key length {} bytes, expected {} bytes",
+                actual, expected
+            ),
+            Error::UnsupportedKeyLength(actual) => write!(
+                f,
+                "invalid key length {} bytes, expected 16, 24 or 32 bytes",
+                actual
+            ),
+            Error::InvalidIvLength { expected, actual } => write!(
+                f,
+                "invalid IV length {} bytes, expected {} bytes",
+                actual, expected
+            ),
+            Error::InvalidCiphertextLength { block, actual } => write!(
+                f,
+                "ciphertext length {} bytes is not a multiple of the {} byte block",
+                actual, block
+            ),
+            Error::InvalidPlaintextLength { block, actual } => write!(
+                f,
+                "plaintext length {} bytes is not a multiple of the {} byte block",
+                actual, block
+            ),
+            Error::BadPadding => write!(f, "bad padding"),
+            Error::AuthenticationFailed => write!(f, "authentication failed"),
+            Error::InvalidHeader(reason) => write!(f, "invalid container header: {}", reason),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "unsupported container version {}", version)
+            }
+            Error::InvalidEncoding(reason) => write!(f, "invalid encoding: {}", reason),
+            Error::InvalidKdfParams(reason) => {
+                write!(f, "invalid key derivation parameters: {}", reason)
+            }
+            Error::Keystore(reason) => write!(f, "keystore: {}", reason),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+#[cfg(feature = "std")]
+impl From<Error> for std::io::Error {
+    fn from(e: Error) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_display() {
+    assert_eq!(
+        Error::InvalidKeyLength {
+            expected: 16,
+            actual: 15
+        }
+        .to_string(),
+        "invalid key length 15 bytes, expected 16 bytes"
+    );
+    assert_eq!(
+        Error::UnsupportedKeyLength(20).to_string(),
+        "invalid key length 20 bytes, expected 16, 24 or 32 bytes"
+    );
+    assert_eq!(
+        Error::InvalidCiphertextLength {
+            block: 16,
+            actual: 17
+        }
+        .to_string(),
+        "ciphertext length 17 bytes is not a multiple of the 16 byte block"
+    );
+    assert_eq!(
+        Error::AuthenticationFailed.to_string(),
+        "authentication failed"
+    );
+    assert_eq!(
+        Error::InvalidHeader("missing cipher spec").to_string(),
+        "invalid container header: missing cipher spec"
+    );
+}
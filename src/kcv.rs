@@ -0,0 +1,60 @@
+//! Key check values, short fingerprints of a key to verify it was entered
+//! or transported correctly without revealing it
+
+use crate::aes::{Array, BlockCipher, Rijndael, RijndaelMode};
+use crate::mac::Cmac;
+
+/// The classic KCV, the first 3 bytes of a zero block encrypted with `key`.
+pub fn kcv<M: RijndaelMode>(key: &M::Key) -> [u8; 3] {
+    let mut block = M::Block::zeroed();
+    Rijndael::<M>::new(key).encrypt_block(&mut block);
+    let block = block.as_ref();
+    [block[0], block[1], block[2]]
+}
+
+/// The CMAC KCV of ANSI X9.24-1, the first 5 bytes of the CMAC of a zero
+/// block under `key`.
+pub fn cmac_kcv<M: RijndaelMode>(key: &M::Key) -> [u8; 5] {
+    let mac = Cmac::<M>::mac(key, M::Block::zeroed().as_ref());
+    let mac = mac.as_ref();
+    [mac[0], mac[1], mac[2], mac[3], mac[4]]
+}
+
+#[cfg(test)]
+macro_rules! impl_test_kcv {
+    ($mode: ty, $key: literal, $kcv: literal, $cmac: literal) => {{
+        use std::convert::TryInto;
+
+        let key = hex::decode($key).unwrap();
+        let key = key.as_slice().try_into().unwrap();
+        assert_eq!(hex::encode(kcv::<$mode>(key)), $kcv);
+        assert_eq!(hex::encode(cmac_kcv::<$mode>(key)), $cmac);
+    }};
+}
+
+#[cfg(test)]
+#[test]
+fn test_kcv() {
+    use crate::aes::{AES128, AES192, AES256};
+
+    // the following test cases are from `openssl enc -nopad` and
+    // `openssl mac CMAC` of a zero block
+    impl_test_kcv!(
+        AES128,
+        "2b7e151628aed2a6abf7158809cf4f3c",
+        "7df76b",
+        "7ad386c376"
+    );
+    impl_test_kcv!(
+        AES192,
+        "8e73b0f7da0e6452c810f32b809079e562f8ead2522c6b7b",
+        "22452d",
+        "3a072a425d"
+    );
+    impl_test_kcv!(
+        AES256,
+        "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4",
+        "e568f6",
+        "1a0b2df267"
+    );
+}
@@ -0,0 +1,282 @@
+//! Key derivation functions, stretching a passphrase into key material
+
+use crate::aes::Array;
+use crate::digest::{Digest, Hmac};
+#[cfg(feature = "alloc")]
+use crate::{
+    digest::Sha256,
+    error::{Error, Result},
+};
+#[cfg(feature = "alloc")]
+use {
+    alloc::{string::String, vec::Vec},
+    core::convert::TryInto,
+};
+
+#[cfg(feature = "alloc")]
+mod scrypt;
+#[cfg(feature = "alloc")]
+pub use scrypt::{scrypt, Params as ScryptParams};
+
+/// iterations of [`Kdf::Pbkdf2Sha256`] by default
+pub const DEFAULT_PBKDF2_ITERATIONS: u32 = 600_000;
+
+/// the most iterations of [`Kdf::Pbkdf2Sha256`], as they may come from an
+/// untrusted header
+pub const MAX_PBKDF2_ITERATIONS: u32 = 100_000_000;
+
+/// A key derivation with its cost parameters, as stored alongside the
+/// ciphertext by [`Self::to_bytes`]
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kdf {
+    Scrypt(ScryptParams),
+    Pbkdf2Sha256 { iterations: u32 },
+}
+
+#[cfg(feature = "alloc")]
+impl Kdf {
+    /// identifiers of the derivations in [`Self::to_bytes`]
+    const SCRYPT: u8 = 0x01;
+    const PBKDF2_SHA256: u8 = 0x02;
+
+    /// The same derivation with the cost set to `cost`, the base 2
+    /// logarithm of `N` for scrypt or the iterations for PBKDF2.
+    pub fn with_cost(self, cost: u32) -> Result<Self> {
+        match self {
+            Kdf::Scrypt(params) => {
+                let log_n = cost
+                    .try_into()
+                    .map_err(|_| Error::InvalidKdfParams("scrypt cost out of range"))?;
+                ScryptParams::new(log_n, params.r, params.p).map(Kdf::Scrypt)
+            }
+            Kdf::Pbkdf2Sha256 { .. } if cost == 0 => {
+                Err(Error::InvalidKdfParams("no PBKDF2 iterations"))
+            }
+            Kdf::Pbkdf2Sha256 { .. } if cost > MAX_PBKDF2_ITERATIONS => {
+                Err(Error::InvalidKdfParams("too many PBKDF2 iterations"))
+            }
+            Kdf::Pbkdf2Sha256 { .. } => Ok(Kdf::Pbkdf2Sha256 { iterations: cost }),
+        }
+    }
+
+    /// Fills `out` with the key derived from `pass` and `salt`, failing if
+    /// the memory scrypt takes cannot be allocated.
+    pub fn derive(&self, pass: &[u8], salt: &[u8], out: &mut [u8]) -> Result<()> {
+        match self {
+            Kdf::Scrypt(params) => scrypt(pass, salt, params, out),
+            Kdf::Pbkdf2Sha256 { iterations } => {
+                pbkdf2_hmac::<Sha256>(pass, salt, *iterations, out);
+                Ok(())
+            }
+        }
+    }
+
+    /// The identifier of the derivation followed by its big-endian
+    /// parameters, `01 log_n r p` for scrypt and `02 iterations` for PBKDF2.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            Kdf::Scrypt(params) => {
+                out.extend_from_slice(&[Self::SCRYPT, params.log_n]);
+                out.extend_from_slice(&params.r.to_be_bytes());
+                out.extend_from_slice(&params.p.to_be_bytes());
+            }
+            Kdf::Pbkdf2Sha256 { iterations } => {
+                out.push(Self::PBKDF2_SHA256);
+                out.extend_from_slice(&iterations.to_be_bytes());
+            }
+        }
+        out
+    }
+
+    /// Reads the parameters written by [`Self::to_bytes`], refusing costs
+    /// beyond [`ScryptParams::MAX_MEMORY`], [`ScryptParams::MAX_P`] and
+    /// [`MAX_PBKDF2_ITERATIONS`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let u32_at =
+            |i: usize| u32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        match *bytes {
+            [Self::SCRYPT, log_n, _, _, _, _, _, _, _, _] => {
+                ScryptParams::new(log_n, u32_at(2), u32_at(6)).map(Kdf::Scrypt)
+            }
+            [Self::PBKDF2_SHA256, _, _, _, _] => {
+                Kdf::Pbkdf2Sha256 { iterations: 1 }.with_cost(u32_at(1))
+            }
+            [Self::SCRYPT, ..] | [Self::PBKDF2_SHA256, ..] => {
+                Err(Error::InvalidKdfParams("bad parameter length"))
+            }
+            _ => Err(Error::InvalidKdfParams("unknown key derivation")),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl core::str::FromStr for Kdf {
+    type Err = String;
+
+    /// Parses `scrypt` or `pbkdf2`, with the default cost.
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "scrypt" => Ok(Kdf::Scrypt(ScryptParams::DEFAULT)),
+            "pbkdf2" | "pbkdf2-sha256" => Ok(Kdf::Pbkdf2Sha256 {
+                iterations: DEFAULT_PBKDF2_ITERATIONS,
+            }),
+            _ => Err(alloc::format!(
+                "unsupported key derivation `{}`, expecting one of scrypt, pbkdf2",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl core::fmt::Display for Kdf {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Kdf::Scrypt(params) => write!(
+                f,
+                "scrypt (N = 2^{}, r = {}, p = {})",
+                params.log_n, params.r, params.p
+            ),
+            Kdf::Pbkdf2Sha256 { iterations } => {
+                write!(f, "PBKDF2-HMAC-SHA256 ({} iterations)", iterations)
+            }
+        }
+    }
+}
+
+/// OpenSSL's `EVP_BytesToKey`, filling `out` with the key followed by the
+/// IV. Each output block is the digest of the previous one, `pass` and
+/// `salt`, rehashed `count - 1` times.
+///
+/// This is a weak KDF, only meant for compatibility with `openssl enc`.
+pub fn evp_bytes_to_key<D: Digest>(pass: &[u8], salt: &[u8], count: usize, out: &mut [u8]) {
+    let mut prev: Option<D::Output> = None;
+    for part in out.chunks_mut(D::Output::zeroed().as_ref().len()) {
+        let mut d = D::new();
+        if let Some(prev) = &prev {
+            d.update(prev.as_ref());
+        }
+        d.update(pass);
+        d.update(salt);
+        let mut block = d.finalize();
+        for _ in 1..count {
+            block = D::digest(block.as_ref());
+        }
+        part.copy_from_slice(&block.as_ref()[..part.len()]);
+        prev = Some(block);
+    }
+}
+
+/// PBKDF2 (RFC 8018) with HMAC of `D` as the pseudorandom function, filling
+/// `out` with the derived key.
+pub fn pbkdf2_hmac<D: Digest>(pass: &[u8], salt: &[u8], iterations: u32, out: &mut [u8]) {
+    let prf = Hmac::<D>::new(pass);
+    let len = D::Output::zeroed().as_ref().len();
+    for (i, part) in out.chunks_mut(len).enumerate() {
+        let mut mac = prf.clone();
+        mac.update(salt);
+        mac.update(&(i as u32 + 1).to_be_bytes());
+        let mut u = mac.finalize();
+        let mut t = u;
+        for _ in 1..iterations {
+            let mut mac = prf.clone();
+            mac.update(u.as_ref());
+            u = mac.finalize();
+            for (t, u) in t.as_mut().iter_mut().zip(u.as_ref()) {
+                *t ^= u;
+            }
+        }
+        part.copy_from_slice(&t.as_ref()[..part.len()]);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_evp_bytes_to_key() {
+    use crate::digest::{Md5, Sha256};
+
+    // the following test cases are from `openssl enc -P -pass pass:hunter2 -S 0102030405060708`
+    let salt = [1, 2, 3, 4, 5, 6, 7, 8];
+    let mut out = [0; 48];
+    evp_bytes_to_key::<Md5>(b"hunter2", &salt, 1, &mut out);
+    assert_eq!(
+        hex::encode(out),
+        "dd076b4bcd49c33676d8185c3dd67e935d3b7324ff7d8e1074d9734059f0971e\
+         feb9d83342d7af5beae1fcd7aa9415a6"
+    );
+    evp_bytes_to_key::<Sha256>(b"hunter2", &salt, 1, &mut out);
+    assert_eq!(
+        hex::encode(out),
+        "dfe64dd966c53cabb5e8cea39e74d4a4f8bc3d6a82a7a3a823417d707ebe109c\
+         afc6d4f8a1f08855e3344e99412ebf01"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_pbkdf2() {
+    use crate::digest::Sha256;
+
+    // the following test case is from RFC 7914
+    let mut out = [0; 64];
+    pbkdf2_hmac::<Sha256>(b"passwd", b"salt", 1, &mut out);
+    assert_eq!(
+        hex::encode(out),
+        "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc\
+         49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783"
+    );
+
+    // `openssl enc -P -pbkdf2 -pass pass:hunter2 -S 0102030405060708`
+    let mut out = [0; 32];
+    pbkdf2_hmac::<Sha256>(b"hunter2", &[1, 2, 3, 4, 5, 6, 7, 8], 10000, &mut out);
+    assert_eq!(
+        hex::encode(out),
+        "2e8bb77faf1815202a073292eb753ddcfe7511ef67860f7ed11a6ca615acde69"
+    );
+}
+
+#[cfg(all(test, feature = "alloc"))]
+#[test]
+fn test_kdf() {
+    let scrypt = "scrypt".parse::<Kdf>().unwrap();
+    assert_eq!(scrypt, Kdf::Scrypt(ScryptParams::DEFAULT));
+    let cheap = scrypt.with_cost(4).unwrap();
+    assert_eq!(hex::encode(cheap.to_bytes()), "01040000000800000001");
+    assert_eq!(Kdf::from_bytes(&cheap.to_bytes()), Ok(cheap));
+    assert!(scrypt.with_cost(300).is_err());
+
+    let pbkdf2 = "pbkdf2".parse::<Kdf>().unwrap().with_cost(10000).unwrap();
+    assert_eq!(hex::encode(pbkdf2.to_bytes()), "0200002710");
+    assert_eq!(Kdf::from_bytes(&pbkdf2.to_bytes()), Ok(pbkdf2));
+    let mut out = [0; 32];
+    pbkdf2
+        .derive(b"hunter2", &[1, 2, 3, 4, 5, 6, 7, 8], &mut out)
+        .unwrap();
+    assert_eq!(
+        hex::encode(out),
+        "2e8bb77faf1815202a073292eb753ddcfe7511ef67860f7ed11a6ca615acde69"
+    );
+
+    assert_eq!(
+        Kdf::from_bytes(&[0x01, 4]),
+        Err(Error::InvalidKdfParams("bad parameter length"))
+    );
+    assert_eq!(
+        Kdf::from_bytes(&[0x03]),
+        Err(Error::InvalidKdfParams("unknown key derivation"))
+    );
+    assert!(Kdf::from_bytes(&[0x02, 0, 0, 0, 0]).is_err());
+
+    // hostile costs from a header, 2^44 * 1 KiB of memory and 2^32 - 1
+    // iterations
+    assert_eq!(
+        Kdf::from_bytes(&hex::decode("012c0000000800000001").unwrap()),
+        Err(Error::InvalidKdfParams("scrypt memory out of range"))
+    );
+    assert_eq!(
+        Kdf::from_bytes(&hex::decode("02ffffffff").unwrap()),
+        Err(Error::InvalidKdfParams("too many PBKDF2 iterations"))
+    );
+}
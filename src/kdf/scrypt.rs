@@ -0,0 +1,231 @@
+//! scrypt (RFC 7914), a memory-hard KDF built on PBKDF2-HMAC-SHA256 and the
+//! Salsa20/8 core
+
+use alloc::vec::Vec;
+
+use super::pbkdf2_hmac;
+use crate::digest::Sha256;
+use crate::error::{Error, Result};
+use crate::secret::zeroize;
+
+/// The cost parameters of scrypt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Params {
+    /// base 2 logarithm of the CPU/memory cost `N`
+    pub log_n: u8,
+    /// block size, each block taking `128 * r` bytes
+    pub r: u32,
+    /// parallelization
+    pub p: u32,
+}
+
+impl Params {
+    /// `N = 2^17`, `r = 8` and `p = 1`, taking 128 MiB of memory
+    pub const DEFAULT: Params = Params {
+        log_n: 17,
+        r: 8,
+        p: 1,
+    };
+
+    /// the most memory the parameters may take, 2 GiB
+    pub const MAX_MEMORY: u64 = 1 << 31;
+
+    /// the most parallelization, as each lane takes as long as the one before
+    pub const MAX_P: u32 = 16;
+
+    /// Checks the parameters against the limits of RFC 7914, and against
+    /// [`Self::MAX_MEMORY`] and [`Self::MAX_P`], as they may come from an
+    /// untrusted header.
+    pub fn new(log_n: u8, r: u32, p: u32) -> Result<Self> {
+        if log_n == 0 || log_n as u32 >= usize::BITS || log_n as u64 >= 16 * r as u64 {
+            return Err(Error::InvalidKdfParams("scrypt cost out of range"));
+        }
+        if r == 0 || p == 0 || r as u64 * p as u64 >= 1 << 30 {
+            return Err(Error::InvalidKdfParams("scrypt block size out of range"));
+        }
+        if p > Self::MAX_P {
+            return Err(Error::InvalidKdfParams("scrypt parallelization too high"));
+        }
+        // `v` and `b`, as allocated by `scrypt`
+        if 128 * r as u128 * ((1u128 << log_n) + p as u128) > Self::MAX_MEMORY as u128 {
+            return Err(Error::InvalidKdfParams("scrypt memory out of range"));
+        }
+        Ok(Self { log_n, r, p })
+    }
+}
+
+/// `len` zeroes, failing rather than aborting if they cannot be allocated.
+fn zeroed<T: Copy + Default>(len: usize) -> Result<Vec<T>> {
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(len)
+        .map_err(|_| Error::InvalidKdfParams("not enough memory for scrypt"))?;
+    buf.resize(len, T::default());
+    Ok(buf)
+}
+
+/// Derives `out` from `pass` and `salt` with scrypt, which takes
+/// `128 * r * 2^log_n` bytes of memory, failing with
+/// [`Error::InvalidKdfParams`] if they cannot be allocated.
+pub fn scrypt(pass: &[u8], salt: &[u8], params: &Params, out: &mut [u8]) -> Result<()> {
+    let words = 32 * params.r as usize;
+    // all allocated before anything secret is written to them
+    let mut b = zeroed::<u8>(4 * words * params.p as usize)?;
+    let mut x = zeroed(words)?;
+    let mut v = zeroed(words << params.log_n)?;
+    let mut y = zeroed(words)?;
+    pbkdf2_hmac::<Sha256>(pass, salt, 1, &mut b);
+
+    for chunk in b.chunks_mut(4 * words) {
+        for (x, c) in x.iter_mut().zip(chunk.chunks(4)) {
+            *x = u32::from_le_bytes([c[0], c[1], c[2], c[3]]);
+        }
+        ro_mix(&mut x, &mut v, &mut y, params.log_n);
+        for (x, c) in x.iter().zip(chunk.chunks_mut(4)) {
+            c.copy_from_slice(&x.to_le_bytes());
+        }
+    }
+    pbkdf2_hmac::<Sha256>(pass, &b, 1, out);
+    for buf in [&mut x, &mut v, &mut y] {
+        zeroize(buf);
+    }
+    zeroize(&mut b);
+    Ok(())
+}
+
+/// scryptROMix, mixing `x` with the `2^log_n` states before it kept in `v`
+fn ro_mix(x: &mut [u32], v: &mut [u32], y: &mut [u32], log_n: u8) {
+    let words = x.len();
+    for state in v.chunks_mut(words) {
+        state.copy_from_slice(x);
+        block_mix(x, y);
+    }
+    let mask = (1u64 << log_n) - 1;
+    for _ in 0..1u64 << log_n {
+        // Integerify, the first word of the last 64 byte block
+        let last = words - 16;
+        let j = (x[last] as u64 | (x[last + 1] as u64) << 32) & mask;
+        let state = &v[j as usize * words..][..words];
+        for (x, v) in x.iter_mut().zip(state) {
+            *x ^= v;
+        }
+        block_mix(x, y);
+    }
+}
+
+/// scryptBlockMix, with `y` as scratch space
+fn block_mix(b: &mut [u32], y: &mut [u32]) {
+    let half = b.len() / 2;
+    let mut x = [0; 16];
+    x.copy_from_slice(&b[b.len() - 16..]);
+    for (i, block) in b.chunks(16).enumerate() {
+        for (x, b) in x.iter_mut().zip(block) {
+            *x ^= b;
+        }
+        salsa20_8(&mut x);
+        // the even blocks go to the first half, the odd ones to the second
+        let at = (i % 2) * half + (i / 2) * 16;
+        y[at..at + 16].copy_from_slice(&x);
+    }
+    b.copy_from_slice(y);
+}
+
+/// The Salsa20/8 core, applied to `b` in place
+fn salsa20_8(b: &mut [u32; 16]) {
+    fn quarter(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+        x[b] ^= x[a].wrapping_add(x[d]).rotate_left(7);
+        x[c] ^= x[b].wrapping_add(x[a]).rotate_left(9);
+        x[d] ^= x[c].wrapping_add(x[b]).rotate_left(13);
+        x[a] ^= x[d].wrapping_add(x[c]).rotate_left(18);
+    }
+
+    let mut x = *b;
+    for _ in 0..4 {
+        // columns
+        quarter(&mut x, 0, 4, 8, 12);
+        quarter(&mut x, 5, 9, 13, 1);
+        quarter(&mut x, 10, 14, 2, 6);
+        quarter(&mut x, 15, 3, 7, 11);
+        // rows
+        quarter(&mut x, 0, 1, 2, 3);
+        quarter(&mut x, 5, 6, 7, 4);
+        quarter(&mut x, 10, 11, 8, 9);
+        quarter(&mut x, 15, 12, 13, 14);
+    }
+    for (b, x) in b.iter_mut().zip(&x) {
+        *b = b.wrapping_add(*x);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_salsa20_8() {
+    // the following test case is from RFC 7914
+    let input = hex::decode(
+        "7e879a214f3ec9867ca940e641718f26baee555b8c61c1b50df846116dcd3b1d\
+         ee24f319df9b3d8514121e4b5ac5aa3276021d2909c74829edebc68db8b8c25e",
+    )
+    .unwrap();
+    let mut b = [0; 16];
+    for (b, c) in b.iter_mut().zip(input.chunks(4)) {
+        *b = u32::from_le_bytes([c[0], c[1], c[2], c[3]]);
+    }
+    salsa20_8(&mut b);
+    let output = b.iter().flat_map(|b| b.to_le_bytes()).collect::<Vec<_>>();
+    assert_eq!(
+        hex::encode(output),
+        "a41f859c6608cc993b81cacb020cef05044b2181a2fd337dfd7b1c6396682f29\
+         b4393168e3c9e6bcfe6bc5b7a06d96bae424cc102c91745c24ad673dc7618f81"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_scrypt() {
+    // the following test cases are from RFC 7914
+    let mut out = [0; 64];
+    scrypt(b"", b"", &Params::new(4, 1, 1).unwrap(), &mut out).unwrap();
+    assert_eq!(
+        hex::encode(out),
+        "77d6576238657b203b19ca42c18a0497f16b4844e3074ae8dfdffa3fede21442\
+         fcd0069ded0948f8326a753a0fc81f17e8d3e0fb2e0d3628cf35e20c38d18906"
+    );
+    scrypt(
+        b"password",
+        b"NaCl",
+        &Params::new(10, 8, 16).unwrap(),
+        &mut out,
+    )
+    .unwrap();
+    assert_eq!(
+        hex::encode(out),
+        "fdbabe1c9d3472007856e7190d01e9fe7c6ad7cbc8237830e77376634b373162\
+         2eaf30d92e22a3886ff109279d9830dac727afb94a83ee6d8360cbdfa2cc0640"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_params() {
+    assert!(Params::new(17, 8, 1).is_ok());
+    assert_eq!(
+        Params::new(0, 8, 1),
+        Err(Error::InvalidKdfParams("scrypt cost out of range"))
+    );
+    // N must be below 2^(128 * r / 8)
+    assert!(Params::new(16, 1, 1).is_err());
+    assert_eq!(
+        Params::new(10, 1 << 15, 1 << 15),
+        Err(Error::InvalidKdfParams("scrypt block size out of range"))
+    );
+    // 2 GiB is the most memory, and 16 the most parallelization
+    assert!(Params::new(21, 8, 1).is_err());
+    assert!(Params::new(20, 8, 16).is_ok());
+    assert_eq!(
+        Params::new(20, 16, 1),
+        Err(Error::InvalidKdfParams("scrypt memory out of range"))
+    );
+    assert_eq!(
+        Params::new(10, 8, 17),
+        Err(Error::InvalidKdfParams("scrypt parallelization too high"))
+    );
+}
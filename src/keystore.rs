@@ -0,0 +1,510 @@
+//! A keystore holding named keys and their metadata, wrapped as a whole
+//! under a master key or a key derived from a passphrase. See
+//! `docs/keystore.md` for the format.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::{rngs::OsRng, RngCore};
+
+use crate::aes::{Array, RijndaelMode, AES128, AES192, AES256};
+use crate::cipher::CipherSpec;
+use crate::error::{Error, Result};
+use crate::kdf::Kdf;
+use crate::keywrap;
+use crate::secret::{zeroize, SecretKey};
+
+/// the bytes every keystore starts with
+pub const MAGIC: [u8; 4] = *b"AESK";
+/// the format version written by [`Keystore::to_bytes`]
+pub const VERSION: u8 = 1;
+/// length of the ids keys are told apart by
+pub const ID_LEN: usize = 8;
+
+/// field types, numbered as in the container header
+const END: u8 = 0x00;
+const SALT: u8 = 0x03;
+const KDF: u8 = 0x04;
+/// length of the key encryption key derived from a passphrase
+const KEK_LEN: usize = 32;
+
+pub type KeyId = [u8; ID_LEN];
+
+/// Whether a key may still encrypt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// encrypts and decrypts, at most one version of a name is active
+    Active,
+    /// only decrypts what was encrypted with it before
+    Retired,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Active => write!(f, "active"),
+            Status::Retired => write!(f, "retired"),
+        }
+    }
+}
+
+/// A key in the keystore, one version of those going by its name
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub id: KeyId,
+    pub name: String,
+    /// counts up from 1 as the key is rotated
+    pub version: u32,
+    /// the cipher spec the key is for
+    pub spec: CipherSpec,
+    /// creation time, in seconds since the Unix epoch
+    pub created: u64,
+    pub status: Status,
+    pub key: SecretKey,
+}
+
+/// The secret opening a keystore
+#[derive(Debug, Clone, Copy)]
+pub enum Unlock<'a> {
+    /// a 16, 24 or 32 byte key wrapping the keystore
+    MasterKey(&'a [u8]),
+    /// a passphrase the key wrapping the keystore is derived from
+    Passphrase(&'a [u8]),
+}
+
+#[derive(Debug)]
+pub struct Keystore {
+    entries: Vec<Entry>,
+    /// the key wrapping the keystore
+    kek: SecretKey,
+    /// the salt and the key derivation of `kek`, if derived from a
+    /// passphrase
+    derivation: Option<(Vec<u8>, Kdf)>,
+}
+
+impl Keystore {
+    /// An empty keystore, wrapped under `key`.
+    pub fn with_master_key(key: &[u8]) -> Result<Self> {
+        if ![16, 24, 32].contains(&key.len()) {
+            return Err(Error::UnsupportedKeyLength(key.len()));
+        }
+        Ok(Self {
+            entries: Vec::new(),
+            kek: SecretKey::new(key.to_vec()),
+            derivation: None,
+        })
+    }
+
+    /// An empty keystore, wrapped under a key derived from `pass` and `salt`
+    /// with `kdf`.
+    pub fn with_passphrase(pass: &[u8], kdf: Kdf, salt: Vec<u8>) -> Result<Self> {
+        let mut kek = SecretKey::new(vec![0; KEK_LEN]);
+        kdf.derive(pass, &salt, &mut kek)?;
+        Ok(Self {
+            entries: Vec::new(),
+            kek,
+            derivation: Some((salt, kdf)),
+        })
+    }
+
+    /// Opens a keystore written by [`Self::to_bytes`], failing with
+    /// [`Error::AuthenticationFailed`] if `unlock` is not the secret it was
+    /// wrapped under.
+    pub fn open(data: &[u8], unlock: Unlock<'_>) -> Result<Self> {
+        if !data.starts_with(&MAGIC) {
+            return Err(Error::Keystore("bad magic bytes"));
+        }
+        let mut data = &data[MAGIC.len()..];
+        match take(&mut data, 1)? {
+            [VERSION] => {}
+            _ => return Err(Error::Keystore("unsupported version")),
+        }
+        let (mut salt, mut kdf) = (None, None);
+        loop {
+            let ty = take(&mut data, 1)?[0];
+            if ty == END {
+                break;
+            }
+            let len = take(&mut data, 2)?;
+            let value = take(&mut data, u16::from_be_bytes([len[0], len[1]]) as usize)?.to_vec();
+            let duplicate = match ty {
+                SALT => salt.replace(value).is_some(),
+                KDF => kdf.replace(Kdf::from_bytes(&value)?).is_some(),
+                _ => return Err(Error::Keystore("unknown field")),
+            };
+            if duplicate {
+                return Err(Error::Keystore("duplicate field"));
+            }
+        }
+
+        let mut keystore = match (salt, kdf, unlock) {
+            (None, None, Unlock::MasterKey(key)) => Self::with_master_key(key)?,
+            (Some(salt), Some(kdf), Unlock::Passphrase(pass)) => {
+                Self::with_passphrase(pass, kdf, salt)?
+            }
+            (None, None, Unlock::Passphrase(_)) => {
+                return Err(Error::Keystore("protected by a master key"))
+            }
+            (Some(_), Some(_), Unlock::MasterKey(_)) => {
+                return Err(Error::Keystore("protected by a passphrase"))
+            }
+            _ => return Err(Error::Keystore("salt or key derivation missing")),
+        };
+        let body = unwrap(&keystore.kek, data)?;
+        keystore.entries = parse_entries(&body)?;
+        Ok(keystore)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        if let Some((salt, kdf)) = &self.derivation {
+            for (ty, value) in [(SALT, &salt[..]), (KDF, &kdf.to_bytes()[..])] {
+                out.push(ty);
+                out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+                out.extend_from_slice(value);
+            }
+        }
+        out.push(END);
+        out.extend_from_slice(&wrap(&self.kek, &self.entries_to_bytes()));
+        out
+    }
+
+    /// Every version of every key, in the order they were added.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// The key `id`, whatever its status.
+    pub fn get(&self, id: &[u8]) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.id == id)
+    }
+
+    /// The version of `name` which encrypts.
+    pub fn active(&self, name: &str) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .find(|e| e.name == name && e.status == Status::Active)
+    }
+
+    /// Adds `key` for `spec` as the first version of `name`, which must be
+    /// new to the keystore.
+    pub fn add(&mut self, name: &str, spec: CipherSpec, key: SecretKey) -> Result<&Entry> {
+        if !valid_name(name) {
+            return Err(Error::Keystore("invalid key name"));
+        }
+        if self.entries.iter().any(|e| e.name == name) {
+            return Err(Error::Keystore("key name taken"));
+        }
+        check_key_len(&spec, &key)?;
+        Ok(self.push(name, 1, spec, key))
+    }
+
+    /// Adds `key` as the next version of `name`, retiring the version
+    /// before it.
+    pub fn rotate(&mut self, name: &str, key: SecretKey) -> Result<&Entry> {
+        let latest = self
+            .entries
+            .iter()
+            .filter(|e| e.name == name)
+            .max_by_key(|e| e.version)
+            .ok_or(Error::Keystore("no key of that name"))?;
+        let (version, spec) = (latest.version + 1, latest.spec);
+        check_key_len(&spec, &key)?;
+        for entry in self.entries.iter_mut().filter(|e| e.name == name) {
+            entry.status = Status::Retired;
+        }
+        Ok(self.push(name, version, spec, key))
+    }
+
+    /// Retires the active version of `name`, which leaves it with none
+    /// encrypting.
+    pub fn retire(&mut self, name: &str) -> Result<&Entry> {
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|e| e.name == name && e.status == Status::Active)
+            .ok_or(Error::Keystore("no active key of that name"))?;
+        entry.status = Status::Retired;
+        Ok(entry)
+    }
+
+    fn push(&mut self, name: &str, version: u32, spec: CipherSpec, key: SecretKey) -> &Entry {
+        let mut id = [0; ID_LEN];
+        while id == [0; ID_LEN] || self.get(&id).is_some() {
+            OsRng.fill_bytes(&mut id);
+        }
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        self.entries.push(Entry {
+            id,
+            name: name.to_string(),
+            version,
+            spec,
+            created,
+            status: Status::Active,
+            key,
+        });
+        self.entries.last().unwrap()
+    }
+
+    fn entries_to_bytes(&self) -> SecretKey {
+        let spec_names = self
+            .entries
+            .iter()
+            .map(|e| e.spec.to_string())
+            .collect::<Vec<_>>();
+        let len = 4 + self
+            .entries
+            .iter()
+            .zip(&spec_names)
+            .map(|(e, spec)| {
+                ID_LEN + 1 + e.name.len() + 4 + 1 + spec.len() + 8 + 1 + 1 + e.key.len()
+            })
+            .sum::<usize>();
+        // reserved up front, as growing it would leave copies of the keys
+        // behind
+        let mut out = Vec::with_capacity(len);
+        out.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        for (e, spec) in self.entries.iter().zip(&spec_names) {
+            out.extend_from_slice(&e.id);
+            out.push(e.name.len() as u8);
+            out.extend_from_slice(e.name.as_bytes());
+            out.extend_from_slice(&e.version.to_be_bytes());
+            out.push(spec.len() as u8);
+            out.extend_from_slice(spec.as_bytes());
+            out.extend_from_slice(&e.created.to_be_bytes());
+            out.push(match e.status {
+                Status::Active => 1,
+                Status::Retired => 2,
+            });
+            out.push(e.key.len() as u8);
+            out.extend_from_slice(&e.key);
+        }
+        SecretKey::new(out)
+    }
+}
+
+/// Whether `name` may name a key: 1 to 64 letters, digits, `.`, `_` or `-`.
+pub fn valid_name(name: &str) -> bool {
+    (1..=64).contains(&name.len())
+        && name
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || b"._-".contains(&c))
+}
+
+fn check_key_len(spec: &CipherSpec, key: &[u8]) -> Result<()> {
+    if key.len() != spec.key_len() {
+        return Err(Error::InvalidKeyLength {
+            expected: spec.key_len(),
+            actual: key.len(),
+        });
+    }
+    Ok(())
+}
+
+fn parse_entries(mut data: &[u8]) -> Result<Vec<Entry>> {
+    fn string<'a>(data: &mut &'a [u8]) -> Result<&'a str> {
+        let len = take(data, 1)?[0] as usize;
+        std::str::from_utf8(take(data, len)?).map_err(|_| Error::Keystore("malformed entry"))
+    }
+
+    let count = take(&mut data, 4)?;
+    let count = u32::from_be_bytes([count[0], count[1], count[2], count[3]]);
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let mut id = [0; ID_LEN];
+        id.copy_from_slice(take(&mut data, ID_LEN)?);
+        let name = string(&mut data)?.to_string();
+        let version = take(&mut data, 4)?;
+        let version = u32::from_be_bytes([version[0], version[1], version[2], version[3]]);
+        let spec = CipherSpec::from_str(string(&mut data)?)
+            .map_err(|_| Error::Keystore("unknown cipher spec"))?;
+        let mut created = [0; 8];
+        created.copy_from_slice(take(&mut data, 8)?);
+        let status = match take(&mut data, 1)?[0] {
+            1 => Status::Active,
+            2 => Status::Retired,
+            _ => return Err(Error::Keystore("unknown key status")),
+        };
+        let len = take(&mut data, 1)?[0] as usize;
+        let key = SecretKey::new(take(&mut data, len)?.to_vec());
+        if key.len() != spec.key_len() || !valid_name(&name) {
+            return Err(Error::Keystore("malformed entry"));
+        }
+        entries.push(Entry {
+            id,
+            name,
+            version,
+            spec,
+            created: u64::from_be_bytes(created),
+            status,
+            key,
+        });
+    }
+    if !data.is_empty() {
+        return Err(Error::Keystore("trailing data"));
+    }
+    Ok(entries)
+}
+
+/// Splits the first `len` bytes off `data`.
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if data.len() < len {
+        return Err(Error::Keystore("truncated"));
+    }
+    let (head, tail) = data.split_at(len);
+    *data = tail;
+    Ok(head)
+}
+
+fn wrap(kek: &[u8], data: &[u8]) -> Vec<u8> {
+    fn wrap_with<M: RijndaelMode>(kek: &[u8], data: &[u8], out: &mut [u8]) -> Result<()> {
+        let mut kek = M::Key::from_slice(kek);
+        let result = keywrap::wrap_pad::<M>(&kek, data, out);
+        zeroize(kek.as_mut());
+        result
+    }
+
+    let mut out = vec![0; keywrap::wrapped_len(data.len())];
+    match kek.len() {
+        16 => wrap_with::<AES128>(kek, data, &mut out),
+        24 => wrap_with::<AES192>(kek, data, &mut out),
+        _ => wrap_with::<AES256>(kek, data, &mut out),
+    }
+    // the entry count keeps `data` from being empty
+    .expect("the keystore is never empty");
+    out
+}
+
+fn unwrap(kek: &[u8], wrapped: &[u8]) -> Result<SecretKey> {
+    fn unwrap_with<M: RijndaelMode>(kek: &[u8], wrapped: &[u8], out: &mut [u8]) -> Result<usize> {
+        let mut kek = M::Key::from_slice(kek);
+        let result = keywrap::unwrap_pad::<M>(&kek, wrapped, out);
+        zeroize(kek.as_mut());
+        result
+    }
+
+    let mut out = SecretKey::new(vec![0; wrapped.len().saturating_sub(8)]);
+    let len = match kek.len() {
+        16 => unwrap_with::<AES128>(kek, wrapped, &mut out),
+        24 => unwrap_with::<AES192>(kek, wrapped, &mut out),
+        _ => unwrap_with::<AES256>(kek, wrapped, &mut out),
+    }?;
+    Ok(SecretKey::new(out[..len].to_vec()))
+}
+
+#[cfg(test)]
+#[test]
+fn test_round_trip() {
+    let spec = "aes-128-cbc".parse::<CipherSpec>().unwrap();
+    let master = [0x2b; 32];
+    let mut keystore = Keystore::with_master_key(&master).unwrap();
+    let id = keystore
+        .add("db", spec, SecretKey::new(vec![1; 16]))
+        .unwrap()
+        .id;
+    keystore
+        .add("logs", spec, SecretKey::new(vec![2; 16]))
+        .unwrap();
+    let rotated = keystore
+        .rotate("db", SecretKey::new(vec![3; 16]))
+        .unwrap()
+        .id;
+    keystore.retire("logs").unwrap();
+
+    let bytes = keystore.to_bytes();
+    assert!(bytes.starts_with(b"AESK\x01\x00"));
+    let opened = Keystore::open(&bytes, Unlock::MasterKey(&master)).unwrap();
+    let summary = |keystore: &Keystore| {
+        keystore
+            .entries()
+            .iter()
+            .map(|e| (e.id, e.name.clone(), e.version, e.status, e.key.to_vec()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(summary(&opened), summary(&keystore));
+    assert_eq!(opened.get(&id).unwrap().status, Status::Retired);
+    assert_eq!(opened.active("db").unwrap().id, rotated);
+    assert_eq!(opened.active("db").unwrap().version, 2);
+    assert!(opened.active("logs").is_none());
+
+    assert_eq!(
+        Keystore::open(&bytes, Unlock::MasterKey(&[0x2c; 32])).unwrap_err(),
+        Error::AuthenticationFailed
+    );
+    assert_eq!(
+        Keystore::open(&bytes, Unlock::Passphrase(b"pass")).unwrap_err(),
+        Error::Keystore("protected by a master key")
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_passphrase() {
+    let kdf = Kdf::Pbkdf2Sha256 { iterations: 1000 };
+    let keystore = Keystore::with_passphrase(b"pass", kdf, vec![7; 16]).unwrap();
+    let bytes = keystore.to_bytes();
+    let opened = Keystore::open(&bytes, Unlock::Passphrase(b"pass")).unwrap();
+    assert!(opened.entries().is_empty());
+    assert_eq!(
+        Keystore::open(&bytes, Unlock::Passphrase(b"wrong")).unwrap_err(),
+        Error::AuthenticationFailed
+    );
+    assert_eq!(
+        Keystore::open(&bytes, Unlock::MasterKey(&[0; 16])).unwrap_err(),
+        Error::Keystore("protected by a passphrase")
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_errors() {
+    let spec = "aes-256-cbc".parse::<CipherSpec>().unwrap();
+    let mut keystore = Keystore::with_master_key(&[0; 16]).unwrap();
+    assert_eq!(
+        keystore
+            .add("a b", spec, SecretKey::new(vec![0; 32]))
+            .unwrap_err(),
+        Error::Keystore("invalid key name")
+    );
+    assert_eq!(
+        keystore
+            .add("a", spec, SecretKey::new(vec![0; 16]))
+            .unwrap_err(),
+        Error::InvalidKeyLength {
+            expected: 32,
+            actual: 16
+        }
+    );
+    keystore
+        .add("a", spec, SecretKey::new(vec![0; 32]))
+        .unwrap();
+    assert_eq!(
+        keystore
+            .add("a", spec, SecretKey::new(vec![0; 32]))
+            .unwrap_err(),
+        Error::Keystore("key name taken")
+    );
+    assert_eq!(
+        keystore
+            .rotate("b", SecretKey::new(vec![0; 32]))
+            .unwrap_err(),
+        Error::Keystore("no key of that name")
+    );
+    keystore.retire("a").unwrap();
+    assert_eq!(
+        keystore.retire("a").unwrap_err(),
+        Error::Keystore("no active key of that name")
+    );
+    assert_eq!(
+        Keystore::with_master_key(&[0; 20]).unwrap_err(),
+        Error::UnsupportedKeyLength(20)
+    );
+    assert_eq!(
+        Keystore::open(b"AESC\x01\x00", Unlock::MasterKey(&[0; 16])).unwrap_err(),
+        Error::Keystore("bad magic bytes")
+    );
+}
@@ -0,0 +1,262 @@
+//! AES key wrap (RFC 3394) and key wrap with padding (RFC 5649), which
+//! encrypt keys, or other short secrets, under a key encryption key while
+//! protecting their integrity
+
+use crate::aes::{Array, BlockCipher, Rijndael, RijndaelMode};
+use crate::error::{Error, Result};
+use crate::secret::{ct_eq, xor, zeroize};
+
+/// the initial value of RFC 3394, which unwrapping checks for
+const IV: [u8; 8] = [0xa6; 8];
+/// the first half of the alternative initial value of RFC 5649, followed by
+/// the length of the data
+const AIV: [u8; 4] = [0xa6, 0x59, 0x59, 0xa6];
+
+/// The length of `len` bytes wrapped by [`wrap_pad`], padded to a multiple
+/// of 8 bytes and with the 8 byte integrity check value in front.
+pub const fn wrapped_len(len: usize) -> usize {
+    len.div_ceil(8) * 8 + 8
+}
+
+/// Wraps `data`, a multiple of 8 and at least 16 bytes long, into `out`,
+/// which must be 8 bytes longer.
+pub fn wrap<M: RijndaelMode>(kek: &M::Key, data: &[u8], out: &mut [u8]) -> Result<()> {
+    if !data.len().is_multiple_of(8) || data.len() < 16 {
+        return Err(Error::InvalidPlaintextLength {
+            block: 8,
+            actual: data.len(),
+        });
+    }
+    assert_eq!(out.len(), data.len() + 8);
+    out[8..].copy_from_slice(data);
+    let a = w(&Rijndael::<M>::new(kek), IV, &mut out[8..]);
+    out[..8].copy_from_slice(&a);
+    Ok(())
+}
+
+/// Unwraps `wrapped` into `out`, which must be 8 bytes shorter, failing with
+/// [`Error::AuthenticationFailed`] if it was not wrapped under `kek`.
+pub fn unwrap<M: RijndaelMode>(kek: &M::Key, wrapped: &[u8], out: &mut [u8]) -> Result<()> {
+    if !wrapped.len().is_multiple_of(8) || wrapped.len() < 24 {
+        return Err(Error::InvalidCiphertextLength {
+            block: 8,
+            actual: wrapped.len(),
+        });
+    }
+    assert_eq!(out.len(), wrapped.len() - 8);
+    out.copy_from_slice(&wrapped[8..]);
+    let mut a = [0; 8];
+    a.copy_from_slice(&wrapped[..8]);
+    let a = w_inv(&Rijndael::<M>::new(kek), a, out);
+    if !ct_eq(&a, &IV) {
+        zeroize(out);
+        return Err(Error::AuthenticationFailed);
+    }
+    Ok(())
+}
+
+/// Wraps `data` of any length but zero into `out`, which must be
+/// [`wrapped_len`] bytes long.
+pub fn wrap_pad<M: RijndaelMode>(kek: &M::Key, data: &[u8], out: &mut [u8]) -> Result<()> {
+    if data.is_empty() || data.len() > u32::MAX as usize {
+        return Err(Error::InvalidPlaintextLength {
+            block: 8,
+            actual: data.len(),
+        });
+    }
+    assert_eq!(out.len(), wrapped_len(data.len()));
+    let mut a = [0; 8];
+    a[..4].copy_from_slice(&AIV);
+    a[4..].copy_from_slice(&(data.len() as u32).to_be_bytes());
+    out.iter_mut().for_each(|b| *b = 0);
+    out[8..8 + data.len()].copy_from_slice(data);
+
+    let cipher = Rijndael::<M>::new(kek);
+    if out.len() == 16 {
+        // a single block of data is encrypted as is
+        out[..8].copy_from_slice(&a);
+        let mut block = M::Block::from_slice(out);
+        cipher.encrypt_block(&mut block);
+        out.copy_from_slice(block.as_ref());
+        zeroize(block.as_mut());
+    } else {
+        let a = w(&cipher, a, &mut out[8..]);
+        out[..8].copy_from_slice(&a);
+    }
+    Ok(())
+}
+
+/// Unwraps what [`wrap_pad`] wrapped into `out`, which must be 8 bytes
+/// shorter than `wrapped`, and returns the length of the data at its start.
+pub fn unwrap_pad<M: RijndaelMode>(kek: &M::Key, wrapped: &[u8], out: &mut [u8]) -> Result<usize> {
+    if !wrapped.len().is_multiple_of(8) || wrapped.len() < 16 {
+        return Err(Error::InvalidCiphertextLength {
+            block: 8,
+            actual: wrapped.len(),
+        });
+    }
+    assert_eq!(out.len(), wrapped.len() - 8);
+    let cipher = Rijndael::<M>::new(kek);
+    let mut a = [0; 8];
+    if wrapped.len() == 16 {
+        let mut block = M::Block::from_slice(wrapped);
+        cipher.decrypt_block(&mut block);
+        a.copy_from_slice(&block.as_ref()[..8]);
+        out.copy_from_slice(&block.as_ref()[8..]);
+        zeroize(block.as_mut());
+    } else {
+        out.copy_from_slice(&wrapped[8..]);
+        a.copy_from_slice(&wrapped[..8]);
+        a = w_inv(&cipher, a, out);
+    }
+
+    let len = u32::from_be_bytes([a[4], a[5], a[6], a[7]]) as usize;
+    let valid = ct_eq(&a[..4], &AIV)
+        && len + 8 > out.len()
+        && len <= out.len()
+        && out[len..].iter().all(|&b| b == 0);
+    if !valid {
+        zeroize(out);
+        return Err(Error::AuthenticationFailed);
+    }
+    Ok(len)
+}
+
+/// The wrapping function W, encrypting the 8 byte blocks `r` in place and
+/// returning the integrity check register started from `a`
+fn w<M: RijndaelMode>(cipher: &Rijndael<M>, mut a: [u8; 8], r: &mut [u8]) -> [u8; 8] {
+    let n = r.len() / 8;
+    let mut b = M::Block::zeroed();
+    for j in 0..6 {
+        for (i, r) in r.chunks_exact_mut(8).enumerate() {
+            b.as_mut()[..8].copy_from_slice(&a);
+            b.as_mut()[8..].copy_from_slice(r);
+            cipher.encrypt_block(&mut b);
+            let t = (n * j + i + 1) as u64;
+            a.copy_from_slice(&b.as_ref()[..8]);
+            xor(&mut a, &t.to_be_bytes());
+            r.copy_from_slice(&b.as_ref()[8..]);
+        }
+    }
+    zeroize(b.as_mut());
+    a
+}
+
+/// The unwrapping function W⁻¹, undoing [`w`]
+fn w_inv<M: RijndaelMode>(cipher: &Rijndael<M>, mut a: [u8; 8], r: &mut [u8]) -> [u8; 8] {
+    let n = r.len() / 8;
+    let mut b = M::Block::zeroed();
+    for j in (0..6).rev() {
+        for (i, r) in r.chunks_exact_mut(8).enumerate().rev() {
+            let t = (n * j + i + 1) as u64;
+            xor(&mut a, &t.to_be_bytes());
+            b.as_mut()[..8].copy_from_slice(&a);
+            b.as_mut()[8..].copy_from_slice(r);
+            cipher.decrypt_block(&mut b);
+            a.copy_from_slice(&b.as_ref()[..8]);
+            r.copy_from_slice(&b.as_ref()[8..]);
+        }
+    }
+    zeroize(b.as_mut());
+    a
+}
+
+#[cfg(test)]
+macro_rules! impl_test_wrap {
+    ($mode: ty, $kek: literal, $data: literal, $wrapped: literal) => {{
+        use std::convert::TryInto;
+
+        let kek = hex::decode($kek).unwrap();
+        let kek = kek.as_slice().try_into().unwrap();
+        let data = hex::decode($data).unwrap();
+        let mut wrapped = vec![0; data.len() + 8];
+        wrap::<$mode>(kek, &data, &mut wrapped).unwrap();
+        assert_eq!(hex::encode(&wrapped), $wrapped);
+
+        let mut out = vec![0; data.len()];
+        unwrap::<$mode>(kek, &wrapped, &mut out).unwrap();
+        assert_eq!(out, data);
+        wrapped[20] ^= 1;
+        assert_eq!(
+            unwrap::<$mode>(kek, &wrapped, &mut out),
+            Err(Error::AuthenticationFailed)
+        );
+    }};
+}
+
+#[cfg(test)]
+#[test]
+fn test_wrap() {
+    use crate::aes::{AES128, AES192, AES256};
+
+    // the following test cases are from RFC 3394
+    impl_test_wrap!(
+        AES128,
+        "000102030405060708090a0b0c0d0e0f",
+        "00112233445566778899aabbccddeeff",
+        "1fa68b0a8112b447aef34bd8fb5a7b829d3e862371d2cfe5"
+    );
+    impl_test_wrap!(
+        AES192,
+        "000102030405060708090a0b0c0d0e0f1011121314151617",
+        "00112233445566778899aabbccddeeff0001020304050607",
+        "031d33264e15d33268f24ec260743edce1c6c7ddee725a936ba814915c6762d2"
+    );
+    impl_test_wrap!(
+        AES256,
+        "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+        "00112233445566778899aabbccddeeff000102030405060708090a0b0c0d0e0f",
+        "28c9f404c4b810f4cbccb35cfb87f8263f5786e2d80ed326cbc7f0e71a99f43bfb988b9b7a02dd21"
+    );
+
+    assert_eq!(
+        wrap::<AES128>(&[0; 16], &[0; 12], &mut [0; 20]),
+        Err(Error::InvalidPlaintextLength {
+            block: 8,
+            actual: 12
+        })
+    );
+}
+
+#[cfg(test)]
+macro_rules! impl_test_wrap_pad {
+    ($mode: ty, $kek: literal, $data: literal, $wrapped: literal) => {{
+        use std::convert::TryInto;
+
+        let kek = hex::decode($kek).unwrap();
+        let kek = kek.as_slice().try_into().unwrap();
+        let data = hex::decode($data).unwrap();
+        let mut wrapped = vec![0; wrapped_len(data.len())];
+        wrap_pad::<$mode>(kek, &data, &mut wrapped).unwrap();
+        assert_eq!(hex::encode(&wrapped), $wrapped);
+
+        let mut out = vec![0; wrapped.len() - 8];
+        let len = unwrap_pad::<$mode>(kek, &wrapped, &mut out).unwrap();
+        assert_eq!(&out[..len], &data[..]);
+        wrapped[0] ^= 1;
+        assert_eq!(
+            unwrap_pad::<$mode>(kek, &wrapped, &mut out),
+            Err(Error::AuthenticationFailed)
+        );
+    }};
+}
+
+#[cfg(test)]
+#[test]
+fn test_wrap_pad() {
+    use crate::aes::AES192;
+
+    // the following test cases are from RFC 5649
+    impl_test_wrap_pad!(
+        AES192,
+        "5840df6e29b02af1ab493b705bf16ea1ae8338f4dcc176a8",
+        "c37b7e6492584340bed12207808941155068f738",
+        "138bdeaa9b8fa7fc61f97742e72248ee5ae6ae5360d1ae6a5f54f373fa543b6a"
+    );
+    impl_test_wrap_pad!(
+        AES192,
+        "5840df6e29b02af1ab493b705bf16ea1ae8338f4dcc176a8",
+        "466f7250617369",
+        "afbeb0f07dfbf5419200f2ccb50bb24f"
+    );
+}
@@ -0,0 +1,78 @@
+//! An AES implementation in Rust.
+//!
+//! - [`aes`] contains the Rijndael block cipher, sized by the [`aes::AES128`],
+//!   [`aes::AES192`] and [`aes::AES256`] modes.
+//! - [`stream`] contains the block cipher modes of operation, driven through
+//!   the [`stream::Streamer`] trait.
+//! - [`padding`] contains the [`padding::Padding`] schemes.
+//! - [`cipher`] selects a combination of the above by a spec parsed at runtime.
+//! - [`container`] wraps ciphertext in a header describing how to decrypt it,
+//!   and [`openssl`] reads and writes the files of `openssl enc`.
+//! - [`encoding`] turns ciphertext into hex, Base64 or armored text.
+//! - [`digest`] and [`kdf`] derive keys from passphrases, with scrypt or
+//!   PBKDF2.
+//! - [`mac`] contains CMAC, and [`kcv`] the check values verifying a key.
+//! - [`keywrap`] wraps keys under a key encryption key, and [`keystore`]
+//!   keeps named keys in a file wrapped so.
+//! - [`siv`] encrypts deterministically with AES-SIV, for data such as file
+//!   names which must encrypt the same every time.
+//! - [`secret`] wipes key material from memory, and holds keys in a
+//!   [`secret::SecretKey`] which does so when dropped.
+//! - [`error`] contains the [`error::Error`] returned by fallible operations.
+//!
+//! ```
+//! # #[cfg(feature = "alloc")] {
+//! use aes::{aes::AES128, padding::PKCS7, stream::{CipherBlockChaining, Streamer}};
+//!
+//! let key = [0x2b; 16];
+//! let iv = [0x00; 16];
+//! let ciphertext = CipherBlockChaining::<AES128, PKCS7>::new(iv, key).stream_encrypt(b"hello")?;
+//! let plaintext = CipherBlockChaining::<AES128, PKCS7>::new(iv, key).stream_decrypt(&ciphertext)?;
+//! assert_eq!(plaintext, b"hello");
+//! # }
+//! # Ok::<(), aes::error::Error>(())
+//! ```
+//!
+//! # Features
+//!
+//! - `alloc` enables the helpers returning a `Vec`, such as
+//!   [`stream::Streamer::stream_encrypt`].
+//! - `std` (default) additionally enables [`cipher`], [`container`],
+//!   [`encoding`], [`keystore`], [`openssl`], [`padding::ISO10126`],
+//!   [`stream::ParallelEngine`], the [`std::io`] adapters such as
+//!   [`stream::EncryptWriter`] and the `aes` binary.
+//!
+//! Without either the crate is `no_std`: the block cipher and the modes
+//! work on caller-provided buffers through the in-place slice APIs, such as
+//! [`stream::Streamer::encrypt_padded_in_place`].
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+pub mod aes;
+#[cfg(feature = "std")]
+pub mod cipher;
+#[cfg(feature = "std")]
+pub mod container;
+pub mod digest;
+#[cfg(feature = "std")]
+pub mod encoding;
+pub mod error;
+pub mod kcv;
+pub mod kdf;
+#[cfg(feature = "std")]
+pub mod keystore;
+pub mod keywrap;
+pub mod mac;
+#[cfg(feature = "std")]
+pub mod openssl;
+pub mod padding;
+pub mod secret;
+pub mod siv;
+pub mod stream;
+
+const fn max(a: usize, b: usize) -> usize {
+    [a, b][(a < b) as usize]
+}
@@ -0,0 +1,132 @@
+//! Message authentication codes built on the block cipher
+
+use crate::aes::{Array, BlockCipher, Rijndael, RijndaelMode};
+use crate::secret::{xor, zeroize};
+
+/// CMAC (NIST SP 800-38B, RFC 4493) of a message handed over in chunks
+pub struct Cmac<M: RijndaelMode> {
+    cipher: Rijndael<M>,
+    /// the subkeys for a complete and an incomplete last block
+    k1: M::Block,
+    k2: M::Block,
+    state: M::Block,
+    /// the last block, held back until [`Self::finalize`] knows it is last
+    buf: M::Block,
+    len: usize,
+}
+
+impl<M: RijndaelMode> Drop for Cmac<M> {
+    fn drop(&mut self) {
+        // the subkeys are derived from the key, and the buffers hold the
+        // message
+        zeroize(self.k1.as_mut());
+        zeroize(self.k2.as_mut());
+        zeroize(self.state.as_mut());
+        zeroize(self.buf.as_mut());
+    }
+}
+
+impl<M: RijndaelMode> Cmac<M> {
+    pub fn new(key: &M::Key) -> Self {
+        let cipher = Rijndael::<M>::new(key);
+        let mut l = M::Block::zeroed();
+        cipher.encrypt_block(&mut l);
+        let k1 = double::<M>(&l);
+        let k2 = double::<M>(&k1);
+        Self {
+            cipher,
+            k1,
+            k2,
+            state: M::Block::zeroed(),
+            buf: M::Block::zeroed(),
+            len: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        let bs = M::NB_WORDS * 4;
+        while !data.is_empty() {
+            if self.len == bs {
+                xor(self.state.as_mut(), self.buf.as_ref());
+                self.cipher.encrypt_block(&mut self.state);
+                self.len = 0;
+            }
+            let n = (bs - self.len).min(data.len());
+            self.buf.as_mut()[self.len..self.len + n].copy_from_slice(&data[..n]);
+            self.len += n;
+            data = &data[n..];
+        }
+    }
+
+    pub fn finalize(mut self) -> M::Block {
+        let bs = M::NB_WORDS * 4;
+        if self.len == bs {
+            xor(self.buf.as_mut(), self.k1.as_ref());
+        } else {
+            self.buf.as_mut()[self.len] = 0x80;
+            self.buf.as_mut()[self.len + 1..]
+                .iter_mut()
+                .for_each(|b| *b = 0);
+            xor(self.buf.as_mut(), self.k2.as_ref());
+        }
+        xor(self.state.as_mut(), self.buf.as_ref());
+        self.cipher.encrypt_block(&mut self.state);
+        self.state
+    }
+
+    /// The CMAC of `data` under `key`.
+    pub fn mac(key: &M::Key, data: &[u8]) -> M::Block {
+        let mut cmac = Self::new(key);
+        cmac.update(data);
+        cmac.finalize()
+    }
+}
+
+/// Doubles `block` in GF(2^128), the subkey derivation of CMAC
+pub(crate) fn double<M: RijndaelMode>(block: &M::Block) -> M::Block {
+    let mut out = M::Block::zeroed();
+    let b = block.as_ref();
+    for (i, o) in out.as_mut().iter_mut().enumerate() {
+        *o = b[i] << 1 | b.get(i + 1).map_or(0, |next| next >> 7);
+    }
+    // reduce by x^128 + x^7 + x^2 + x + 1 if the top bit was shifted out
+    let last = out.as_ref().len() - 1;
+    out.as_mut()[last] ^= 0x87 & 0u8.wrapping_sub(b[0] >> 7);
+    out
+}
+
+#[cfg(test)]
+#[test]
+fn test_cmac() {
+    use crate::aes::AES128;
+    use std::convert::TryInto;
+
+    // the following test cases are from RFC 4493
+    let key = hex::decode("2b7e151628aed2a6abf7158809cf4f3c").unwrap();
+    let key = key.as_slice().try_into().unwrap();
+    let message = hex::decode(
+        "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51\
+         30c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710",
+    )
+    .unwrap();
+    let cmac = Cmac::<AES128>::new(key);
+    assert_eq!(hex::encode(cmac.k1), "fbeed618357133667c85e08f7236a8de");
+    assert_eq!(hex::encode(cmac.k2), "f7ddac306ae266ccf90bc11ee46d513b");
+    for (len, expected) in [
+        (0, "bb1d6929e95937287fa37d129b756746"),
+        (16, "070a16b46b4d4144f79bdd9dd04a287c"),
+        (40, "dfa66747de9ae63030ca32611497c827"),
+        (64, "51f0bebf7e3b9d92fc49741779363cfe"),
+    ] {
+        assert_eq!(
+            hex::encode(Cmac::<AES128>::mac(key, &message[..len])),
+            expected
+        );
+        // in chunks that do not line up with the blocks
+        let mut cmac = Cmac::<AES128>::new(key);
+        for chunk in message[..len].chunks(7) {
+            cmac.update(chunk);
+        }
+        assert_eq!(hex::encode(cmac.finalize()), expected);
+    }
+}
@@ -1,55 +1,409 @@
-#![allow(incomplete_features)]
-#![feature(generic_const_exprs)]
-#![feature(associated_type_defaults)]
-
 //! https://songlee24.github.io/2014/12/13/aes-encrypt/
 
+use aes::{
+    cipher::CipherSpec,
+    container::Header,
+    encoding::{DecodeReader, EncodeWriter, Encoding},
+    kdf::Kdf,
+    keystore::{Entry, Keystore},
+    openssl,
+    secret::SecretKey,
+};
+use anyhow::{anyhow, bail};
+use rand::{rngs::OsRng, RngCore};
 use std::{
-    fs::File,
-    io::{stdin, stdout, Read, Write},
+    convert::TryInto,
+    fs::{self, File, OpenOptions},
+    io::{self, stdin, stdout, Cursor, ErrorKind, Read, Write},
+    path::Path,
 };
 
-mod aes;
+mod commands;
 mod opt;
-mod padding;
-mod stream;
+mod recursive;
+mod reencrypt;
+mod source;
+mod tree;
 
-const fn max(a: usize, b: usize) -> usize {
-    [a, b][(a < b) as usize]
-}
+/// size of the chunks the input is read and processed in
+const CHUNK: usize = 1 << 20;
+
+/// length of the salt of `--passphrase`
+const SALT_LEN: usize = 16;
+
+/// the output, in the encoding it is written in
+type Output = EncodeWriter<Box<dyn Write>>;
 
 fn main() -> anyhow::Result<()> {
     let opts = opt::Opts::parse();
+    match &opts.command {
+        Some(opt::Command::Keygen(args)) => return commands::keygen(args),
+        Some(opt::Command::Kcv(args)) => return commands::kcv(args),
+        Some(opt::Command::Key(args)) => return commands::key(args),
+        Some(opt::Command::Reencrypt(args)) => return reencrypt::reencrypt(args),
+        None => {}
+    }
+    if opts.list_ciphers {
+        for spec in CipherSpec::all() {
+            println!("{}", spec);
+        }
+        return Ok(());
+    }
+
+    if opts.recursive {
+        return recursive::recursive(&opts);
+    }
+
+    let input: Box<dyn Read> = if opts.input != "-" {
+        Box::new(File::open(&opts.input)?)
+    } else {
+        Box::new(stdin())
+    };
+    if opts.output == "-" {
+        return process(&opts, input, Box::new(stdout()));
+    }
+    let output = Path::new(&opts.output);
+    if opts.input != "-" && tree::same_file(Path::new(&opts.input), output) {
+        bail!("The output is the input file, which would be overwritten while it is read");
+    }
+    match fs::metadata(output) {
+        // devices and pipes are written to as they are
+        Ok(metadata) if !metadata.is_file() => {
+            let file = OpenOptions::new().write(true).open(output)?;
+            process(&opts, input, Box::new(file))
+        }
+        // files are only replaced once complete, so a failure leaves them as they were
+        _ => tree::replace(output, |file| {
+            process(&opts, input, Box::new(file.try_clone()?))
+        }),
+    }
+}
 
-    let mut input = Vec::new();
-    if opts.input != "-" {
-        File::open(&opts.input)?.read_to_end(&mut input)?;
+/// Encrypts or decrypts `input` into `output`.
+fn process(
+    opts: &opt::Opts,
+    mut input: Box<dyn Read>,
+    output: Box<dyn Write>,
+) -> anyhow::Result<()> {
+    // `--hex` applies to both the plaintext and the ciphertext, `--encoding`
+    // only to the ciphertext
+    let plain_encoding = match opts.hex {
+        true => Encoding::Hex,
+        false => Encoding::Binary,
+    };
+
+    let (spec, key, iv, mut output) = if opts.is_encrypt() {
+        if opts.hex {
+            input = Box::new(DecodeReader::new(input, Encoding::Hex)?);
+        }
+        write_header(opts, output)?
     } else {
-        stdin().read_to_end(&mut input)?;
+        let output = EncodeWriter::new(output, plain_encoding, &[])?;
+        let (armor, decoded): (_, Box<dyn Read>) = match opts.encoding() {
+            Some(Encoding::Binary) => (Vec::new(), input),
+            Some(encoding) => {
+                let r = DecodeReader::new(input, encoding)?;
+                (r.headers().to_vec(), Box::new(r))
+            }
+            None => {
+                let r = DecodeReader::detect(input)?;
+                (r.headers().to_vec(), Box::new(r))
+            }
+        };
+        input = decoded;
+        let (spec, key, iv) = read_header(opts, &mut input, &armor)?;
+        (spec, key, iv, output)
+    };
+    let mut cipher = spec.build(&key, &iv)?;
+    cipher.set_threads(opts.threads);
+
+    let mut chunk = vec![0; CHUNK];
+    let mut result = Vec::with_capacity(CHUNK + 32);
+    loop {
+        let n = match input.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        result.clear();
+        if opts.is_encrypt() {
+            cipher.encrypt_update(&chunk[..n], &mut result);
+        } else {
+            assert!(opts.is_decrypt());
+            cipher.decrypt_update(&chunk[..n], &mut result);
+        }
+        output.write_all(&result)?;
     }
-    if opts.hex {
-        input = hex::decode(String::from_utf8(input)?.trim())?;
+
+    result.clear();
+    if opts.is_encrypt() {
+        cipher.encrypt_finalize(&mut result)?;
+    } else {
+        cipher.decrypt_finalize(&mut result)?;
     }
+    output.write_all(&result)?;
+    output.finish()?;
+
+    Ok(())
+}
 
-    let key = hex::decode(&opts.key)?;
-    let iv = hex::decode(&opts.iv)?;
+/// Picks the cipher, key and IV to encrypt with, and starts the output with
+/// what the decryption needs to know about them: a container header, armor
+/// headers, the `openssl enc` header, or with `--raw` a generated IV.
+fn write_header(
+    opts: &opt::Opts,
+    output: Box<dyn Write>,
+) -> anyhow::Result<(CipherSpec, SecretKey, Vec<u8>, Output)> {
+    let entry = match &opts.key_name {
+        Some(name) => Some(active_key(opts, name)?),
+        None => None,
+    };
+    let spec = opts
+        .mode
+        .or_else(|| entry.as_ref().map(|e| e.spec))
+        .ok_or_else(|| anyhow!("A cipher spec is required for encryption"))?;
+    let encoding = opts.encoding().unwrap_or(match opts.hex {
+        true => Encoding::Hex,
+        false => Encoding::Binary,
+    });
+    if opts.openssl {
+        let salt = match &opts.salt {
+            Some(salt) => hex::decode(salt)?
+                .try_into()
+                .map_err(|_| anyhow!("The salt must be {} bytes", openssl::SALT_LEN))?,
+            None => openssl::random_salt(),
+        };
+        let mut output = EncodeWriter::new(output, encoding, &[])?;
+        output.write_all(&openssl::header(&salt))?;
+        let (key, iv) = openssl_derive(opts, &spec, &salt)?;
+        return Ok((spec, key, iv, output));
+    }
 
-    let mut cipher = opts.mode.get_cipher(&key, &iv);
-    let mut result = if opts.is_encrypt() {
-        cipher.encrypt(&input)
+    let iv_source = opts.iv_source()?;
+    let iv = match &iv_source {
+        None => spec.random_iv(),
+        Some(source) => source.read("IV", &spec, spec.iv_len())?.to_vec(),
+    };
+    let mut header = Header::new(spec, iv.clone());
+    let key = match opts.passphrase(true)? {
+        Some(pass) => {
+            if opts.key.source()?.is_some() || entry.is_some() {
+                bail!("Either a key, a key name or a passphrase may be given");
+            }
+            if opts.raw {
+                bail!("The key derivation of --passphrase is stored in the container");
+            }
+            let kdf = opts.kdf()?;
+            let mut salt = vec![0; SALT_LEN];
+            OsRng.fill_bytes(&mut salt);
+            let key = derive_key(&pass, &spec, &kdf, &salt)?;
+            header.salt = Some(salt);
+            header.kdf = Some(kdf.to_bytes());
+            key
+        }
+        None => match entry {
+            Some(entry) => {
+                if opts.key.source()?.is_some() {
+                    bail!("Either a key, a key name or a passphrase may be given");
+                }
+                check_key(&entry, &spec)?;
+                header.key_id = Some(entry.id.to_vec());
+                entry.key
+            }
+            None => opts.read_key(Some(&spec))?,
+        },
+    };
+    let output = if opts.raw {
+        let mut output = EncodeWriter::new(output, encoding, &[])?;
+        if iv_source.is_none() {
+            output.write_all(&iv)?;
+        }
+        output
+    } else if encoding == Encoding::Armor {
+        EncodeWriter::new(output, encoding, &header.to_armor_headers())?
     } else {
-        assert!(opts.is_decrypt());
-        cipher.decrypt(&input)
+        let mut output = EncodeWriter::new(output, encoding, &[])?;
+        output.write_all(&header.to_bytes())?;
+        output
     };
+    Ok((spec, key, iv, output))
+}
 
-    if opts.hex {
-        result = hex::encode(&result).as_bytes().to_vec();
+/// Reads the cipher and IV to decrypt with from the `armor` headers or the
+/// container header at the start of `input`, or takes them from the options
+/// for other input, and picks the key.
+fn read_header(
+    opts: &opt::Opts,
+    input: &mut Box<dyn Read>,
+    armor: &[(String, String)],
+) -> anyhow::Result<(CipherSpec, SecretKey, Vec<u8>)> {
+    if opts.openssl {
+        let spec = opts
+            .mode
+            .ok_or_else(|| anyhow!("A cipher spec is required with --openssl"))?;
+        let salt = openssl::read_header(input)?;
+        let (key, iv) = openssl_derive(opts, &spec, &salt)?;
+        return Ok((spec, key, iv));
     }
-    if opts.output != "-" {
-        File::create(&opts.output)?.write_all(&result)?;
+
+    let iv_source = opts.iv_source()?;
+    let mut magic = Vec::new();
+    if !opts.raw && armor.is_empty() {
+        input.take(4).read_to_end(&mut magic)?;
+    }
+    let header = if !armor.is_empty() {
+        Some(Header::from_armor_headers(armor)?)
+    } else if Header::detect(&magic) {
+        Some(Header::read_from((&magic[..]).chain(&mut *input))?)
     } else {
-        stdout().write_all(&result)?;
+        None
+    };
+    if let Some(header) = header {
+        match opts.mode {
+            Some(spec) if spec != header.spec => {
+                bail!(
+                    "The container was encrypted with {}, not {}",
+                    header.spec,
+                    spec
+                )
+            }
+            _ => {}
+        }
+        if iv_source.is_some() {
+            bail!("The container carries its own IV");
+        }
+        if header.tag_len > 0 {
+            bail!("Authenticated containers are not supported");
+        }
+        let pass = opts.passphrase(false)?;
+        let key = match (&header.salt, &header.kdf, pass) {
+            (None, None, None) => decryption_key(opts, &header.spec, header.key_id.as_deref())?,
+            (None, None, Some(_)) => bail!("The container is encrypted with a key"),
+            (Some(salt), Some(kdf), Some(pass)) => {
+                if opts.key.source()?.is_some() || opts.key_name.is_some() {
+                    bail!("Either a key, a key name or a passphrase may be given");
+                }
+                derive_key(&pass, &header.spec, &Kdf::from_bytes(kdf)?, salt)?
+            }
+            (Some(_), Some(_), None) => {
+                bail!("The container is encrypted with a passphrase, see --passphrase")
+            }
+            _ => bail!("The container has a salt or key derivation, but not both"),
+        };
+        return Ok((header.spec, key, header.iv));
+    }
+
+    // not a container, so the bytes read are ciphertext
+    let rest = std::mem::replace(input, Box::new(io::empty()));
+    *input = Box::new(Cursor::new(magic).chain(rest));
+    let spec = opts
+        .mode
+        .ok_or_else(|| anyhow!("A cipher spec is required unless decrypting a container"))?;
+    if opts.passphrase(false)?.is_some() {
+        bail!("The key derivation of --passphrase is read from a container");
+    }
+    let key = decryption_key(opts, &spec, None)?;
+    let iv = match iv_source {
+        None => {
+            let mut iv = vec![0; spec.iv_len()];
+            input.read_exact(&mut iv).map_err(|e| match e.kind() {
+                ErrorKind::UnexpectedEof => anyhow!("Input is shorter than the IV"),
+                _ => e.into(),
+            })?;
+            iv
+        }
+        Some(source) => source.read("IV", &spec, spec.iv_len())?.to_vec(),
+    };
+    Ok((spec, key, iv))
+}
+
+/// Derives the key and IV from the passphrase and the salt of the
+/// `openssl enc` header.
+fn openssl_derive(
+    opts: &opt::Opts,
+    spec: &CipherSpec,
+    salt: &[u8],
+) -> anyhow::Result<(SecretKey, Vec<u8>)> {
+    let pass = opts
+        .passphrase(opts.is_encrypt())?
+        .ok_or_else(|| anyhow!("A passphrase is required with --openssl"))?;
+    if opts.key.source()?.is_some() || opts.key_name.is_some() || opts.iv_source()?.is_some() {
+        bail!("The key and IV are derived from the passphrase with --openssl");
     }
+    let (key, iv) = opts.key_derivation().derive(spec, &pass, salt);
+    Ok((SecretKey::new(key), iv))
+}
+
+/// Derives the key of `spec` from the passphrase `pass` and `salt`.
+fn derive_key(pass: &[u8], spec: &CipherSpec, kdf: &Kdf, salt: &[u8]) -> anyhow::Result<SecretKey> {
+    let mut key = SecretKey::new(vec![0; spec.key_len()]);
+    kdf.derive(pass, salt, &mut key)?;
+    Ok(key)
+}
 
+/// The active version of the keystore key `name`.
+fn active_key(opts: &opt::Opts, name: &str) -> anyhow::Result<Entry> {
+    let entry = keystore(opts)?
+        .active(name)
+        .ok_or_else(|| anyhow!("There is no active key named {}", name))?;
+    Ok(entry.clone())
+}
+
+/// The keystore, opened on first use.
+fn keystore(opts: &opt::Opts) -> anyhow::Result<&Keystore> {
+    if opts.cache.keystore.get().is_none() {
+        let _ = opts
+            .cache
+            .keystore
+            .set(commands::open_keystore(&opts.keystore)?);
+    }
+    Ok(opts.cache.keystore.get().unwrap())
+}
+
+/// The key to decrypt `spec` with, given with the `--key` options, or taken
+/// from the keystore by the `key_id` a container records or by `--key-name`.
+fn decryption_key(
+    opts: &opt::Opts,
+    spec: &CipherSpec,
+    key_id: Option<&[u8]>,
+) -> anyhow::Result<SecretKey> {
+    if opts.key.source()?.is_some() {
+        if opts.key_name.is_some() {
+            bail!("Either a key or a key name may be given");
+        }
+        return opts.read_key(Some(spec));
+    }
+    let entry = match (key_id, &opts.key_name) {
+        (None, None) => return opts.read_key(Some(spec)),
+        (None, Some(name)) => active_key(opts, name)?,
+        (Some(id), name) => {
+            let entry = keystore(opts)?
+                .get(id)
+                .ok_or_else(|| anyhow!("The key {} is not in the keystore", hex::encode(id)))?;
+            match name {
+                Some(name) if entry.name != *name => bail!(
+                    "The container was encrypted with the key {}, not {}",
+                    entry.name,
+                    name
+                ),
+                _ => entry.clone(),
+            }
+        }
+    };
+    check_key(&entry, spec)?;
+    Ok(entry.key)
+}
+
+/// Checks the keystore key `entry` suits `spec`.
+fn check_key(entry: &Entry, spec: &CipherSpec) -> anyhow::Result<()> {
+    if entry.key.len() != spec.key_len() {
+        bail!(
+            "The key {} is for {}, and cannot be used for {}",
+            entry.name,
+            entry.spec,
+            spec
+        );
+    }
     Ok(())
 }
@@ -0,0 +1,132 @@
+//! Files of `openssl enc`: the ciphertext preceded by `Salted__` and a salt,
+//! with the key and IV derived from a passphrase and the salt.
+
+use std::io::{self, Read};
+use std::str::FromStr;
+
+use rand::RngCore;
+
+use crate::cipher::CipherSpec;
+use crate::digest::{Md5, Sha256};
+use crate::error::Error;
+use crate::kdf::{evp_bytes_to_key, pbkdf2_hmac};
+
+/// the bytes every salted file starts with
+pub const MAGIC: [u8; 8] = *b"Salted__";
+/// length of the salt following [`MAGIC`]
+pub const SALT_LEN: usize = 8;
+/// iterations of PBKDF2 used by `openssl enc -pbkdf2` without `-iter`
+pub const DEFAULT_ITERATIONS: u32 = 10000;
+
+/// digest of the key derivation, `-md` of `openssl enc`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageDigest {
+    Md5,
+    Sha256,
+}
+
+impl FromStr for MessageDigest {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "md5" => Ok(Self::Md5),
+            "sha256" => Ok(Self::Sha256),
+            _ => Err(format!(
+                "unsupported digest `{}`, expecting one of md5, sha256",
+                s
+            )),
+        }
+    }
+}
+
+/// How `openssl enc` derives the key and IV from the passphrase
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyDerivation {
+    /// `EVP_BytesToKey` with a single iteration, the default
+    BytesToKey(MessageDigest),
+    /// PBKDF2, with `-pbkdf2` or `-iter`
+    Pbkdf2 {
+        digest: MessageDigest,
+        iterations: u32,
+    },
+}
+
+impl KeyDerivation {
+    /// Derives the key and IV of `spec`.
+    pub fn derive(&self, spec: &CipherSpec, pass: &[u8], salt: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut out = vec![0; spec.key_len() + spec.iv_len()];
+        match *self {
+            KeyDerivation::BytesToKey(MessageDigest::Md5) => {
+                evp_bytes_to_key::<Md5>(pass, salt, 1, &mut out)
+            }
+            KeyDerivation::BytesToKey(MessageDigest::Sha256) => {
+                evp_bytes_to_key::<Sha256>(pass, salt, 1, &mut out)
+            }
+            KeyDerivation::Pbkdf2 { digest, iterations } => match digest {
+                MessageDigest::Md5 => pbkdf2_hmac::<Md5>(pass, salt, iterations, &mut out),
+                MessageDigest::Sha256 => pbkdf2_hmac::<Sha256>(pass, salt, iterations, &mut out),
+            },
+        }
+        let iv = out.split_off(spec.key_len());
+        (out, iv)
+    }
+}
+
+/// Generates a salt from the operating system CSPRNG.
+pub fn random_salt() -> [u8; SALT_LEN] {
+    let mut salt = [0; SALT_LEN];
+    rand::rngs::OsRng.fill_bytes(&mut salt);
+    salt
+}
+
+/// The header of a file with `salt`.
+pub fn header(salt: &[u8; SALT_LEN]) -> [u8; MAGIC.len() + SALT_LEN] {
+    let mut header = [0; MAGIC.len() + SALT_LEN];
+    header[..MAGIC.len()].copy_from_slice(&MAGIC);
+    header[MAGIC.len()..].copy_from_slice(salt);
+    header
+}
+
+/// Reads the header of a file, returning the salt and leaving `r` at the
+/// start of the ciphertext.
+pub fn read_header<R: Read>(mut r: R) -> io::Result<[u8; SALT_LEN]> {
+    let mut header = [0; MAGIC.len() + SALT_LEN];
+    r.read_exact(&mut header)?;
+    if header[..MAGIC.len()] != MAGIC {
+        return Err(Error::InvalidHeader("missing `Salted__`").into());
+    }
+    let mut salt = [0; SALT_LEN];
+    salt.copy_from_slice(&header[MAGIC.len()..]);
+    Ok(salt)
+}
+
+#[cfg(test)]
+#[test]
+fn test_derive() {
+    let salt = [1, 2, 3, 4, 5, 6, 7, 8];
+    let ecb = "aes-192-ecb".parse::<CipherSpec>().unwrap();
+    let kdf = KeyDerivation::Pbkdf2 {
+        digest: MessageDigest::Md5,
+        iterations: 1000,
+    };
+    let (key, iv) = kdf.derive(&ecb, b"hunter2", &salt);
+    // `openssl enc -P -aes-192-ecb -pbkdf2 -iter 1000 -md md5 -pass pass:hunter2 -S 0102030405060708`
+    assert_eq!(
+        hex::encode(key),
+        "87bafbf7bc07530b527075fdbe0f91ec08ba6a9f43479911"
+    );
+    assert!(iv.is_empty());
+
+    let cbc = "aes-128-cbc".parse::<CipherSpec>().unwrap();
+    let (key, iv) =
+        KeyDerivation::BytesToKey(MessageDigest::Sha256).derive(&cbc, b"hunter2", &salt);
+    assert_eq!(hex::encode(key), "dfe64dd966c53cabb5e8cea39e74d4a4");
+    assert_eq!(hex::encode(iv), "f8bc3d6a82a7a3a823417d707ebe109c");
+
+    let header = header(&salt);
+    assert_eq!(&header[..], b"Salted__\x01\x02\x03\x04\x05\x06\x07\x08");
+    assert_eq!(read_header(&header[..]).unwrap(), salt);
+    assert!(read_header(&b"Salted_!\x01\x02\x03\x04\x05\x06\x07\x08"[..]).is_err());
+    assert_ne!(random_salt(), random_salt());
+}
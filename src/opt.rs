@@ -1,17 +1,19 @@
-use std::str::FromStr;
+use aes::cipher::CipherSpec;
+use aes::encoding::{Base64, Encoding};
+use aes::kdf::Kdf;
+use aes::keystore::{Keystore, Unlock};
+use aes::openssl::{KeyDerivation, MessageDigest, DEFAULT_ITERATIONS};
+use aes::secret::SecretKey;
+use anyhow::{anyhow, bail};
+use clap::{crate_authors, crate_version, Args, Parser, Subcommand};
+use std::cell::OnceCell;
 
-use crate::padding::{ISO10126, PKCS7, X923};
-use crate::stream::{CipherBlockChaining, Streamer};
-use crate::{
-    aes::{AES128, AES192, AES256},
-    stream::ElectronicCodeBook,
-};
-use clap::{crate_authors, crate_version, Parser};
+use crate::source::Source;
 
 /// This doc string acts as a help message when the user runs '--help'
 /// as do all doc strings on fields
 #[derive(Parser, Debug)]
-#[clap(version = crate_version!(), author = crate_authors!())]
+#[clap(version = crate_version!(), author = crate_authors!(), subcommand_negates_reqs = true)]
 pub struct Opts {
     /// Input file name, use a `-` for standard input
     #[clap(default_value = "-")]
@@ -19,24 +21,313 @@ pub struct Opts {
     /// Output file name, use a `-` for standard output
     #[clap(default_value = "-")]
     pub output: String,
-    /// Key in hex format.
-    #[clap(short, long, default_value = "-")]
-    pub key: String,
-    /// IV in hex format.
+    #[clap(flatten)]
+    pub key: KeyOpts,
+    /// Encrypts with the active version of the named keystore key, and
+    /// checks a container is encrypted with a version of it on decryption.
+    #[clap(long, value_name = "NAME")]
+    pub key_name: Option<String>,
+    #[clap(flatten)]
+    pub keystore: KeystoreOpts,
+    /// IV in hex format. Without one, encryption generates a random IV and
+    /// stores it in the container, or with `--raw` ahead of the ciphertext,
+    /// and decryption reads it from there. ECB takes no IV.
     #[clap(short, long, default_value = "-")]
     pub iv: String,
+    /// Reads the IV from a file, raw, in hex or in Base64.
+    #[clap(long, value_name = "PATH")]
+    pub iv_file: Option<String>,
+    /// Reads the IV from an environment variable, in hex or in Base64.
+    #[clap(long, value_name = "VAR")]
+    pub iv_env: Option<String>,
+    /// Reads the IV from an inherited file descriptor, like `--iv-file`.
+    #[clap(long, value_name = "N")]
+    pub iv_fd: Option<i32>,
+    /// Asks for the IV on the terminal, in hex or in Base64.
+    #[clap(long)]
+    pub iv_prompt: bool,
     /// Indicates input and output are hex strings.
     #[clap(short = 'a', long)]
     pub hex: bool,
+    /// Encoding of the ciphertext, `binary`, `hex`, `base64`, `base64url` or
+    /// `armor`. Decryption detects it by default.
+    #[clap(short, long, conflicts_with = "hex")]
+    pub encoding: Option<Encoding>,
+    /// Line length of Base64 output, `0` for a single line.
+    #[clap(long, default_value = "64")]
+    pub wrap: usize,
+    /// Leaves out the `=` padding of Base64 output.
+    #[clap(long)]
+    pub no_pad: bool,
     /// Log verbosity. May be used multiple times.
     #[clap(short, long, parse(from_occurrences))]
     pub verbose: i32,
     /// Operation mode, `encrypt` or `decrypt`
+    #[clap(short, long, required_unless_present = "list-ciphers")]
+    pub op: Option<Operation>,
+    /// Cipher spec, `<algo>-<keybits>-<mode>[-<padding>]`, such as `aes-128-cbc`.
+    /// Decrypting a container reads it from the container instead.
     #[clap(short, long)]
-    pub op: Operation,
-    /// AES / Rijndael Modes
+    pub mode: Option<CipherSpec>,
+    /// Writes the bare ciphertext instead of a container, and decrypts input
+    /// without looking for one.
+    #[clap(long)]
+    pub raw: bool,
+    /// Encrypts or decrypts every file in the input directory into a mirrored
+    /// tree in the output directory, keeping their permissions and
+    /// modification times.
     #[clap(short, long)]
-    pub mode: Cipherset,
+    pub recursive: bool,
+    /// Encrypts the names of the files and directories too with
+    /// `--recursive`, deterministically with AES-SIV under a key derived from
+    /// the key.
+    #[clap(long, requires = "recursive")]
+    pub encrypt_names: bool,
+    /// Prints every supported cipher spec and exits.
+    #[clap(long)]
+    pub list_ciphers: bool,
+    /// Reads and writes the `Salted__` format of `openssl enc`, with the key
+    /// and IV derived from `--passphrase`.
+    #[clap(long)]
+    pub openssl: bool,
+    /// Passphrase to derive the key from with `--kdf`, or the key and IV
+    /// with `--openssl`.
+    #[clap(long)]
+    pub passphrase: Option<String>,
+    /// Reads the passphrase from the first line of a file.
+    #[clap(long, value_name = "PATH")]
+    pub passphrase_file: Option<String>,
+    /// Reads the passphrase from an environment variable.
+    #[clap(long, value_name = "VAR")]
+    pub passphrase_env: Option<String>,
+    /// Reads the passphrase from an inherited file descriptor, like
+    /// `--passphrase-file`.
+    #[clap(long, value_name = "N")]
+    pub passphrase_fd: Option<i32>,
+    /// Asks for the passphrase on the terminal.
+    #[clap(long)]
+    pub passphrase_prompt: bool,
+    /// Key derivation of the passphrase, `scrypt` or `pbkdf2`. Decryption
+    /// reads it from the container.
+    #[clap(long, default_value = "scrypt")]
+    pub kdf: Kdf,
+    /// Cost of the key derivation, the base 2 logarithm of the scrypt
+    /// `N` [default: 17] or the PBKDF2 iterations [default: 600000].
+    #[clap(long, value_name = "COST")]
+    pub kdf_cost: Option<u32>,
+    /// Digest of the `--openssl` key derivation, `md5` or `sha256`.
+    #[clap(long, default_value = "sha256")]
+    pub md: MessageDigest,
+    /// Derives the `--openssl` key and IV with PBKDF2 instead of
+    /// `EVP_BytesToKey`.
+    #[clap(long)]
+    pub pbkdf2: bool,
+    /// Iterations of PBKDF2, implies `--pbkdf2` [default: 10000]
+    #[clap(long)]
+    pub iter: Option<u32>,
+    /// Salt of the `--openssl` key derivation in hex format, random by
+    /// default.
+    #[clap(long)]
+    pub salt: Option<String>,
+    /// Number of threads for the parallelisable modes, `0` for one per core.
+    #[clap(short, long, default_value = "1")]
+    pub threads: usize,
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+    #[clap(skip)]
+    pub cache: Cache,
+}
+
+/// The key, passphrase and keystore once read, as `--recursive` uses them for
+/// every file but may only read them once
+#[derive(Debug, Default)]
+pub struct Cache {
+    pub key: OnceCell<SecretKey>,
+    pub passphrase: OnceCell<SecretKey>,
+    pub keystore: OnceCell<Keystore>,
+}
+
+/// Where the key is read from
+#[derive(Args, Debug)]
+pub struct KeyOpts {
+    /// Key in hex format.
+    #[clap(short, long, default_value = "-")]
+    pub key: String,
+    /// Reads the key from a file, raw, in hex or in Base64.
+    #[clap(long, value_name = "PATH")]
+    pub key_file: Option<String>,
+    /// Reads the key from an environment variable, in hex or in Base64.
+    #[clap(long, value_name = "VAR")]
+    pub key_env: Option<String>,
+    /// Reads the key from an inherited file descriptor, like `--key-file`.
+    #[clap(long, value_name = "N")]
+    pub key_fd: Option<i32>,
+    /// Asks for the key on the terminal, in hex or in Base64.
+    #[clap(long)]
+    pub key_prompt: bool,
+}
+
+/// Where the keystore is, and what unlocks it
+#[derive(Args, Debug)]
+pub struct KeystoreOpts {
+    /// Keystore file, `$AES_KEYSTORE` by default.
+    #[clap(long, value_name = "PATH")]
+    pub keystore: Option<String>,
+    /// Master key of the keystore in hex format.
+    #[clap(long)]
+    pub master_key: Option<String>,
+    /// Reads the master key from a file, raw, in hex or in Base64.
+    #[clap(long, value_name = "PATH")]
+    pub master_key_file: Option<String>,
+    /// Reads the master key from an environment variable, in hex or in
+    /// Base64.
+    #[clap(long, value_name = "VAR")]
+    pub master_key_env: Option<String>,
+    /// Reads the master key from an inherited file descriptor, like
+    /// `--master-key-file`.
+    #[clap(long, value_name = "N")]
+    pub master_key_fd: Option<i32>,
+    /// Asks for the master key on the terminal, in hex or in Base64.
+    #[clap(long)]
+    pub master_key_prompt: bool,
+    /// Passphrase of the keystore, instead of a master key.
+    #[clap(long)]
+    pub keystore_passphrase: Option<String>,
+    /// Reads the keystore passphrase from the first line of a file.
+    #[clap(long, value_name = "PATH")]
+    pub keystore_passphrase_file: Option<String>,
+    /// Reads the keystore passphrase from an environment variable.
+    #[clap(long, value_name = "VAR")]
+    pub keystore_passphrase_env: Option<String>,
+    /// Reads the keystore passphrase from an inherited file descriptor,
+    /// like `--keystore-passphrase-file`.
+    #[clap(long, value_name = "N")]
+    pub keystore_passphrase_fd: Option<i32>,
+    /// Asks for the keystore passphrase on the terminal.
+    #[clap(long)]
+    pub keystore_passphrase_prompt: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Generates a random key, and optionally an IV, from the operating
+    /// system CSPRNG.
+    Keygen(Keygen),
+    /// Prints the check values of a key, to verify it was entered correctly.
+    Kcv(Kcv),
+    /// Manages the named keys of a keystore.
+    Key(Box<KeyCommand>),
+    /// Re-encrypts containers with a new key or cipher spec in place, in a
+    /// single pass which keeps the plaintext off the disk.
+    Reencrypt(Box<Reencrypt>),
+}
+
+#[derive(Args, Debug)]
+pub struct Keygen {
+    /// Cipher spec to generate the key for.
+    #[clap(short, long, default_value = "aes-256-cbc")]
+    pub mode: CipherSpec,
+    /// Generates an IV too, printed on a line of its own or written after
+    /// the raw key.
+    #[clap(long)]
+    pub iv: bool,
+    /// Format of the output, `hex`, `base64` or `raw`.
+    #[clap(short, long, default_value = "hex")]
+    pub format: KeyFormat,
+    /// Output file name, use a `-` for standard output. Files, existing ones
+    /// included, are made readable by their owner only.
+    #[clap(default_value = "-")]
+    pub output: String,
+}
+
+#[derive(Args, Debug)]
+pub struct Kcv {
+    #[clap(flatten)]
+    pub key: KeyOpts,
+}
+
+#[derive(Args, Debug)]
+pub struct KeyCommand {
+    #[clap(subcommand)]
+    pub action: KeyAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum KeyAction {
+    /// Adds a key, random unless given with the `--key` options, creating
+    /// the keystore if it does not exist.
+    Add(KeyAdd),
+    /// Lists every version of every key.
+    List(KeyList),
+    /// Adds a new version of a key, random unless given, and retires the
+    /// one before it.
+    Rotate(KeyRotate),
+    /// Retires the active version of a key, which then only decrypts.
+    Retire(KeyRetire),
+    /// Writes a key out of the keystore.
+    Export(KeyExport),
+}
+
+#[derive(Args, Debug)]
+pub struct KeyAdd {
+    /// Name of the key, letters, digits, `.`, `_` and `-`.
+    pub name: String,
+    /// Cipher spec the key is for.
+    #[clap(short, long, default_value = "aes-256-cbc")]
+    pub mode: CipherSpec,
+    #[clap(flatten)]
+    pub key: KeyOpts,
+    #[clap(flatten)]
+    pub keystore: KeystoreOpts,
+    /// Key derivation of the passphrase of a new keystore, `scrypt` or
+    /// `pbkdf2`.
+    #[clap(long, default_value = "scrypt")]
+    pub kdf: Kdf,
+    /// Cost of the key derivation, as for encryption.
+    #[clap(long, value_name = "COST")]
+    pub kdf_cost: Option<u32>,
+}
+
+#[derive(Args, Debug)]
+pub struct KeyList {
+    #[clap(flatten)]
+    pub keystore: KeystoreOpts,
+}
+
+#[derive(Args, Debug)]
+pub struct KeyRotate {
+    /// Name of the key.
+    pub name: String,
+    #[clap(flatten)]
+    pub key: KeyOpts,
+    #[clap(flatten)]
+    pub keystore: KeystoreOpts,
+}
+
+#[derive(Args, Debug)]
+pub struct KeyRetire {
+    /// Name of the key.
+    pub name: String,
+    #[clap(flatten)]
+    pub keystore: KeystoreOpts,
+}
+
+#[derive(Args, Debug)]
+pub struct KeyExport {
+    /// Name of the key.
+    pub name: String,
+    /// Version of the key, the active one by default.
+    #[clap(long)]
+    pub version: Option<u32>,
+    /// Format of the output, `hex`, `base64` or `raw`.
+    #[clap(short, long, default_value = "hex")]
+    pub format: KeyFormat,
+    /// Output file name, use a `-` for standard output. Files, existing ones
+    /// included, are made readable by their owner only.
+    #[clap(default_value = "-")]
+    pub output: String,
+    #[clap(flatten)]
+    pub keystore: KeystoreOpts,
 }
 
 impl Opts {
@@ -45,172 +336,323 @@ impl Opts {
     }
 
     pub fn is_encrypt(&self) -> bool {
-        match self.op {
-            Operation::Encrypt => true,
-            Operation::Decrypt => false,
-        }
+        matches!(self.op, Some(Operation::Encrypt))
     }
 
     pub fn is_decrypt(&self) -> bool {
-        match self.op {
-            Operation::Encrypt => false,
-            Operation::Decrypt => true,
+        matches!(self.op, Some(Operation::Decrypt))
+    }
+
+    /// Where the IV is read from, if it is given.
+    pub fn iv_source(&self) -> anyhow::Result<Option<Source>> {
+        source(
+            "iv",
+            Some(&self.iv).filter(|iv| *iv != "-"),
+            &self.iv_file,
+            &self.iv_env,
+            self.iv_fd,
+            self.iv_prompt,
+        )
+    }
+
+    /// The passphrase, if it is given, confirming it if it is asked for on
+    /// the terminal and `confirm` is set.
+    pub fn passphrase(&self, confirm: bool) -> anyhow::Result<Option<SecretKey>> {
+        if let Some(pass) = self.cache.passphrase.get() {
+            return Ok(Some(pass.clone()));
         }
+        let pass = source(
+            "passphrase",
+            self.passphrase.as_ref(),
+            &self.passphrase_file,
+            &self.passphrase_env,
+            self.passphrase_fd,
+            self.passphrase_prompt,
+        )?
+        .map(|source| source.read_passphrase(confirm))
+        .transpose()?;
+        if let Some(pass) = &pass {
+            let _ = self.cache.passphrase.set(pass.clone());
+        }
+        Ok(pass)
     }
-}
 
-#[derive(Debug)]
-pub enum Operation {
-    Encrypt,
-    Decrypt,
-}
+    /// The key for `spec`, or of any length without one. It is read once, and
+    /// checked against the spec of every later use.
+    pub fn read_key(&self, spec: Option<&CipherSpec>) -> anyhow::Result<SecretKey> {
+        let key = match self.cache.key.get() {
+            Some(key) => key.clone(),
+            None => {
+                let key = match spec {
+                    Some(spec) => self.key.read(spec)?,
+                    None => self.key.read_any(&[16, 24, 32])?,
+                };
+                let _ = self.cache.key.set(key.clone());
+                key
+            }
+        };
+        match spec {
+            Some(spec) if key.len() != spec.key_len() => bail!(
+                "The key must be {} bytes for {}, not {}",
+                spec.key_len(),
+                spec,
+                key.len()
+            ),
+            _ => Ok(key),
+        }
+    }
 
-impl std::str::FromStr for Operation {
-    type Err = String;
+    /// The key derivation of `--passphrase`, at the cost given.
+    pub fn kdf(&self) -> anyhow::Result<Kdf> {
+        match self.kdf_cost {
+            Some(cost) => Ok(self.kdf.with_cost(cost)?),
+            None => Ok(self.kdf),
+        }
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "encrypt" | "enc" | "e" => Ok(Self::Encrypt),
-            "decrypt" | "dec" | "d" => Ok(Self::Decrypt),
-            _ => Err(format!(
-                "unexpected value `{}`, expecting `encrypt`, `enc`, `e`, `decrypt`, `dec`, `d`",
-                s
-            )),
+    /// The ciphertext encoding given, with the Base64 options applied.
+    pub fn encoding(&self) -> Option<Encoding> {
+        match self.encoding {
+            Some(Encoding::Base64(base64)) => Some(Encoding::Base64(Base64 {
+                wrap: self.wrap,
+                pad: !self.no_pad,
+                ..base64
+            })),
+            encoding => encoding,
+        }
+    }
+
+    /// The key derivation of `--openssl`, as `openssl enc` picks it.
+    pub fn key_derivation(&self) -> KeyDerivation {
+        match (self.pbkdf2, self.iter) {
+            (false, None) => KeyDerivation::BytesToKey(self.md),
+            (_, iterations) => KeyDerivation::Pbkdf2 {
+                digest: self.md,
+                iterations: iterations.unwrap_or(DEFAULT_ITERATIONS),
+            },
         }
     }
 }
 
-pub trait StreamCipher {
-    fn new(key: &[u8], iv: &[u8]) -> Self
-    where
-        Self: Sized;
-    fn encrypt(&mut self, data: &[u8]) -> Vec<u8>;
-    fn decrypt(&mut self, data: &[u8]) -> Vec<u8>;
+impl KeyOpts {
+    /// Where the key is read from, if it is given.
+    pub fn source(&self) -> anyhow::Result<Option<Source>> {
+        source(
+            "key",
+            Some(&self.key).filter(|key| *key != "-"),
+            &self.key_file,
+            &self.key_env,
+            self.key_fd,
+            self.key_prompt,
+        )
+    }
+
+    /// The key for `spec`.
+    pub fn read(&self, spec: &CipherSpec) -> anyhow::Result<SecretKey> {
+        self.required()?.read("key", spec, spec.key_len())
+    }
+
+    /// The key, of any of the lengths `lens`.
+    pub fn read_any(&self, lens: &[usize]) -> anyhow::Result<SecretKey> {
+        self.required()?.read_any("key", lens)
+    }
+
+    fn required(&self) -> anyhow::Result<Source> {
+        self.source()?.ok_or_else(|| {
+            anyhow!(
+                "A key is required, see --key, --key-file, --key-env, --key-fd and --key-prompt"
+            )
+        })
+    }
 }
 
-macro_rules! impl_cipherset {
-    ($vis: vis $name: ident => $m: ident, $st: ident, $pad: ident) => {
-        $vis struct $name($st<$m, $pad>);
+#[derive(Args, Debug)]
+pub struct Reencrypt {
+    /// Containers, or directories to re-encrypt every container in.
+    #[clap(required = true)]
+    pub paths: Vec<String>,
+    /// Cipher spec to re-encrypt with, that of each container by default.
+    #[clap(short, long)]
+    pub mode: Option<CipherSpec>,
+    #[clap(flatten)]
+    pub key: KeyOpts,
+    /// Re-encrypts with the active version of the named keystore key.
+    /// Containers already encrypted with it are left as they are.
+    #[clap(long, value_name = "NAME")]
+    pub key_name: Option<String>,
+    #[clap(flatten)]
+    pub old_key: OldKeyOpts,
+    /// Re-encrypts with `--old-key` containers whose padding cannot tell a
+    /// wrong key, which would then be replaced with garbage.
+    #[clap(long)]
+    pub force: bool,
+    #[clap(flatten)]
+    pub keystore: KeystoreOpts,
+    /// Reports what would be re-encrypted, without changing anything.
+    #[clap(short = 'n', long)]
+    pub dry_run: bool,
+    /// Number of threads for the parallelisable modes, `0` for one per core.
+    #[clap(short, long, default_value = "1")]
+    pub threads: usize,
+}
 
-        impl StreamCipher for $name {
+/// Where the key the containers are encrypted with is read from, if not
+/// from the keystore by the key id they record
+#[derive(Args, Debug)]
+pub struct OldKeyOpts {
+    /// Old key in hex format.
+    #[clap(long, default_value = "-")]
+    pub old_key: String,
+    /// Reads the old key from a file, raw, in hex or in Base64.
+    #[clap(long, value_name = "PATH")]
+    pub old_key_file: Option<String>,
+    /// Reads the old key from an environment variable, in hex or in Base64.
+    #[clap(long, value_name = "VAR")]
+    pub old_key_env: Option<String>,
+    /// Reads the old key from an inherited file descriptor, like
+    /// `--old-key-file`.
+    #[clap(long, value_name = "N")]
+    pub old_key_fd: Option<i32>,
+    /// Asks for the old key on the terminal, in hex or in Base64.
+    #[clap(long)]
+    pub old_key_prompt: bool,
+}
 
-            fn new(key: &[u8], iv: &[u8]) -> Self
-            where
-                Self: Sized,
-            {
-                Self($st::<$m, $pad>::new(
-                    std::convert::TryInto::try_into(iv).unwrap(),
-                    std::convert::TryInto::try_into(key).unwrap(),
-                ))
-            }
+impl OldKeyOpts {
+    /// Where the old key is read from, if it is given.
+    pub fn source(&self) -> anyhow::Result<Option<Source>> {
+        source(
+            "old-key",
+            Some(&self.old_key).filter(|key| *key != "-"),
+            &self.old_key_file,
+            &self.old_key_env,
+            self.old_key_fd,
+            self.old_key_prompt,
+        )
+    }
+}
 
-            fn encrypt(&mut self, data: &[u8]) -> Vec<u8> {
-                self.0.stream_encrypt(data)
-            }
+/// The secret unlocking a keystore
+pub enum StoreSecret {
+    MasterKey(SecretKey),
+    Passphrase(SecretKey),
+}
 
-            fn decrypt(&mut self, data: &[u8]) -> Vec<u8> {
-                self.0.stream_decrypt(data)
-            }
+impl StoreSecret {
+    pub fn unlock(&self) -> Unlock<'_> {
+        match self {
+            StoreSecret::MasterKey(key) => Unlock::MasterKey(key),
+            StoreSecret::Passphrase(pass) => Unlock::Passphrase(pass),
         }
-    };
-}
-
-impl_cipherset!(pub Aes128CbcIso10126 => AES128, CipherBlockChaining, ISO10126);
-impl_cipherset!(pub Aes128CbcPkcs7 => AES128, CipherBlockChaining, PKCS7);
-impl_cipherset!(pub Aes128CbcX923 => AES128, CipherBlockChaining, X923);
-impl_cipherset!(pub Aes128EcbIso10126 => AES128, ElectronicCodeBook, ISO10126);
-impl_cipherset!(pub Aes128EcbPkcs7 => AES128, ElectronicCodeBook, PKCS7);
-impl_cipherset!(pub Aes128EcbX923 => AES128, ElectronicCodeBook, X923);
-impl_cipherset!(pub Aes192CbcIso10126 => AES192, CipherBlockChaining, ISO10126);
-impl_cipherset!(pub Aes192CbcPkcs7 => AES192, CipherBlockChaining, PKCS7);
-impl_cipherset!(pub Aes192CbcX923 => AES192, CipherBlockChaining, X923);
-impl_cipherset!(pub Aes192EcbIso10126 => AES192, ElectronicCodeBook, ISO10126);
-impl_cipherset!(pub Aes192EcbPkcs7 => AES192, ElectronicCodeBook, PKCS7);
-impl_cipherset!(pub Aes192EcbX923 => AES192, ElectronicCodeBook, X923);
-impl_cipherset!(pub Aes256CbcIso10126 => AES256, CipherBlockChaining, ISO10126);
-impl_cipherset!(pub Aes256CbcPkcs7 => AES256, CipherBlockChaining, PKCS7);
-impl_cipherset!(pub Aes256CbcX923 => AES256, CipherBlockChaining, X923);
-impl_cipherset!(pub Aes256EcbIso10126 => AES256, ElectronicCodeBook, ISO10126);
-impl_cipherset!(pub Aes256EcbPkcs7 => AES256, ElectronicCodeBook, PKCS7);
-impl_cipherset!(pub Aes256EcbX923 => AES256, ElectronicCodeBook, X923);
+    }
+}
 
-#[derive(Debug)]
-pub enum Cipherset {
-    Aes128CbcIso10126,
-    Aes128CbcPkcs7,
-    Aes128CbcX923,
-    Aes128EcbIso10126,
-    Aes128EcbPkcs7,
-    Aes128EcbX923,
-    Aes192CbcIso10126,
-    Aes192CbcPkcs7,
-    Aes192CbcX923,
-    Aes192EcbIso10126,
-    Aes192EcbPkcs7,
-    Aes192EcbX923,
-    Aes256CbcIso10126,
-    Aes256CbcPkcs7,
-    Aes256CbcX923,
-    Aes256EcbIso10126,
-    Aes256EcbPkcs7,
-    Aes256EcbX923,
-}
-
-impl FromStr for Cipherset {
+impl KeystoreOpts {
+    /// The keystore file.
+    pub fn path(&self) -> anyhow::Result<String> {
+        match &self.keystore {
+            Some(path) => Ok(path.clone()),
+            None => std::env::var("AES_KEYSTORE")
+                .map_err(|_| anyhow!("A keystore is required, see --keystore")),
+        }
+    }
+
+    /// The master key or the passphrase of the keystore, confirming the
+    /// passphrase if it is asked for on the terminal and `confirm` is set.
+    pub fn secret(&self, confirm: bool) -> anyhow::Result<StoreSecret> {
+        let key = source(
+            "master-key",
+            self.master_key.as_ref(),
+            &self.master_key_file,
+            &self.master_key_env,
+            self.master_key_fd,
+            self.master_key_prompt,
+        )?;
+        let pass = source(
+            "keystore-passphrase",
+            self.keystore_passphrase.as_ref(),
+            &self.keystore_passphrase_file,
+            &self.keystore_passphrase_env,
+            self.keystore_passphrase_fd,
+            self.keystore_passphrase_prompt,
+        )?;
+        match (key, pass) {
+            (Some(key), None) => Ok(StoreSecret::MasterKey(
+                key.read_any("master key", &[16, 24, 32])?,
+            )),
+            (None, Some(pass)) => Ok(StoreSecret::Passphrase(pass.read_passphrase(confirm)?)),
+            (Some(_), Some(_)) => bail!("Either a master key or a keystore passphrase may be given"),
+            (None, None) => bail!(
+                "The keystore is unlocked with a master key or a passphrase, see --master-key and --keystore-passphrase"
+            ),
+        }
+    }
+}
+
+/// Format of the keys written by `keygen` and `key export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyFormat {
+    Hex,
+    Base64,
+    Raw,
+}
+
+impl std::str::FromStr for KeyFormat {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "aes-128-cbc" => Ok(Cipherset::Aes128CbcPkcs7),
-            "aes-128-cbc-iso10126" => Ok(Cipherset::Aes128CbcIso10126),
-            "aes-128-cbc-pkcs7" => Ok(Cipherset::Aes128CbcPkcs7),
-            "aes-128-cbc-x923" => Ok(Cipherset::Aes128CbcX923),
-            "aes-128-ecb" => Ok(Cipherset::Aes128EcbPkcs7),
-            "aes-128-ecb-iso10126" => Ok(Cipherset::Aes128EcbIso10126),
-            "aes-128-ecb-pkcs7" => Ok(Cipherset::Aes128EcbPkcs7),
-            "aes-128-ecb-x923" => Ok(Cipherset::Aes128EcbX923),
-            "aes-192-cbc" => Ok(Cipherset::Aes192CbcPkcs7),
-            "aes-192-cbc-iso10126" => Ok(Cipherset::Aes192CbcIso10126),
-            "aes-192-cbc-pkcs7" => Ok(Cipherset::Aes192CbcPkcs7),
-            "aes-192-cbc-x923" => Ok(Cipherset::Aes192CbcX923),
-            "aes-192-ecb" => Ok(Cipherset::Aes192EcbPkcs7),
-            "aes-192-ecb-iso10126" => Ok(Cipherset::Aes192EcbIso10126),
-            "aes-192-ecb-pkcs7" => Ok(Cipherset::Aes192EcbPkcs7),
-            "aes-192-ecb-x923" => Ok(Cipherset::Aes192EcbX923),
-            "aes-256-cbc" => Ok(Cipherset::Aes256CbcPkcs7),
-            "aes-256-cbc-iso10126" => Ok(Cipherset::Aes256CbcIso10126),
-            "aes-256-cbc-pkcs7" => Ok(Cipherset::Aes256CbcPkcs7),
-            "aes-256-cbc-x923" => Ok(Cipherset::Aes256CbcX923),
-            "aes-256-ecb" => Ok(Cipherset::Aes256EcbPkcs7),
-            "aes-256-ecb-iso10126" => Ok(Cipherset::Aes256EcbIso10126),
-            "aes-256-ecb-pkcs7" => Ok(Cipherset::Aes256EcbPkcs7),
-            "aes-256-ecb-x923" => Ok(Cipherset::Aes256EcbX923),
-            _ => Err("invalid cipher set".to_string()),
+        match s.to_lowercase().as_str() {
+            "hex" => Ok(Self::Hex),
+            "base64" => Ok(Self::Base64),
+            "raw" => Ok(Self::Raw),
+            _ => Err(format!(
+                "unexpected value `{}`, expecting `hex`, `base64` or `raw`",
+                s
+            )),
         }
     }
 }
 
-impl Cipherset {
-    pub fn get_cipher(&self, key: &[u8], iv: &[u8]) -> Box<dyn StreamCipher> {
-        match self {
-            Cipherset::Aes128CbcIso10126 => Box::new(Aes128CbcIso10126::new(key, iv)),
-            Cipherset::Aes128CbcPkcs7 => Box::new(Aes128CbcPkcs7::new(key, iv)),
-            Cipherset::Aes128CbcX923 => Box::new(Aes128CbcX923::new(key, iv)),
-            Cipherset::Aes128EcbIso10126 => Box::new(Aes128EcbIso10126::new(key, iv)),
-            Cipherset::Aes128EcbPkcs7 => Box::new(Aes128EcbPkcs7::new(key, iv)),
-            Cipherset::Aes128EcbX923 => Box::new(Aes128EcbX923::new(key, iv)),
-            Cipherset::Aes192CbcIso10126 => Box::new(Aes192CbcIso10126::new(key, iv)),
-            Cipherset::Aes192CbcPkcs7 => Box::new(Aes192CbcPkcs7::new(key, iv)),
-            Cipherset::Aes192CbcX923 => Box::new(Aes192CbcX923::new(key, iv)),
-            Cipherset::Aes192EcbIso10126 => Box::new(Aes192EcbIso10126::new(key, iv)),
-            Cipherset::Aes192EcbPkcs7 => Box::new(Aes192EcbPkcs7::new(key, iv)),
-            Cipherset::Aes192EcbX923 => Box::new(Aes192EcbX923::new(key, iv)),
-            Cipherset::Aes256CbcIso10126 => Box::new(Aes256CbcIso10126::new(key, iv)),
-            Cipherset::Aes256CbcPkcs7 => Box::new(Aes256CbcPkcs7::new(key, iv)),
-            Cipherset::Aes256CbcX923 => Box::new(Aes256CbcX923::new(key, iv)),
-            Cipherset::Aes256EcbIso10126 => Box::new(Aes256EcbIso10126::new(key, iv)),
-            Cipherset::Aes256EcbPkcs7 => Box::new(Aes256EcbPkcs7::new(key, iv)),
-            Cipherset::Aes256EcbX923 => Box::new(Aes256EcbX923::new(key, iv)),
+/// The one source of the `--<name>` options given, if any.
+fn source(
+    name: &str,
+    arg: Option<&String>,
+    file: &Option<String>,
+    env: &Option<String>,
+    fd: Option<i32>,
+    prompt: bool,
+) -> anyhow::Result<Option<Source>> {
+    let mut sources = vec![];
+    sources.extend(arg.cloned().map(Source::Arg));
+    sources.extend(file.clone().map(Source::File));
+    sources.extend(env.clone().map(Source::Env));
+    sources.extend(fd.map(Source::Fd));
+    if prompt {
+        sources.push(Source::Prompt);
+    }
+    if sources.len() > 1 {
+        bail!("Only one of the --{} options may be given", name);
+    }
+    Ok(sources.pop())
+}
+
+#[derive(Debug)]
+pub enum Operation {
+    Encrypt,
+    Decrypt,
+}
+
+impl std::str::FromStr for Operation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "encrypt" | "enc" | "e" => Ok(Self::Encrypt),
+            "decrypt" | "dec" | "d" => Ok(Self::Decrypt),
+            _ => Err(format!(
+                "unexpected value `{}`, expecting `encrypt`, `enc`, `e`, `decrypt`, `dec`, `d`",
+                s
+            )),
         }
     }
 }
@@ -1,26 +1,43 @@
-use super::Padding;
+use super::{ct_eq, ct_in_padding, ct_len_valid, to_result, Padding};
+use crate::error::{Error, Result};
 
 pub struct X923;
 
 impl Padding for X923 {
-    fn pad_block(ds_byte: usize, bs_byte: usize) -> Vec<u8> {
-        let mut ret = vec![0x00; bs_byte - ds_byte - 1];
-        ret.push((bs_byte - ds_byte) as u8);
-        ret
+    fn pad_into(pad: &mut [u8], ds_byte: usize, bs_byte: usize) {
+        pad.fill(0x00);
+        *pad.last_mut().unwrap() = (bs_byte - ds_byte) as u8;
+    }
+
+    fn unpad_block(data: &[u8], bs_byte: usize) -> Result<usize> {
+        let n = *data.last().ok_or(Error::BadPadding)?;
+        let mut mask = ct_len_valid(n, bs_byte);
+        for (i, &b) in data[..data.len() - 1].iter().enumerate() {
+            // every padding byte but the length must be zero
+            mask &= !(ct_in_padding(i, n, bs_byte) & !ct_eq(b, 0));
+        }
+        to_result(mask, n)
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "alloc"))]
 #[test]
 #[rustfmt::skip]
 fn test() {
-    assert_eq!(&X923::pad_eat(vec![0xFF; 0], 8), b"\x00\x00\x00\x00\x00\x00\x00\x08");
-    assert_eq!(&X923::pad_eat(vec![0xFF; 8], 8), b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x00\x00\x00\x00\x00\x00\x00\x08");
-    assert_eq!(&X923::pad_eat(vec![0xFF; 1], 8), b"\xFF\x00\x00\x00\x00\x00\x00\x07");
-    assert_eq!(&X923::pad_eat(vec![0xFF; 2], 8), b"\xFF\xFF\x00\x00\x00\x00\x00\x06");
-    assert_eq!(&X923::pad_eat(vec![0xFF; 3], 8), b"\xFF\xFF\xFF\x00\x00\x00\x00\x05");
-    assert_eq!(&X923::pad_eat(vec![0xFF; 4], 8), b"\xFF\xFF\xFF\xFF\x00\x00\x00\x04");
-    assert_eq!(&X923::pad_eat(vec![0xFF; 5], 8), b"\xFF\xFF\xFF\xFF\xFF\x00\x00\x03");
-    assert_eq!(&X923::pad_eat(vec![0xFF; 6], 8), b"\xFF\xFF\xFF\xFF\xFF\xFF\x00\x02");
-    assert_eq!(&X923::pad_eat(vec![0xFF; 7], 8), b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x01");
+    assert_eq!(&X923::pad_eat(vec![0xFF; 0], 8).unwrap(), b"\x00\x00\x00\x00\x00\x00\x00\x08");
+    assert_eq!(&X923::pad_eat(vec![0xFF; 8], 8).unwrap(), b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x00\x00\x00\x00\x00\x00\x00\x08");
+    assert_eq!(&X923::pad_eat(vec![0xFF; 1], 8).unwrap(), b"\xFF\x00\x00\x00\x00\x00\x00\x07");
+    assert_eq!(&X923::pad_eat(vec![0xFF; 2], 8).unwrap(), b"\xFF\xFF\x00\x00\x00\x00\x00\x06");
+    assert_eq!(&X923::pad_eat(vec![0xFF; 3], 8).unwrap(), b"\xFF\xFF\xFF\x00\x00\x00\x00\x05");
+    assert_eq!(&X923::pad_eat(vec![0xFF; 4], 8).unwrap(), b"\xFF\xFF\xFF\xFF\x00\x00\x00\x04");
+    assert_eq!(&X923::pad_eat(vec![0xFF; 5], 8).unwrap(), b"\xFF\xFF\xFF\xFF\xFF\x00\x00\x03");
+    assert_eq!(&X923::pad_eat(vec![0xFF; 6], 8).unwrap(), b"\xFF\xFF\xFF\xFF\xFF\xFF\x00\x02");
+    assert_eq!(&X923::pad_eat(vec![0xFF; 7], 8).unwrap(), b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x01");
+    assert_eq!(X923::unpad_eat(b"\xFF\xFF\xFF\x00\x00\x00\x00\x05".to_vec(), 8), Ok(vec![0xFF; 3]));
+    assert_eq!(X923::unpad_eat(b"\x00\x00\x00\x00\x00\x00\x00\x08".to_vec(), 8), Ok(vec![]));
+    assert_eq!(X923::unpad_eat(b"\xFF\xFF\xFF\x00\x00\x01\x00\x05".to_vec(), 8), Err(Error::BadPadding));
+    assert_eq!(X923::unpad_eat(b"\xFF\xFF\xFF\xFF\x00\x00\x00\x05".to_vec(), 8), Err(Error::BadPadding));
+    assert_eq!(X923::unpad_eat(b"\x01\x00\x00\x00\x00\x00\x00\x08".to_vec(), 8), Err(Error::BadPadding));
+    assert_eq!(X923::unpad_eat(b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x00".to_vec(), 8), Err(Error::BadPadding));
+    assert_eq!(X923::unpad_eat(b"\x00\x00\x00\x00\x00\x00\x00\x09".to_vec(), 8), Err(Error::BadPadding));
 }
@@ -4,11 +4,9 @@ use rand::prelude::*;
 pub struct ISO10126;
 
 impl Padding for ISO10126 {
-    fn pad_block(ds_byte: usize, bs_byte: usize) -> Vec<u8> {
-        let mut ret = vec![0x00; bs_byte - ds_byte];
-        rand::thread_rng().fill_bytes(&mut ret);
-        *ret.last_mut().unwrap() = (bs_byte - ds_byte) as u8;
-        ret
+    fn pad_into(pad: &mut [u8], ds_byte: usize, bs_byte: usize) {
+        rand::thread_rng().fill_bytes(pad);
+        *pad.last_mut().unwrap() = (bs_byte - ds_byte) as u8;
     }
 }
 
@@ -16,12 +14,17 @@ impl Padding for ISO10126 {
 #[test]
 #[rustfmt::skip]
 fn test() {
-    assert_eq!(*ISO10126::pad_eat(vec![0xFF; 8], 8).last().unwrap(), 0x08);
-    assert_eq!(*ISO10126::pad_eat(vec![0xFF; 1], 8).last().unwrap(), 0x07);
-    assert_eq!(*ISO10126::pad_eat(vec![0xFF; 2], 8).last().unwrap(), 0x06);
-    assert_eq!(*ISO10126::pad_eat(vec![0xFF; 3], 8).last().unwrap(), 0x05);
-    assert_eq!(*ISO10126::pad_eat(vec![0xFF; 4], 8).last().unwrap(), 0x04);
-    assert_eq!(*ISO10126::pad_eat(vec![0xFF; 5], 8).last().unwrap(), 0x03);
-    assert_eq!(*ISO10126::pad_eat(vec![0xFF; 6], 8).last().unwrap(), 0x02);
-    assert_eq!(*ISO10126::pad_eat(vec![0xFF; 7], 8).last().unwrap(), 0x01);
+    use crate::error::Error;
+    assert_eq!(*ISO10126::pad_eat(vec![0xFF; 8], 8).unwrap().last().unwrap(), 0x08);
+    assert_eq!(*ISO10126::pad_eat(vec![0xFF; 1], 8).unwrap().last().unwrap(), 0x07);
+    assert_eq!(*ISO10126::pad_eat(vec![0xFF; 2], 8).unwrap().last().unwrap(), 0x06);
+    assert_eq!(*ISO10126::pad_eat(vec![0xFF; 3], 8).unwrap().last().unwrap(), 0x05);
+    assert_eq!(*ISO10126::pad_eat(vec![0xFF; 4], 8).unwrap().last().unwrap(), 0x04);
+    assert_eq!(*ISO10126::pad_eat(vec![0xFF; 5], 8).unwrap().last().unwrap(), 0x03);
+    assert_eq!(*ISO10126::pad_eat(vec![0xFF; 6], 8).unwrap().last().unwrap(), 0x02);
+    assert_eq!(*ISO10126::pad_eat(vec![0xFF; 7], 8).unwrap().last().unwrap(), 0x01);
+    assert_eq!(ISO10126::unpad_eat(b"\xFF\xFF\xFF\x12\x34\x56\x78\x05".to_vec(), 8), Ok(vec![0xFF; 3]));
+    assert_eq!(ISO10126::unpad_eat(b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x00".to_vec(), 8), Err(Error::BadPadding));
+    assert_eq!(ISO10126::unpad_eat(b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x09".to_vec(), 8), Err(Error::BadPadding));
+    assert_eq!(ISO10126::unpad_eat(vec![], 8), Err(Error::BadPadding));
 }
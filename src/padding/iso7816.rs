@@ -0,0 +1,45 @@
+use super::{ct_eq, to_result, Padding};
+use crate::error::Result;
+
+/// ISO/IEC 7816-4 padding, a `0x80` byte followed by zeros
+pub struct ISO7816;
+
+impl Padding for ISO7816 {
+    fn pad_into(pad: &mut [u8], _ds_byte: usize, _bs_byte: usize) {
+        pad.fill(0x00);
+        pad[0] = 0x80;
+    }
+
+    fn unpad_block(data: &[u8], bs_byte: usize) -> Result<usize> {
+        // the padding ends at the last non-zero byte, which must be `0x80`
+        let mut zeros = 0xFF;
+        let mut found = 0x00;
+        let mut n = 0x00;
+        for (i, &b) in data.iter().enumerate().rev() {
+            let marker = zeros & ct_eq(b, 0x80);
+            found |= marker;
+            n |= marker & (bs_byte - i) as u8;
+            zeros &= ct_eq(b, 0x00);
+        }
+        to_result(found, n)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+#[test]
+#[rustfmt::skip]
+fn test() {
+    use crate::error::Error;
+    assert_eq!(&ISO7816::pad_eat(vec![0xFF; 0], 8).unwrap(), b"\x80\x00\x00\x00\x00\x00\x00\x00");
+    assert_eq!(&ISO7816::pad_eat(vec![0xFF; 8], 8).unwrap(), b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x80\x00\x00\x00\x00\x00\x00\x00");
+    assert_eq!(&ISO7816::pad_eat(vec![0xFF; 1], 8).unwrap(), b"\xFF\x80\x00\x00\x00\x00\x00\x00");
+    assert_eq!(&ISO7816::pad_eat(vec![0xFF; 4], 8).unwrap(), b"\xFF\xFF\xFF\xFF\x80\x00\x00\x00");
+    assert_eq!(&ISO7816::pad_eat(vec![0xFF; 7], 8).unwrap(), b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x80");
+    assert_eq!(ISO7816::unpad_eat(b"\xFF\x80\x00\x00\x00\x00\x00\x00".to_vec(), 8), Ok(vec![0xFF]));
+    assert_eq!(ISO7816::unpad_eat(b"\x80\x80\x80\x00\x00\x00\x00\x00".to_vec(), 8), Ok(vec![0x80; 2]));
+    assert_eq!(ISO7816::unpad_eat(b"\x80\x00\x00\x00\x00\x00\x00\x00".to_vec(), 8), Ok(vec![]));
+    assert_eq!(ISO7816::unpad_eat(b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x80".to_vec(), 8), Ok(vec![0xFF; 7]));
+    assert_eq!(ISO7816::unpad_eat(b"\x00\x00\x00\x00\x00\x00\x00\x00".to_vec(), 8), Err(Error::BadPadding));
+    assert_eq!(ISO7816::unpad_eat(b"\xFF\x81\x00\x00\x00\x00\x00\x00".to_vec(), 8), Err(Error::BadPadding));
+    assert_eq!(ISO7816::unpad_eat(b"\xFF\x80\x00\x00\x00\x00\x00\x01".to_vec(), 8), Err(Error::BadPadding));
+}
@@ -1,31 +1,155 @@
+use crate::error::{Error, Result};
+#[cfg(feature = "alloc")]
+use alloc::{vec, vec::Vec};
+
 pub trait Padding {
-    fn pad_block(ds_byte: usize, bs_byte: usize) -> Vec<u8>;
-    #[allow(unused_variables)]
-    fn unpad_block(data: &[u8], bs_byte: usize) -> usize {
-        *data.last().unwrap() as usize
+    /// Fills `pad`, the `bs_byte - ds_byte` bytes following the `ds_byte`
+    /// bytes of data in the last block.
+    fn pad_into(pad: &mut [u8], ds_byte: usize, bs_byte: usize);
+    #[cfg(feature = "alloc")]
+    fn pad_block(ds_byte: usize, bs_byte: usize) -> Vec<u8> {
+        let mut ret = vec![0x00; bs_byte - ds_byte];
+        Self::pad_into(&mut ret, ds_byte, bs_byte);
+        ret
+    }
+    /// Returns the length of the padding at the end of the last block `data`.
+    ///
+    /// The default only checks that the last byte is a length within the
+    /// block. Implementations must not branch on the contents of `data`, so
+    /// that a failure does not reveal where the padding went wrong.
+    fn unpad_block(data: &[u8], bs_byte: usize) -> Result<usize> {
+        let n = *data.last().ok_or(Error::BadPadding)?;
+        to_result(ct_len_valid(n, bs_byte), n)
     }
-    fn pad(data: &mut Vec<u8>, bs_byte: usize) {
-        data.append(&mut Self::pad_block(data.len() % bs_byte, bs_byte));
+
+    /// Returns the length of `len` bytes of data once padded. The default
+    /// always adds padding, a whole block of it for aligned data.
+    fn padded_len(len: usize, bs_byte: usize) -> Result<usize> {
+        Ok(len - len % bs_byte + bs_byte)
     }
-    fn pad_eat(mut data: Vec<u8>, bs_byte: usize) -> Vec<u8> {
-        Self::pad(&mut data, bs_byte);
-        data
+
+    /// Pads the `len` bytes of data at the start of `buf` in place and
+    /// returns the padded length. `buf` must have room for the padding, see
+    /// [`Self::padded_len`].
+    fn pad_slice(buf: &mut [u8], len: usize, bs_byte: usize) -> Result<usize> {
+        let padded = Self::padded_len(len, bs_byte)?;
+        Self::pad_into(&mut buf[len..padded], len % bs_byte, bs_byte);
+        Ok(padded)
+    }
+    /// Returns the length of the data in the padded `data`.
+    fn unpad_slice(data: &[u8], bs_byte: usize) -> Result<usize> {
+        if data.len() < bs_byte {
+            return Err(Error::BadPadding);
+        }
+        Ok(data.len() - Self::unpad_block(&data[(data.len() - bs_byte)..data.len()], bs_byte)?)
+    }
+
+    #[cfg(feature = "alloc")]
+    fn pad(data: &mut Vec<u8>, bs_byte: usize) -> Result<()> {
+        let len = data.len();
+        data.resize(Self::padded_len(len, bs_byte)?, 0x00);
+        Self::pad_into(&mut data[len..], len % bs_byte, bs_byte);
+        Ok(())
     }
-    fn unpad(data: &mut Vec<u8>, bs_byte: usize) {
-        data.resize_with(
-            data.len() - Self::unpad_block(&data[(data.len() - bs_byte)..data.len()], bs_byte),
-            Default::default,
-        );
+    #[cfg(feature = "alloc")]
+    fn pad_eat(mut data: Vec<u8>, bs_byte: usize) -> Result<Vec<u8>> {
+        Self::pad(&mut data, bs_byte)?;
+        Ok(data)
     }
-    fn unpad_eat(mut data: Vec<u8>, bs_byte: usize) -> Vec<u8> {
-        Self::unpad(&mut data, bs_byte);
-        data
+    #[cfg(feature = "alloc")]
+    fn unpad(data: &mut Vec<u8>, bs_byte: usize) -> Result<()> {
+        data.truncate(Self::unpad_slice(data, bs_byte)?);
+        Ok(())
+    }
+    #[cfg(feature = "alloc")]
+    fn unpad_eat(mut data: Vec<u8>, bs_byte: usize) -> Result<Vec<u8>> {
+        Self::unpad(&mut data, bs_byte)?;
+        Ok(data)
+    }
+}
+
+/// `0xFF` if `a <= b`, otherwise `0x00`, without branching on either value
+fn ct_le(a: u8, b: u8) -> u8 {
+    ((a as u16).wrapping_sub(b as u16 + 1) >> 8) as u8
+}
+
+/// `0xFF` if `a == b`, otherwise `0x00`, without branching on either value
+fn ct_eq(a: u8, b: u8) -> u8 {
+    ct_le(a ^ b, 0)
+}
+
+/// `0xFF` if `n` is a padding length that fits in a `bs_byte` block,
+/// otherwise `0x00`, without branching on `n`
+fn ct_len_valid(n: u8, bs_byte: usize) -> u8 {
+    !ct_le(n, 0) & ct_le(n, bs_byte as u8)
+}
+
+/// `0xFF` if the `index`-th byte of a `bs_byte` block lies in the last `n`
+/// bytes, otherwise `0x00`, without branching on `n`
+fn ct_in_padding(index: usize, n: u8, bs_byte: usize) -> u8 {
+    ct_le((bs_byte - index) as u8, n)
+}
+
+/// Turns the accumulated validity `mask` into the padding length `n`. Only
+/// this final outcome is branched on.
+fn to_result(mask: u8, n: u8) -> Result<usize> {
+    match mask {
+        0xFF => Ok(n as usize),
+        _ => Err(Error::BadPadding),
     }
 }
 
 mod ansix923;
 pub use ansix923::X923;
+#[cfg(feature = "std")]
 mod iso10126;
+#[cfg(feature = "std")]
 pub use iso10126::ISO10126;
+mod iso7816;
+pub use iso7816::ISO7816;
+mod nopad;
+pub use nopad::NoPadding;
 mod pkcs7;
 pub use pkcs7::PKCS7;
+mod zero;
+pub use zero::ZeroPadding;
+
+#[cfg(test)]
+#[test]
+fn test_ct() {
+    for a in 0..=255u8 {
+        for b in 0..=255u8 {
+            assert_eq!(ct_le(a, b), if a <= b { 0xFF } else { 0x00 });
+            assert_eq!(ct_eq(a, b), if a == b { 0xFF } else { 0x00 });
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_slices() {
+    /// Pads `len` bytes of data in a buffer of two 8 byte blocks, expecting
+    /// `pad`, and unpads them again.
+    fn check<P: Padding>(len: usize, pad: &[u8]) {
+        let mut buf = [0xFF; 16];
+        let n = P::pad_slice(&mut buf, len, 8).unwrap();
+        assert_eq!(&buf[len..n], pad);
+        assert_eq!(P::unpad_slice(&buf[..n], 8), Ok(len));
+    }
+
+    check::<PKCS7>(5, &[0x03; 3]);
+    check::<PKCS7>(8, &[0x08; 8]);
+    check::<X923>(5, &[0x00, 0x00, 0x03]);
+    check::<ISO7816>(5, &[0x80, 0x00, 0x00]);
+    check::<ZeroPadding>(5, &[0x00; 3]);
+    check::<ZeroPadding>(8, &[]);
+    check::<NoPadding>(8, &[]);
+    assert_eq!(
+        NoPadding::pad_slice(&mut [0; 16], 5, 8),
+        Err(Error::InvalidPlaintextLength {
+            block: 8,
+            actual: 5
+        })
+    );
+    assert_eq!(PKCS7::unpad_slice(&[0x02; 7], 8), Err(Error::BadPadding));
+}
@@ -0,0 +1,42 @@
+use super::Padding;
+use crate::error::{Error, Result};
+
+/// No padding, for data already aligned to the block size
+pub struct NoPadding;
+
+impl Padding for NoPadding {
+    fn pad_into(_pad: &mut [u8], _ds_byte: usize, _bs_byte: usize) {}
+
+    fn unpad_block(_data: &[u8], _bs_byte: usize) -> Result<usize> {
+        Ok(0)
+    }
+
+    fn padded_len(len: usize, bs_byte: usize) -> Result<usize> {
+        match len % bs_byte {
+            0 => Ok(len),
+            _ => Err(Error::InvalidPlaintextLength {
+                block: bs_byte,
+                actual: len,
+            }),
+        }
+    }
+
+    fn unpad_slice(data: &[u8], _bs_byte: usize) -> Result<usize> {
+        Ok(data.len())
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+#[test]
+fn test() {
+    assert_eq!(NoPadding::pad_eat(vec![], 8), Ok(vec![]));
+    assert_eq!(NoPadding::pad_eat(vec![0xFF; 16], 8), Ok(vec![0xFF; 16]));
+    assert_eq!(
+        NoPadding::pad_eat(vec![0xFF; 9], 8),
+        Err(Error::InvalidPlaintextLength {
+            block: 8,
+            actual: 9
+        })
+    );
+    assert_eq!(NoPadding::unpad_eat(vec![0x00; 16], 8), Ok(vec![0x00; 16]));
+}
@@ -1,14 +1,25 @@
-use super::Padding;
+use super::{ct_eq, ct_in_padding, ct_len_valid, to_result, Padding};
+use crate::error::{Error, Result};
 
 pub struct PKCS7;
 
 impl Padding for PKCS7 {
-    fn pad_block(ds_byte: usize, bs_byte: usize) -> Vec<u8> {
-        vec![(bs_byte - ds_byte) as u8; bs_byte - ds_byte]
+    fn pad_into(pad: &mut [u8], ds_byte: usize, bs_byte: usize) {
+        pad.fill((bs_byte - ds_byte) as u8);
+    }
+
+    fn unpad_block(data: &[u8], bs_byte: usize) -> Result<usize> {
+        let n = *data.last().ok_or(Error::BadPadding)?;
+        let mut mask = ct_len_valid(n, bs_byte);
+        for (i, &b) in data.iter().enumerate() {
+            // every padding byte must equal the padding length
+            mask &= !(ct_in_padding(i, n, bs_byte) & !ct_eq(b, n));
+        }
+        to_result(mask, n)
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "alloc"))]
 #[test]
 #[rustfmt::skip]
 #[allow(clippy::identity_op)]
@@ -21,13 +32,21 @@ fn test() {
     assert_eq!(PKCS7::pad_block(5, 8), vec![0x08 - 0x05; 0x08 - 0x05]);
     assert_eq!(PKCS7::pad_block(6, 8), vec![0x08 - 0x06; 0x08 - 0x06]);
     assert_eq!(PKCS7::pad_block(7, 8), vec![0x08 - 0x07; 0x08 - 0x07]);
-    assert_eq!(&PKCS7::pad_eat(vec![0xFF; 0], 8), b"\x08\x08\x08\x08\x08\x08\x08\x08");
-    assert_eq!(&PKCS7::pad_eat(vec![0xFF; 8], 8), b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x08\x08\x08\x08\x08\x08\x08\x08");
-    assert_eq!(&PKCS7::pad_eat(vec![0xFF; 1], 8), b"\xFF\x07\x07\x07\x07\x07\x07\x07");
-    assert_eq!(&PKCS7::pad_eat(vec![0xFF; 2], 8), b"\xFF\xFF\x06\x06\x06\x06\x06\x06");
-    assert_eq!(&PKCS7::pad_eat(vec![0xFF; 3], 8), b"\xFF\xFF\xFF\x05\x05\x05\x05\x05");
-    assert_eq!(&PKCS7::pad_eat(vec![0xFF; 4], 8), b"\xFF\xFF\xFF\xFF\x04\x04\x04\x04");
-    assert_eq!(&PKCS7::pad_eat(vec![0xFF; 5], 8), b"\xFF\xFF\xFF\xFF\xFF\x03\x03\x03");
-    assert_eq!(&PKCS7::pad_eat(vec![0xFF; 6], 8), b"\xFF\xFF\xFF\xFF\xFF\xFF\x02\x02");
-    assert_eq!(&PKCS7::pad_eat(vec![0xFF; 7], 8), b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x01");
+    assert_eq!(&PKCS7::pad_eat(vec![0xFF; 0], 8).unwrap(), b"\x08\x08\x08\x08\x08\x08\x08\x08");
+    assert_eq!(&PKCS7::pad_eat(vec![0xFF; 8], 8).unwrap(), b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x08\x08\x08\x08\x08\x08\x08\x08");
+    assert_eq!(&PKCS7::pad_eat(vec![0xFF; 1], 8).unwrap(), b"\xFF\x07\x07\x07\x07\x07\x07\x07");
+    assert_eq!(&PKCS7::pad_eat(vec![0xFF; 2], 8).unwrap(), b"\xFF\xFF\x06\x06\x06\x06\x06\x06");
+    assert_eq!(&PKCS7::pad_eat(vec![0xFF; 3], 8).unwrap(), b"\xFF\xFF\xFF\x05\x05\x05\x05\x05");
+    assert_eq!(&PKCS7::pad_eat(vec![0xFF; 4], 8).unwrap(), b"\xFF\xFF\xFF\xFF\x04\x04\x04\x04");
+    assert_eq!(&PKCS7::pad_eat(vec![0xFF; 5], 8).unwrap(), b"\xFF\xFF\xFF\xFF\xFF\x03\x03\x03");
+    assert_eq!(&PKCS7::pad_eat(vec![0xFF; 6], 8).unwrap(), b"\xFF\xFF\xFF\xFF\xFF\xFF\x02\x02");
+    assert_eq!(&PKCS7::pad_eat(vec![0xFF; 7], 8).unwrap(), b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x01");
+    assert_eq!(PKCS7::unpad_eat(b"\xFF\xFF\xFF\xFF\x04\x04\x04\x04".to_vec(), 8), Ok(vec![0xFF; 4]));
+    assert_eq!(PKCS7::unpad_eat(b"\x08\x08\x08\x08\x08\x08\x08\x08".to_vec(), 8), Ok(vec![]));
+    assert_eq!(PKCS7::unpad_eat(b"\xFF\xFF\xFF\xFF\x04\x04\x05\x04".to_vec(), 8), Err(Error::BadPadding));
+    assert_eq!(PKCS7::unpad_eat(b"\xFF\xFF\xFF\xFF\xFF\x04\x04\x04".to_vec(), 8), Err(Error::BadPadding));
+    assert_eq!(PKCS7::unpad_eat(b"\x07\x07\x07\x07\x07\x07\x07\x08".to_vec(), 8), Err(Error::BadPadding));
+    assert_eq!(PKCS7::unpad_eat(b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x00".to_vec(), 8), Err(Error::BadPadding));
+    assert_eq!(PKCS7::unpad_eat(b"\x09\x09\x09\x09\x09\x09\x09\x09".to_vec(), 8), Err(Error::BadPadding));
+    assert_eq!(PKCS7::unpad_eat(b"\x01\x01\x01".to_vec(), 8), Err(Error::BadPadding));
 }
@@ -0,0 +1,52 @@
+use super::{ct_eq, Padding};
+use crate::error::{Error, Result};
+
+/// Zero padding, filling the last block up with zeros.
+///
+/// Aligned data is left as is. The padding is ambiguous: trailing zeros of
+/// the data itself are removed along with it, so this is only suitable for
+/// data that cannot end in a zero byte, such as text.
+pub struct ZeroPadding;
+
+impl Padding for ZeroPadding {
+    fn pad_into(pad: &mut [u8], _ds_byte: usize, _bs_byte: usize) {
+        pad.fill(0x00);
+    }
+
+    fn unpad_block(data: &[u8], _bs_byte: usize) -> Result<usize> {
+        let mut zeros = 0xFF;
+        let mut n = 0;
+        for &b in data.iter().rev() {
+            zeros &= ct_eq(b, 0x00);
+            n += (zeros & 1) as usize;
+        }
+        Ok(n)
+    }
+
+    fn padded_len(len: usize, bs_byte: usize) -> Result<usize> {
+        Ok(len.div_ceil(bs_byte) * bs_byte)
+    }
+
+    fn unpad_slice(data: &[u8], bs_byte: usize) -> Result<usize> {
+        match data.len() {
+            0 => Ok(0),
+            len if len < bs_byte => Err(Error::BadPadding),
+            len => Ok(len - Self::unpad_block(&data[len - bs_byte..], bs_byte)?),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+#[test]
+#[rustfmt::skip]
+fn test() {
+    assert_eq!(&ZeroPadding::pad_eat(vec![0xFF; 0], 8).unwrap(), b"");
+    assert_eq!(&ZeroPadding::pad_eat(vec![0xFF; 8], 8).unwrap(), b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF");
+    assert_eq!(&ZeroPadding::pad_eat(vec![0xFF; 1], 8).unwrap(), b"\xFF\x00\x00\x00\x00\x00\x00\x00");
+    assert_eq!(&ZeroPadding::pad_eat(vec![0xFF; 7], 8).unwrap(), b"\xFF\xFF\xFF\xFF\xFF\xFF\xFF\x00");
+    assert_eq!(ZeroPadding::unpad_eat(b"\xFF\x00\x00\x00\x00\x00\x00\x00".to_vec(), 8), Ok(vec![0xFF]));
+    assert_eq!(ZeroPadding::unpad_eat(vec![0xFF; 16], 8), Ok(vec![0xFF; 16]));
+    assert_eq!(ZeroPadding::unpad_eat(vec![], 8), Ok(vec![]));
+    // the ambiguity: trailing zeros of the data are lost
+    assert_eq!(ZeroPadding::unpad_eat(ZeroPadding::pad_eat(b"\xFF\x00".to_vec(), 8).unwrap(), 8), Ok(vec![0xFF]));
+}
@@ -0,0 +1,265 @@
+//! Encryption of directory trees into mirrored trees, with `--recursive`
+
+use aes::{
+    aes::{Array, RijndaelMode, AES128, AES192, AES256},
+    encoding::Base64,
+    mac::Cmac,
+    secret::{zeroize, SecretKey},
+    siv,
+};
+use anyhow::{anyhow, bail, Context};
+use std::{
+    ffi::{OsStr, OsString},
+    fs::{self, File, Metadata},
+    io::ErrorKind,
+    path::Path,
+};
+
+use crate::{opt::Opts, tree};
+
+/// the label of the key derivation of the name key, binding it to its use
+const NAME_LABEL: &[u8] = b"aes file names";
+
+/// the longest file name most file systems take
+const NAME_MAX: usize = 255;
+
+/// Base64 of the encrypted names, safe in file names
+const NAME_ENCODING: Base64 = Base64 {
+    url_safe: true,
+    pad: false,
+    wrap: 0,
+};
+
+/// Encrypts or decrypts every file in the input directory into the output
+/// directory, under the same names, or under the names encrypted or
+/// decrypted with `--encrypt-names`.
+pub fn recursive(opts: &Opts) -> anyhow::Result<()> {
+    if opts.input == "-" || opts.output == "-" {
+        bail!("--recursive takes an input and an output directory");
+    }
+    if opts.iv_source()?.is_some() {
+        bail!("Every file has its own IV with --recursive");
+    }
+    let (input, output) = (Path::new(&opts.input), Path::new(&opts.output));
+    if !input.is_dir() {
+        bail!("{} is not a directory", input.display());
+    }
+    fs::create_dir_all(output).with_context(|| format!("Cannot create {}", output.display()))?;
+    if output.canonicalize()?.starts_with(input.canonicalize()?) {
+        let _ = fs::remove_dir(output);
+        bail!("The output directory cannot be inside the input directory");
+    }
+
+    let mut walk = Walk {
+        opts,
+        names: match opts.encrypt_names {
+            true => Some(Names::new(opts)?),
+            false => None,
+        },
+        verb: match opts.is_encrypt() {
+            true => "encrypt",
+            false => "decrypt",
+        },
+        failed: 0,
+    };
+    walk.dir(input, output);
+    copy_metadata(&fs::metadata(input)?, &File::open(output)?)?;
+    if walk.failed > 0 {
+        bail!(
+            "{} files or directories could not be {}ed",
+            walk.failed,
+            walk.verb
+        );
+    }
+    Ok(())
+}
+
+struct Walk<'a> {
+    opts: &'a Opts,
+    names: Option<Names>,
+    verb: &'static str,
+    failed: usize,
+}
+
+impl Walk<'_> {
+    /// Mirrors the entries of `src` into `dst`, reporting those which fail
+    /// and carrying on with the rest.
+    fn dir(&mut self, src: &Path, dst: &Path) {
+        let entries = match tree::entries(src) {
+            Ok(entries) => entries,
+            Err(e) => return self.fail(src, e.into()),
+        };
+        for entry in entries {
+            let path = entry.path();
+            if let Err(e) = self.entry(&entry.file_name(), &path, dst) {
+                self.fail(&path, e);
+            }
+        }
+    }
+
+    fn entry(&mut self, name: &OsStr, src: &Path, dst: &Path) -> anyhow::Result<()> {
+        let metadata = fs::symlink_metadata(src)?;
+        if !metadata.is_dir() && !metadata.is_file() {
+            eprintln!("Skipped {}: not a file or a directory", src.display());
+            return Ok(());
+        }
+        let dst = dst.join(match (&self.names, self.opts.is_encrypt()) {
+            (None, _) => name.to_os_string(),
+            (Some(names), true) => names.encrypt(name)?,
+            (Some(names), false) => names.decrypt(name)?,
+        });
+        if metadata.is_dir() {
+            match fs::create_dir(&dst) {
+                Err(e) if e.kind() == ErrorKind::AlreadyExists && dst.is_dir() => {}
+                result => result?,
+            }
+            self.dir(src, &dst);
+            // the times last, as filling the directory changes them
+            return copy_metadata(&metadata, &File::open(&dst)?);
+        }
+
+        let input = File::open(src)?;
+        // files are only replaced once complete, so a failure leaves them as
+        // they were
+        let mut handle = None;
+        tree::replace(&dst, |output| {
+            // readable by the owner only until the permissions are copied
+            #[cfg(unix)]
+            output.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+            handle = Some(output.try_clone()?);
+            crate::process(self.opts, Box::new(input), Box::new(output.try_clone()?))
+        })?;
+        copy_metadata(&metadata, &handle.expect("written"))
+    }
+
+    fn fail(&mut self, path: &Path, e: anyhow::Error) {
+        eprintln!("Cannot {} {}: {:#}", self.verb, path.display(), e);
+        self.failed += 1;
+    }
+}
+
+/// Gives `file` the modification time and the permissions of `metadata`.
+fn copy_metadata(metadata: &Metadata, file: &File) -> anyhow::Result<()> {
+    file.set_modified(metadata.modified()?)?;
+    file.set_permissions(metadata.permissions())?;
+    Ok(())
+}
+
+/// Encrypts names deterministically with AES-SIV, so a name encrypts the
+/// same every time, under a key derived from the key of the files
+struct Names {
+    /// the name keys, the first of which encrypts, and any of which may
+    /// decrypt, as every version of a keystore key is tried
+    keys: Vec<SecretKey>,
+}
+
+impl Names {
+    fn new(opts: &Opts) -> anyhow::Result<Self> {
+        let keys = match &opts.key_name {
+            Some(name) if opts.is_encrypt() => vec![crate::active_key(opts, name)?.key],
+            Some(name) => crate::keystore(opts)?
+                .entries()
+                .iter()
+                .rev()
+                .filter(|entry| entry.name == *name)
+                .map(|entry| entry.key.clone())
+                .collect::<Vec<_>>(),
+            None if opts.key.source()?.is_some() => vec![opts.read_key(opts.mode.as_ref())?],
+            None => bail!("--encrypt-names derives the key of the names from a key or a key name"),
+        };
+        if keys.is_empty() {
+            bail!(
+                "There is no key named {}",
+                opts.key_name.as_deref().unwrap_or_default()
+            );
+        }
+        Ok(Self {
+            keys: keys.iter().map(|key| name_key(key)).collect(),
+        })
+    }
+
+    fn encrypt(&self, name: &OsStr) -> anyhow::Result<OsString> {
+        let name = name
+            .to_str()
+            .ok_or_else(|| anyhow!("its name is not valid UTF-8"))?;
+        let key = &self.keys[0];
+        let sealed = match key.len() {
+            32 => seal::<AES128>(key, name.as_bytes()),
+            48 => seal::<AES192>(key, name.as_bytes()),
+            _ => seal::<AES256>(key, name.as_bytes()),
+        };
+        let encrypted = NAME_ENCODING.encode(&sealed);
+        if encrypted.len() > NAME_MAX {
+            bail!("its name is too long to encrypt");
+        }
+        Ok(encrypted.into())
+    }
+
+    fn decrypt(&self, name: &OsStr) -> anyhow::Result<OsString> {
+        let sealed = name
+            .to_str()
+            .and_then(|name| NAME_ENCODING.decode(name.as_bytes()).ok())
+            .filter(|sealed| sealed.len() > siv::TAG_LEN)
+            .ok_or_else(|| anyhow!("its name is not encrypted"))?;
+        let name = self
+            .keys
+            .iter()
+            .find_map(|key| match key.len() {
+                32 => open::<AES128>(key, &sealed),
+                48 => open::<AES192>(key, &sealed),
+                _ => open::<AES256>(key, &sealed),
+            })
+            .ok_or_else(|| anyhow!("its name was encrypted with another key"))?;
+        Ok(String::from_utf8(name)
+            .map_err(|_| anyhow!("its name is not valid UTF-8"))?
+            .into())
+    }
+}
+
+/// The AES-SIV key of the names, twice as long as `key` as it is two keys,
+/// derived from it in counter mode with CMAC (NIST SP 800-108).
+fn name_key(key: &[u8]) -> SecretKey {
+    match key.len() {
+        16 => derive::<AES128>(key),
+        24 => derive::<AES192>(key),
+        _ => derive::<AES256>(key),
+    }
+}
+
+fn derive<M: RijndaelMode>(key: &[u8]) -> SecretKey {
+    let mut key = M::Key::from_slice(key);
+    let len = 2 * key.as_ref().len();
+    let mut out = SecretKey::new(vec![0; len]);
+    for (i, chunk) in out.chunks_mut(16).enumerate() {
+        let mut cmac = Cmac::<M>::new(&key);
+        cmac.update(&[i as u8 + 1]);
+        cmac.update(NAME_LABEL);
+        cmac.update(&[0]);
+        cmac.update(&(8 * len as u16).to_be_bytes());
+        let mut block = cmac.finalize();
+        chunk.copy_from_slice(block.as_ref());
+        zeroize(block.as_mut());
+    }
+    zeroize(key.as_mut());
+    out
+}
+
+fn seal<M: RijndaelMode>(key: &[u8], name: &[u8]) -> Vec<u8> {
+    let (mac_key, enc_key) = key.split_at(key.len() / 2);
+    let (mut mac_key, mut enc_key) = (M::Key::from_slice(mac_key), M::Key::from_slice(enc_key));
+    let mut sealed = vec![0; name.len() + siv::TAG_LEN];
+    siv::seal::<M>(&mac_key, &enc_key, &[], name, &mut sealed);
+    zeroize(mac_key.as_mut());
+    zeroize(enc_key.as_mut());
+    sealed
+}
+
+fn open<M: RijndaelMode>(key: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+    let (mac_key, enc_key) = key.split_at(key.len() / 2);
+    let (mut mac_key, mut enc_key) = (M::Key::from_slice(mac_key), M::Key::from_slice(enc_key));
+    let mut name = vec![0; sealed.len() - siv::TAG_LEN];
+    let result = siv::open::<M>(&mac_key, &enc_key, &[], sealed, &mut name);
+    zeroize(mac_key.as_mut());
+    zeroize(enc_key.as_mut());
+    result.ok().map(|_| name)
+}
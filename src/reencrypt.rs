@@ -0,0 +1,237 @@
+//! Re-encryption of containers in place, with a new key or cipher spec
+
+use aes::{
+    cipher::{CipherSpec, PaddingScheme, StreamCipher},
+    container::Header,
+    keystore::Keystore,
+    secret::{zeroize_vec, SecretKey},
+};
+use anyhow::{anyhow, bail};
+use std::{
+    fs::File,
+    io::{ErrorKind, Read, Write},
+    path::Path,
+};
+
+use crate::{commands, opt::Reencrypt, tree, CHUNK};
+
+/// Re-encrypts every container among the paths given, and reports on each.
+pub fn reencrypt(args: &Reencrypt) -> anyhow::Result<()> {
+    let mut rekey = Rekey::new(args)?;
+    let files = tree::files(&args.paths)?;
+    let (mut done, mut skipped, mut failed) = (0, 0, 0);
+    for path in &files {
+        match rekey.file(path) {
+            Ok(Outcome::Done(specs)) => {
+                match args.dry_run {
+                    true => println!("Would re-encrypt {} {}", path.display(), specs),
+                    false => println!("Re-encrypted {} {}", path.display(), specs),
+                }
+                done += 1;
+            }
+            Ok(Outcome::Skipped(reason)) => {
+                println!("Skipped {}: {}", path.display(), reason);
+                skipped += 1;
+            }
+            Err(e) => {
+                eprintln!("Cannot re-encrypt {}: {:#}", path.display(), e);
+                failed += 1;
+            }
+        }
+    }
+    println!(
+        "{} {}, {} skipped, {} failed",
+        done,
+        match args.dry_run {
+            true => "to re-encrypt",
+            false => "re-encrypted",
+        },
+        skipped,
+        failed
+    );
+    if failed > 0 {
+        bail!(
+            "{} of {} files could not be re-encrypted",
+            failed,
+            files.len()
+        );
+    }
+    Ok(())
+}
+
+enum Outcome {
+    /// re-encrypted, or would be in a dry run, from one cipher spec to
+    /// another
+    Done(String),
+    /// left as it is, for the reason given
+    Skipped(String),
+}
+
+/// The keys to re-encrypt with, read once for every container
+struct Rekey<'a> {
+    args: &'a Reencrypt,
+    /// opened once a key is needed from it
+    keystore: Option<Keystore>,
+    /// the key given with the `--old-key` options
+    old: Option<SecretKey>,
+    new: SecretKey,
+    /// the id and the name of `new` if it is from the keystore
+    new_id: Option<(Vec<u8>, String)>,
+    /// the spec of `new` if it is from the keystore
+    new_spec: Option<CipherSpec>,
+}
+
+impl<'a> Rekey<'a> {
+    fn new(args: &'a Reencrypt) -> anyhow::Result<Self> {
+        let mut rekey = Self {
+            args,
+            keystore: None,
+            old: None,
+            new: SecretKey::default(),
+            new_id: None,
+            new_spec: None,
+        };
+        match (&args.key_name, args.key.source()?) {
+            (Some(_), Some(_)) => bail!("Either a key or a key name may be given"),
+            (Some(name), None) => {
+                let entry = rekey
+                    .keystore()?
+                    .active(name)
+                    .ok_or_else(|| anyhow!("There is no active key named {}", name))?;
+                let (key, id, spec) = (entry.key.clone(), entry.id.to_vec(), entry.spec);
+                rekey.new = key;
+                rekey.new_id = Some((id, name.clone()));
+                rekey.new_spec = Some(spec);
+            }
+            (None, Some(source)) => rekey.new = source.read_any("key", &[16, 24, 32])?,
+            (None, None) => bail!("A new key is required, see --key and --key-name"),
+        }
+        rekey.old = match args.old_key.source()? {
+            Some(source) => Some(source.read_any("old key", &[16, 24, 32])?),
+            None => None,
+        };
+        Ok(rekey)
+    }
+
+    fn keystore(&mut self) -> anyhow::Result<&Keystore> {
+        if self.keystore.is_none() {
+            self.keystore = Some(commands::open_keystore(&self.args.keystore)?);
+        }
+        Ok(self.keystore.as_ref().unwrap())
+    }
+
+    fn file(&mut self, path: &Path) -> anyhow::Result<Outcome> {
+        let mut input = File::open(path)?;
+        let mut magic = Vec::new();
+        (&mut input).take(4).read_to_end(&mut magic)?;
+        if !Header::detect(&magic) {
+            return Ok(Outcome::Skipped("not a container".to_string()));
+        }
+        let header = Header::read_from((&magic[..]).chain(&mut input))?;
+        if header.tag_len > 0 {
+            bail!("authenticated containers are not supported");
+        }
+        if header.salt.is_some() || header.kdf.is_some() {
+            bail!("it is encrypted with a passphrase");
+        }
+        let spec = self.args.mode.or(self.new_spec).unwrap_or(header.spec);
+        if let (Some((id, name)), Some(key_id)) = (&self.new_id, &header.key_id) {
+            if id == key_id && spec == header.spec {
+                return Ok(Outcome::Skipped(format!("already encrypted with {}", name)));
+            }
+        }
+
+        let old = match (&self.old, &header.key_id) {
+            (Some(key), _) => key.clone(),
+            (None, Some(id)) => self
+                .keystore()?
+                .get(id)
+                .ok_or_else(|| anyhow!("its key {} is not in the keystore", hex::encode(id)))?
+                .key
+                .clone(),
+            (None, None) => bail!("it records no keystore key, see --old-key"),
+        };
+        if old.len() != header.spec.key_len() {
+            bail!(
+                "it is encrypted with {}, which the old key is not for",
+                header.spec
+            );
+        }
+        if self.new.len() != spec.key_len() {
+            bail!("the new key is not for {}", spec);
+        }
+        if self.old.is_some() && !self.args.force && !checks_key(&header.spec) {
+            bail!(
+                "{} cannot tell a wrong old key, which would replace it with garbage, see --force",
+                header.spec
+            );
+        }
+        let specs = format!("from {} to {}", header.spec, spec);
+        if self.args.dry_run {
+            return Ok(Outcome::Done(specs));
+        }
+
+        let mut dec = header.spec.build(&old, &header.iv)?;
+        let iv = spec.random_iv();
+        let mut enc = spec.build(&self.new, &iv)?;
+        dec.set_threads(self.args.threads);
+        enc.set_threads(self.args.threads);
+        let mut new_header = Header::new(spec, iv);
+        new_header.key_id = self.new_id.as_ref().map(|(id, _)| id.clone());
+        tree::replace(path, |output| {
+            output.write_all(&new_header.to_bytes())?;
+            pass(&mut input, &mut *dec, &mut *enc, output)
+        })?;
+        Ok(Outcome::Done(specs))
+    }
+}
+
+/// Whether decrypting with a wrong key fails all but rarely, on the padding,
+/// as the key given with `--old-key` is checked by nothing else. Zero
+/// padding and no padding take any plaintext, and ISO 10126 only checks the
+/// last byte.
+fn checks_key(spec: &CipherSpec) -> bool {
+    matches!(
+        spec.padding,
+        PaddingScheme::Pkcs7 | PaddingScheme::X923 | PaddingScheme::Iso7816
+    )
+}
+
+/// Decrypts `input` with `dec` and encrypts it with `enc` into `output`, a
+/// chunk at a time. The plaintext is only ever held in memory, and wiped
+/// after.
+fn pass(
+    input: &mut impl Read,
+    dec: &mut dyn StreamCipher,
+    enc: &mut dyn StreamCipher,
+    output: &mut impl Write,
+) -> anyhow::Result<()> {
+    let mut chunk = vec![0; CHUNK];
+    let mut plain = Vec::with_capacity(CHUNK + 32);
+    let mut result = Vec::with_capacity(CHUNK + 64);
+    let mut run = || -> anyhow::Result<()> {
+        loop {
+            let n = match input.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            plain.clear();
+            dec.decrypt_update(&chunk[..n], &mut plain);
+            result.clear();
+            enc.encrypt_update(&plain, &mut result);
+            output.write_all(&result)?;
+        }
+        plain.clear();
+        dec.decrypt_finalize(&mut plain)?;
+        result.clear();
+        enc.encrypt_update(&plain, &mut result);
+        enc.encrypt_finalize(&mut result)?;
+        output.write_all(&result)?;
+        Ok(())
+    };
+    let outcome = run();
+    zeroize_vec(&mut plain);
+    outcome
+}
@@ -0,0 +1,121 @@
+//! Wiping key material from memory once it is no longer needed
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+/// Overwrites `data` with zeros through volatile writes, which the optimizer
+/// cannot elide even though the data is never read again.
+pub fn zeroize<T: Copy + Default>(data: &mut [T]) {
+    for x in data.iter_mut() {
+        // SAFETY: `x` is a valid, aligned and exclusive reference
+        unsafe { core::ptr::write_volatile(x, T::default()) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Xors `b` into `a`, up to the shorter of the two.
+pub(crate) fn xor(a: &mut [u8], b: &[u8]) {
+    a.iter_mut().zip(b).for_each(|(a, b)| *a ^= b);
+}
+
+/// Compares without branching on the contents, so a tag or a check value
+/// does not leak through the time taken.
+pub(crate) fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |d, (a, b)| d | (a ^ b)) == 0
+}
+
+/// Zeroes the whole capacity of `data`, including what was truncated off,
+/// and empties it.
+#[cfg(feature = "alloc")]
+pub fn zeroize_vec(data: &mut Vec<u8>) {
+    zeroize(data);
+    data.clear();
+    for x in data.spare_capacity_mut() {
+        // SAFETY: `x` is a valid, aligned and exclusive reference
+        unsafe { core::ptr::write_volatile(x, core::mem::MaybeUninit::new(0)) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+/// Key material which is wiped from memory when dropped, and not revealed by
+/// `Debug`
+#[cfg(feature = "alloc")]
+#[derive(Clone, Default)]
+pub struct SecretKey(Vec<u8>);
+
+#[cfg(feature = "alloc")]
+impl SecretKey {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<Vec<u8>> for SecretKey {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl core::ops::Deref for SecretKey {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl core::ops::DerefMut for SecretKey {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        zeroize_vec(&mut self.0);
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl core::fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "SecretKey([REDACTED; {}])", self.0.len())
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_zeroize() {
+    let mut words = [0x2b7e1516u32; 8];
+    zeroize(&mut words);
+    assert_eq!(words, [0; 8]);
+}
+
+#[cfg(all(test, feature = "alloc"))]
+#[test]
+fn test_zeroize_vec() {
+    let mut bytes = b"a key to forget".to_vec();
+    bytes.truncate(5);
+    zeroize_vec(&mut bytes);
+    assert!(bytes.is_empty());
+    // SAFETY: the capacity was zeroed, so it is initialized
+    let spare = unsafe { core::slice::from_raw_parts(bytes.as_ptr(), bytes.capacity()) };
+    assert!(spare.iter().all(|&b| b == 0));
+}
+
+#[cfg(all(test, feature = "alloc"))]
+#[test]
+fn test_secret_key() {
+    let key = SecretKey::new(vec![0x2b; 16]);
+    assert_eq!(&key[..], &[0x2b; 16]);
+    assert_eq!(format!("{:?}", key), "SecretKey([REDACTED; 16])");
+}
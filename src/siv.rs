@@ -0,0 +1,179 @@
+//! AES-SIV (RFC 5297), deterministic authenticated encryption: the same
+//! data and associated data always encrypt to the same ciphertext under a
+//! key, so it can be looked up again, as encrypted file names are
+
+use crate::aes::{Array, BlockCipher, Rijndael, RijndaelMode};
+use crate::error::{Error, Result};
+use crate::mac::{double, Cmac};
+use crate::secret::{ct_eq, xor, zeroize};
+
+/// length of the synthetic IV in front of the ciphertext
+pub const TAG_LEN: usize = 16;
+
+/// Encrypts `data` into `out`, which must be [`TAG_LEN`] bytes longer, under
+/// `mac_key` and `enc_key`, the two halves of the RFC 5297 key. The
+/// synthetic IV at its start authenticates `data` along with each of the
+/// associated data `ad`.
+pub fn seal<M: RijndaelMode>(
+    mac_key: &M::Key,
+    enc_key: &M::Key,
+    ad: &[&[u8]],
+    data: &[u8],
+    out: &mut [u8],
+) {
+    assert_eq!(out.len(), data.len() + TAG_LEN);
+    let v = s2v::<M>(mac_key, ad, data);
+    out[..TAG_LEN].copy_from_slice(v.as_ref());
+    out[TAG_LEN..].copy_from_slice(data);
+    ctr::<M>(enc_key, &v, &mut out[TAG_LEN..]);
+}
+
+/// Decrypts what [`seal`] sealed into `out`, which must be [`TAG_LEN`] bytes
+/// shorter than `sealed`, failing with [`Error::AuthenticationFailed`] if it
+/// was not sealed under the keys with the associated data `ad`.
+pub fn open<M: RijndaelMode>(
+    mac_key: &M::Key,
+    enc_key: &M::Key,
+    ad: &[&[u8]],
+    sealed: &[u8],
+    out: &mut [u8],
+) -> Result<()> {
+    if sealed.len() < TAG_LEN {
+        return Err(Error::InvalidCiphertextLength {
+            block: TAG_LEN,
+            actual: sealed.len(),
+        });
+    }
+    assert_eq!(out.len(), sealed.len() - TAG_LEN);
+    let v = M::Block::from_slice(&sealed[..TAG_LEN]);
+    out.copy_from_slice(&sealed[TAG_LEN..]);
+    ctr::<M>(enc_key, &v, out);
+    let t = s2v::<M>(mac_key, ad, out);
+    if !ct_eq(t.as_ref(), v.as_ref()) {
+        zeroize(out);
+        return Err(Error::AuthenticationFailed);
+    }
+    Ok(())
+}
+
+/// S2V, the CMAC of the vector of the associated data and `data`
+fn s2v<M: RijndaelMode>(key: &M::Key, ad: &[&[u8]], data: &[u8]) -> M::Block {
+    let mut d = Cmac::<M>::mac(key, M::Block::zeroed().as_ref());
+    for s in ad {
+        d = double::<M>(&d);
+        xor(d.as_mut(), Cmac::<M>::mac(key, s).as_ref());
+    }
+    let mut cmac = Cmac::<M>::new(key);
+    let bs = d.as_ref().len();
+    if data.len() >= bs {
+        // the last block of the data is xored with `d`
+        let (head, tail) = data.split_at(data.len() - bs);
+        cmac.update(head);
+        xor(d.as_mut(), tail);
+    } else {
+        // the data is padded, and xored with `d` doubled
+        d = double::<M>(&d);
+        xor(d.as_mut(), data);
+        d.as_mut()[data.len()] ^= 0x80;
+    }
+    cmac.update(d.as_ref());
+    zeroize(d.as_mut());
+    cmac.finalize()
+}
+
+/// Encrypts or decrypts `data` in place in counter mode, starting from the
+/// synthetic IV `v` with the 31st and 63rd bits from the right cleared
+fn ctr<M: RijndaelMode>(key: &M::Key, v: &M::Block, data: &mut [u8]) {
+    let cipher = Rijndael::<M>::new(key);
+    let mut q = *v;
+    q.as_mut()[8] &= 0x7f;
+    q.as_mut()[12] &= 0x7f;
+    let mut keystream = M::Block::zeroed();
+    for chunk in data.chunks_mut(q.as_ref().len()) {
+        keystream = q;
+        cipher.encrypt_block(&mut keystream);
+        xor(chunk, keystream.as_ref());
+        for b in q.as_mut().iter_mut().rev() {
+            *b = b.wrapping_add(1);
+            if *b != 0 {
+                break;
+            }
+        }
+    }
+    zeroize(keystream.as_mut());
+}
+
+#[cfg(test)]
+macro_rules! impl_test_siv {
+    ($mode: ty, $key: literal, [$($ad: literal),*], $data: literal, $sealed: literal) => {{
+        use std::convert::TryInto;
+
+        let key = hex::decode($key).unwrap();
+        let (mac_key, enc_key) = key.split_at(key.len() / 2);
+        let (mac_key, enc_key) = (mac_key.try_into().unwrap(), enc_key.try_into().unwrap());
+        let ad: Vec<Vec<u8>> = vec![$(hex::decode($ad).unwrap()),*];
+        let ad = ad.iter().map(|ad| &ad[..]).collect::<Vec<_>>();
+        let data = hex::decode($data).unwrap();
+        let mut sealed = vec![0; data.len() + TAG_LEN];
+        seal::<$mode>(mac_key, enc_key, &ad, &data, &mut sealed);
+        assert_eq!(hex::encode(&sealed), $sealed);
+
+        let mut out = vec![0; data.len()];
+        open::<$mode>(mac_key, enc_key, &ad, &sealed, &mut out).unwrap();
+        assert_eq!(out, data);
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert_eq!(
+            open::<$mode>(mac_key, enc_key, &ad, &sealed, &mut out),
+            Err(Error::AuthenticationFailed)
+        );
+        assert!(out.iter().all(|&b| b == 0));
+    }};
+}
+
+#[cfg(test)]
+#[test]
+fn test_siv() {
+    use crate::aes::{AES128, AES192, AES256};
+
+    // the following test cases are from RFC 5297
+    impl_test_siv!(
+        AES128,
+        "fffefdfcfbfaf9f8f7f6f5f4f3f2f1f0f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff",
+        ["101112131415161718191a1b1c1d1e1f2021222324252627"],
+        "112233445566778899aabbccddee",
+        "85632d07c6e8f37f950acd320a2ecc9340c02b9690c4dc04daef7f6afe5c"
+    );
+    impl_test_siv!(
+        AES128,
+        "7f7e7d7c7b7a797877767574737271704041424344454647\
+         48494a4b4c4d4e4f",
+        [
+            "00112233445566778899aabbccddeeffdeaddadadeaddadaffeeddccbbaa99887766554433221100",
+            "102030405060708090a0",
+            "09f911029d74e35bd84156c5635688c0"
+        ],
+        "7468697320697320736f6d6520706c61696e7465787420746f20656e6372797074207573696e67205349562d414553",
+        "7bdb6e3b432667eb06f4d14bff2fbd0fcb900f2fddbe404326601965c889bf17\
+         dba77ceb094fa663b7a3f748ba8af829ea64ad544a272e9c485b62a3fd5c0d"
+    );
+
+    // the following test cases are from the Python `cryptography` package
+    impl_test_siv!(
+        AES192,
+        "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f\
+         202122232425262728292a2b2c2d2e2f",
+        ["6164"],
+        "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f2021222324252627",
+        "4d5d59812d8d7d3484f493af19200e73eab34efa2bdc32c718260ce12e8f4d99\
+         e1fd4ec3fa81e47619206de82dc8c0b5b47f54c16d1b8088"
+    );
+    impl_test_siv!(
+        AES256,
+        "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f\
+         202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f",
+        [],
+        "612066696c65206e616d652e747874",
+        "2ded41ca18f5c3f89bfc48d94372b0fc022868b9f2996db4af40253e070007"
+    );
+}
@@ -0,0 +1,210 @@
+//! Where the key and IV are read from. Apart from `--key` and `--iv`, the
+//! sources keep them out of process listings and shell history.
+
+use aes::{cipher::CipherSpec, encoding::Base64, secret::SecretKey};
+use anyhow::{anyhow, bail, Context};
+use std::{fs, io::Read};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// given on the command line, in hex for keys and IVs
+    Arg(String),
+    /// a file holding the value raw, in hex or in Base64
+    File(String),
+    /// an environment variable holding the value in hex or in Base64
+    Env(String),
+    /// an inherited file descriptor to read the value from, like a file
+    Fd(i32),
+    /// asked for on the terminal, without echoing the input
+    Prompt,
+}
+
+impl Source {
+    /// Reads the `name`d value, which `spec` needs `len` bytes of.
+    pub fn read(&self, name: &str, spec: &CipherSpec, len: usize) -> anyhow::Result<SecretKey> {
+        if len == 0 {
+            bail!("{} takes no {}", spec, name);
+        }
+        if let Source::Arg(value) = self {
+            let value =
+                SecretKey::new(hex::decode(value).with_context(|| format!("Invalid {}", name))?);
+            if value.len() != len {
+                bail!(
+                    "The {} must be {} bytes ({} hex digits) for {}, not {}",
+                    name,
+                    len,
+                    2 * len,
+                    spec,
+                    value.len()
+                );
+            }
+            return Ok(value);
+        }
+        let prompt = format!("Enter the {} for {}: ", name, spec);
+        decode(&self.read_raw(name, &prompt)?, len, self.is_binary()).ok_or_else(|| {
+            anyhow!(
+                "The {} must be {} bytes for {}, given raw, in hex or in Base64",
+                name,
+                len,
+                spec
+            )
+        })
+    }
+
+    /// Reads the `name`d value of any of the lengths `lens`, for when no
+    /// cipher spec tells which.
+    pub fn read_any(&self, name: &str, lens: &[usize]) -> anyhow::Result<SecretKey> {
+        let mut lengths = lens.iter().map(|len| len.to_string()).collect::<Vec<_>>();
+        let last = lengths.pop().unwrap_or_default();
+        let lengths = match lengths.is_empty() {
+            true => last,
+            false => format!("{} or {}", lengths.join(", "), last),
+        };
+        let mut values = match self {
+            Source::Arg(value) => {
+                vec![SecretKey::new(
+                    hex::decode(value).with_context(|| format!("Invalid {}", name))?,
+                )]
+            }
+            _ => {
+                let value = self.read_raw(name, &format!("Enter the {}: ", name))?;
+                lens.iter()
+                    .filter_map(|&len| decode(&value, len, self.is_binary()))
+                    .collect()
+            }
+        };
+        values.retain(|value| lens.contains(&value.len()));
+        match values.len() {
+            1 => Ok(values.remove(0)),
+            0 => bail!("The {} must be {} bytes long", name, lengths),
+            _ => bail!(
+                "The {} could be read as more than one of {} bytes",
+                name,
+                lengths
+            ),
+        }
+    }
+
+    /// Reads a passphrase, up to the end of the first line. Prompts for it
+    /// twice if `confirm` is set, as a typo would go unnoticed.
+    pub fn read_passphrase(&self, confirm: bool) -> anyhow::Result<SecretKey> {
+        let pass = self.read_raw("passphrase", "Passphrase: ")?;
+        let mut end = pass.iter().position(|&c| c == b'\n').unwrap_or(pass.len());
+        if end < pass.len() && end > 0 && pass[end - 1] == b'\r' {
+            end -= 1;
+        }
+        let pass = SecretKey::new(pass[..end].to_vec());
+        if confirm && *self == Source::Prompt {
+            let again = self.read_raw("passphrase", "Passphrase again: ")?;
+            if *again != *pass {
+                bail!("The passphrases do not match");
+            }
+        }
+        if pass.is_empty() {
+            bail!("The passphrase is empty");
+        }
+        Ok(pass)
+    }
+
+    /// Whether the value may be raw bytes, which only files and descriptors
+    /// hold, rather than text.
+    fn is_binary(&self) -> bool {
+        matches!(self, Source::File(_) | Source::Fd(_))
+    }
+
+    /// Reads the `name`d value as given, asking with `prompt` on the
+    /// terminal.
+    fn read_raw(&self, name: &str, prompt: &str) -> anyhow::Result<SecretKey> {
+        Ok(SecretKey::new(match self {
+            Source::Arg(value) => value.clone().into_bytes(),
+            Source::File(path) => {
+                fs::read(path).with_context(|| format!("Cannot read the {} from {}", name, path))?
+            }
+            Source::Env(var) => std::env::var(var)
+                .with_context(|| format!("Cannot read the {} from ${}", name, var))?
+                .into_bytes(),
+            Source::Fd(fd) => read_fd(*fd)
+                .with_context(|| format!("Cannot read the {} from descriptor {}", name, fd))?,
+            Source::Prompt => rpassword::read_password_from_tty(Some(prompt))?.into_bytes(),
+        }))
+    }
+}
+
+/// Decodes `value` of `len` bytes, given in hex or in Base64, or raw if
+/// `raw` is set.
+///
+/// Text in hex or in Base64 is taken as such whatever its length, so a key
+/// of the wrong size is rejected rather than read raw. Only values which are
+/// neither are raw.
+pub fn decode(value: &[u8], len: usize, raw: bool) -> Option<SecretKey> {
+    let text = std::str::from_utf8(value)
+        .ok()
+        .map(str::trim)
+        .filter(|text| !text.is_empty());
+    // hex digits are valid Base64 too, but a Base64 value of random bytes is
+    // next to never all hex digits
+    let decoded = text.and_then(|text| match text.bytes().all(|c| c.is_ascii_hexdigit()) {
+        true => hex::decode(text).ok(),
+        false => Base64::STANDARD.decode(text.as_bytes()).ok(),
+    });
+    match decoded {
+        Some(decoded) => Some(SecretKey::new(decoded)).filter(|value| value.len() == len),
+        None if raw && value.len() == len => Some(SecretKey::new(value.to_vec())),
+        None => None,
+    }
+}
+
+#[cfg(unix)]
+fn read_fd(fd: i32) -> std::io::Result<Vec<u8>> {
+    use std::{fs::File, mem::ManuallyDrop, os::unix::io::FromRawFd};
+
+    extern "C" {
+        fn fcntl(fd: i32, cmd: i32, ...) -> i32;
+    }
+    /// the command of `fcntl` reading the flags of a descriptor, the same on
+    /// every Unix
+    const F_GETFD: i32 = 1;
+
+    // SAFETY: `fcntl` only looks the descriptor up, and fails with `EBADF` if
+    // it is not open
+    if unsafe { fcntl(fd, F_GETFD) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: the descriptor is open, as checked above, and nothing else in
+    // the process closes it while it is read. The file is never dropped, so
+    // the descriptor is left open for the parent to close, and one the
+    // process holds itself, like standard input, stays usable.
+    let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+    let mut value = Vec::new();
+    file.read_to_end(&mut value)?;
+    Ok(value)
+}
+
+#[cfg(not(unix))]
+fn read_fd(_: i32) -> std::io::Result<Vec<u8>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "file descriptors are only supported on Unix",
+    ))
+}
+
+#[cfg(test)]
+#[test]
+fn test_decode() {
+    let key = (0..16).collect::<Vec<u8>>();
+    let decoded = |value: &[u8], len, raw| decode(value, len, raw).map(|value| value.to_vec());
+    assert_eq!(decoded(&key, 16, true), Some(key.clone()));
+    assert_eq!(decoded(&key, 16, false), None);
+    let hex = format!("{}\n", hex::encode(&key));
+    assert_eq!(decoded(hex.as_bytes(), 16, false), Some(key.clone()));
+    let base64 = format!(" {}\n", Base64::STANDARD.encode(&key));
+    assert_eq!(decoded(base64.as_bytes(), 16, false), Some(key.clone()));
+
+    // 32 hex digits are a 16 byte key, not 32 raw bytes nor 24 in Base64
+    let hex = hex::encode(&key);
+    assert_eq!(decoded(hex.as_bytes(), 16, true), Some(key.clone()));
+    assert_eq!(decoded(hex.as_bytes(), 24, true), None);
+    assert_eq!(decoded(hex.as_bytes(), 32, true), None);
+    assert_eq!(decoded(&key[..15], 16, true), None);
+    assert_eq!(decoded(b"not a key at all", 24, true), None);
+}
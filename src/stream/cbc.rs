@@ -1,53 +1,71 @@
 use super::{Block, Streamer};
 use crate::{
-    aes::{RijndaelCryptor, RijndaelMode},
+    aes::{BlockCipher, Rijndael, RijndaelMode},
     padding::Padding,
 };
-use std::marker::PhantomData;
-
-pub struct CipherBlockChaining<M: RijndaelMode, P: Padding>
-where
-    [(); M::NR_KEY * M::NB_WORDS]:,
-    [(); M::NB_WORDS * 4]:,
-{
-    key: [u32; M::NR_KEY * M::NB_WORDS],
+use core::marker::PhantomData;
+
+/// number of ciphertext blocks kept on the stack by a batched decryption
+const BATCH: usize = 64;
+
+pub struct CipherBlockChaining<M: RijndaelMode, P: Padding> {
+    cipher: Rijndael<M>,
     acc: Block<M>,
     _p: PhantomData<P>,
 }
 
-impl<M: RijndaelMode, P: Padding> Streamer<M, P> for CipherBlockChaining<M, P>
-where
-    [(); M::NR_KEY * M::NB_WORDS]:,
-    [(); M::NB_WORDS * 4]:,
-    [(); M::NK_WORDS * 4]:,
-{
-    fn new_with_ext_key(iv: super::Block<M>, key: [u32; M::NR_KEY * M::NB_WORDS]) -> Self {
+impl<M: RijndaelMode, P: Padding> Streamer<M, P> for CipherBlockChaining<M, P> {
+    fn new_with_ext_key(iv: super::Block<M>, key: M::KeySchedule) -> Self {
         Self {
-            key,
+            cipher: Rijndael::new_with_ext_key(key),
             acc: iv,
-            _p: PhantomData::default(),
+            _p: PhantomData,
         }
     }
 
     fn stream_encrypt_iter(&mut self, data: &super::Block<M>) -> super::Block<M> {
-        for i in 0..data.len() {
-            self.acc[i] ^= data[i];
+        for (a, d) in self.acc.as_mut().iter_mut().zip(data.as_ref()) {
+            *a ^= d;
         }
-        let res =
-            RijndaelCryptor::<M>::new_with_raw_data_key(&self.acc, &self.key).encrypt_to_arr();
-        self.acc = res.clone();
-        res
+        self.cipher.encrypt_block(&mut self.acc);
+        self.acc
     }
 
     fn stream_decrypt_iter(&mut self, data: &super::Block<M>) -> super::Block<M> {
-        let mut res = RijndaelCryptor::<M>::new_with_raw_data_key(data.as_slice(), &self.key)
-            .decrypt_to_arr();
-        for i in 0..data.len() {
-            res[i] ^= self.acc[i];
+        let mut res = *data;
+        self.cipher.decrypt_block(&mut res);
+        for (r, a) in res.as_mut().iter_mut().zip(self.acc.as_ref()) {
+            *r ^= a;
         }
-        self.acc = data.clone();
+        self.acc = *data;
         res
     }
+
+    fn stream_decrypt_blocks(&mut self, data: &mut [super::Block<M>]) {
+        // each plaintext block only depends on two ciphertext blocks, so the
+        // block decryptions are independent and can be batched
+        for part in data.chunks_mut(BATCH) {
+            let mut chain = [self.acc; BATCH];
+            chain[1..part.len()].copy_from_slice(&part[..part.len() - 1]);
+            let last = part[part.len() - 1];
+            self.cipher.decrypt_blocks(part);
+            for (block, prev) in part.iter_mut().zip(chain.iter()) {
+                for (r, a) in block.as_mut().iter_mut().zip(prev.as_ref()) {
+                    *r ^= a;
+                }
+            }
+            self.acc = last;
+        }
+    }
+
+    fn fork_decrypt(&self, prev: Option<&super::Block<M>>) -> Option<Self> {
+        // each ciphertext block is the chaining value of the next
+        Some(Self {
+            cipher: self.cipher.clone(),
+            acc: *prev.unwrap_or(&self.acc),
+            _p: PhantomData,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -171,3 +189,9 @@ pub fn test() {
     impl_test_block_de!(cipher : "39f23369a9d9bacfa530e26304231461" => "30c81c46a35ce411e5fbc1191a0a52ef");
     impl_test_block_de!(cipher : "b2eb05e2c39be9fcda6c19078c6a9d1b" => "f69f2445df4f9b17ad2b417be66c3710");
 }
+
+#[cfg(test)]
+#[test]
+pub fn test_blocks() {
+    super::check_blocks::<CipherBlockChaining<crate::aes::AES128, crate::padding::PKCS7>>();
+}
@@ -1,42 +1,57 @@
 use super::Streamer;
 use crate::{
-    aes::{RijndaelCryptor, RijndaelMode},
+    aes::{BlockCipher, Rijndael, RijndaelMode},
     padding::Padding,
 };
+use core::marker::PhantomData;
 #[cfg(test)]
 use std::convert::TryInto;
-use std::marker::PhantomData;
-
-pub struct ElectronicCodeBook<M: RijndaelMode, P: Padding>
-where
-    [(); M::NR_KEY * M::NB_WORDS]:,
-{
-    key: [u32; M::NR_KEY * M::NB_WORDS],
-    _m: PhantomData<M>,
+
+pub struct ElectronicCodeBook<M: RijndaelMode, P: Padding> {
+    cipher: Rijndael<M>,
     _p: PhantomData<P>,
 }
 
-impl<M: RijndaelMode, P: Padding> Streamer<M, P> for ElectronicCodeBook<M, P>
-where
-    [(); M::NR_KEY * M::NB_WORDS]:,
-    [(); <M as RijndaelMode>::NB_WORDS * 4]:,
-    [(); M::NK_WORDS]:,
-    [(); M::NK_WORDS * 4]:,
-{
-    fn new_with_ext_key(_: super::Block<M>, key: [u32; M::NR_KEY * M::NB_WORDS]) -> Self {
+impl<M: RijndaelMode, P: Padding> Streamer<M, P> for ElectronicCodeBook<M, P> {
+    fn new_with_ext_key(_: super::Block<M>, key: M::KeySchedule) -> Self {
         Self {
-            key,
-            _m: PhantomData::default(),
-            _p: PhantomData::default(),
+            cipher: Rijndael::new_with_ext_key(key),
+            _p: PhantomData,
         }
     }
 
     fn stream_encrypt_iter(&mut self, data: &super::Block<M>) -> super::Block<M> {
-        RijndaelCryptor::<M>::new_with_raw_data_key(data.as_slice(), &self.key).encrypt_to_arr()
+        let mut block = *data;
+        self.cipher.encrypt_block(&mut block);
+        block
     }
 
     fn stream_decrypt_iter(&mut self, data: &super::Block<M>) -> super::Block<M> {
-        RijndaelCryptor::<M>::new_with_raw_data_key(data.as_slice(), &self.key).decrypt_to_arr()
+        let mut block = *data;
+        self.cipher.decrypt_block(&mut block);
+        block
+    }
+
+    fn stream_encrypt_blocks(&mut self, data: &mut [super::Block<M>]) {
+        self.cipher.encrypt_blocks(data);
+    }
+
+    fn stream_decrypt_blocks(&mut self, data: &mut [super::Block<M>]) {
+        self.cipher.decrypt_blocks(data);
+    }
+
+    fn fork_encrypt(&self, _: Option<&super::Block<M>>) -> Option<Self> {
+        Some(Self {
+            cipher: self.cipher.clone(),
+            _p: PhantomData,
+        })
+    }
+
+    fn fork_decrypt(&self, _: Option<&super::Block<M>>) -> Option<Self> {
+        Some(Self {
+            cipher: self.cipher.clone(),
+            _p: PhantomData,
+        })
     }
 }
 
@@ -158,3 +173,9 @@ pub fn test() {
     impl_test_block_de!(cipher : "b6ed21b99ca6f4f9f153e7b1beafed1d" => "30c81c46a35ce411e5fbc1191a0a52ef");
     impl_test_block_de!(cipher : "23304b7a39f9f3ff067d8d8f9e24ecc7" => "f69f2445df4f9b17ad2b417be66c3710");
 }
+
+#[cfg(test)]
+#[test]
+pub fn test_blocks() {
+    super::check_blocks::<ElectronicCodeBook<crate::aes::AES128, crate::padding::PKCS7>>();
+}
@@ -0,0 +1,270 @@
+use super::{in_place, Block, Streamer};
+use crate::{
+    aes::{Array, RijndaelMode},
+    error::{Error, Result},
+    padding::Padding,
+};
+use core::marker::PhantomData;
+
+/// An encryption or decryption fed a message in chunks, see [`Encryptor`]
+/// and [`Decryptor`]
+pub trait Incremental {
+    /// block size in bytes
+    const BLOCK: usize;
+
+    /// Processes the next chunk `data` into `out`, returning the number of
+    /// bytes written. `out` must hold at least `data.len()` plus one block.
+    fn update(&mut self, data: &[u8], out: &mut [u8]) -> usize;
+    /// Processes the end of the message into `out`, returning the number of
+    /// bytes written. `out` must hold at least one block.
+    fn finalize(&mut self, out: &mut [u8]) -> Result<usize>;
+}
+
+/// Encrypts a message handed over in chunks of any size.
+///
+/// Every [`Self::update`] encrypts the whole blocks available so far and
+/// buffers the rest, [`Self::finalize`] pads and encrypts the last block.
+pub struct Encryptor<M: RijndaelMode, P: Padding, S: Streamer<M, P>> {
+    streamer: S,
+    buf: Block<M>,
+    len: usize,
+    _p: PhantomData<P>,
+}
+
+impl<M: RijndaelMode, P: Padding, S: Streamer<M, P>> Encryptor<M, P, S> {
+    pub fn new(streamer: S) -> Self {
+        Self {
+            streamer,
+            buf: Block::<M>::zeroed(),
+            len: 0,
+            _p: PhantomData,
+        }
+    }
+
+    /// Encrypts the next chunk `data` into `out`, returning the number of
+    /// bytes written. `out` must hold at least `data.len()` plus one block.
+    pub fn update(&mut self, data: &[u8], out: &mut [u8]) -> usize {
+        self.update_with(data, out, |s, d| {
+            in_place::<M>(d, |blocks| s.stream_encrypt_blocks(blocks))
+        })
+    }
+
+    /// [`Self::update`], encrypting the aligned blocks with `blocks`.
+    pub fn update_with(
+        &mut self,
+        mut data: &[u8],
+        out: &mut [u8],
+        blocks: impl FnOnce(&mut S, &mut [u8]),
+    ) -> usize {
+        let bs = M::NB_WORDS * 4;
+        if self.len + data.len() < bs {
+            self.buf.as_mut()[self.len..self.len + data.len()].copy_from_slice(data);
+            self.len += data.len();
+            return 0;
+        }
+        let mut written = 0;
+        if self.len > 0 {
+            let (head, rest) = data.split_at(bs - self.len);
+            self.buf.as_mut()[self.len..].copy_from_slice(head);
+            out[..bs].copy_from_slice(self.buf.as_ref());
+            written = bs;
+            data = rest;
+        }
+        let whole = data.len() - data.len() % bs;
+        out[written..written + whole].copy_from_slice(&data[..whole]);
+        written += whole;
+        blocks(&mut self.streamer, &mut out[..written]);
+        self.len = data.len() - whole;
+        self.buf.as_mut()[..self.len].copy_from_slice(&data[whole..]);
+        written
+    }
+
+    /// Pads and encrypts the rest of the message into `out`, returning the
+    /// number of bytes written. `out` must hold at least one block.
+    pub fn finalize(&mut self, out: &mut [u8]) -> Result<usize> {
+        let len = core::mem::take(&mut self.len);
+        out[..len].copy_from_slice(&self.buf.as_ref()[..len]);
+        self.streamer.encrypt_padded_in_place(out, len)
+    }
+
+    pub fn into_inner(self) -> S {
+        self.streamer
+    }
+}
+
+/// Decrypts a message handed over in chunks of any size.
+///
+/// The last block is held back by [`Self::update`], as only
+/// [`Self::finalize`] knows it ends the message and removes the padding.
+pub struct Decryptor<M: RijndaelMode, P: Padding, S: Streamer<M, P>> {
+    streamer: S,
+    buf: Block<M>,
+    len: usize,
+    total: usize,
+    _p: PhantomData<P>,
+}
+
+impl<M: RijndaelMode, P: Padding, S: Streamer<M, P>> Decryptor<M, P, S> {
+    pub fn new(streamer: S) -> Self {
+        Self {
+            streamer,
+            buf: Block::<M>::zeroed(),
+            len: 0,
+            total: 0,
+            _p: PhantomData,
+        }
+    }
+
+    /// Decrypts the next chunk `data` into `out`, returning the number of
+    /// bytes written. `out` must hold at least `data.len()` plus one block.
+    pub fn update(&mut self, data: &[u8], out: &mut [u8]) -> usize {
+        self.update_with(data, out, |s, d| {
+            in_place::<M>(d, |blocks| s.stream_decrypt_blocks(blocks))
+        })
+    }
+
+    /// [`Self::update`], decrypting the aligned blocks with `blocks`.
+    pub fn update_with(
+        &mut self,
+        data: &[u8],
+        out: &mut [u8],
+        blocks: impl FnOnce(&mut S, &mut [u8]),
+    ) -> usize {
+        let bs = M::NB_WORDS * 4;
+        self.total += data.len();
+        if self.len + data.len() <= bs {
+            self.buf.as_mut()[self.len..self.len + data.len()].copy_from_slice(data);
+            self.len += data.len();
+            return 0;
+        }
+        // the buffer is a whole block followed by more data, so it is not the
+        // last one, and neither are the blocks of `data` but its final one
+        let (head, data) = data.split_at(bs - self.len);
+        self.buf.as_mut()[self.len..].copy_from_slice(head);
+        out[..bs].copy_from_slice(self.buf.as_ref());
+        let keep = match data.len() % bs {
+            0 => bs,
+            r => r,
+        };
+        let whole = data.len() - keep;
+        out[bs..bs + whole].copy_from_slice(&data[..whole]);
+        blocks(&mut self.streamer, &mut out[..bs + whole]);
+        self.len = keep;
+        self.buf.as_mut()[..keep].copy_from_slice(&data[whole..]);
+        bs + whole
+    }
+
+    /// Decrypts the held back block into `out` and removes the padding,
+    /// returning the number of bytes written. `out` must hold at least one
+    /// block.
+    pub fn finalize(&mut self, out: &mut [u8]) -> Result<usize> {
+        let bs = M::NB_WORDS * 4;
+        let len = core::mem::take(&mut self.len);
+        let total = core::mem::take(&mut self.total);
+        if !total.is_multiple_of(bs) {
+            return Err(Error::InvalidCiphertextLength {
+                block: bs,
+                actual: total,
+            });
+        }
+        out[..len].copy_from_slice(&self.buf.as_ref()[..len]);
+        self.streamer.decrypt_padded_in_place(&mut out[..len])
+    }
+
+    pub fn into_inner(self) -> S {
+        self.streamer
+    }
+}
+
+impl<M: RijndaelMode, P: Padding, S: Streamer<M, P>> Incremental for Encryptor<M, P, S> {
+    const BLOCK: usize = M::NB_WORDS * 4;
+
+    fn update(&mut self, data: &[u8], out: &mut [u8]) -> usize {
+        Encryptor::update(self, data, out)
+    }
+
+    fn finalize(&mut self, out: &mut [u8]) -> Result<usize> {
+        Encryptor::finalize(self, out)
+    }
+}
+
+impl<M: RijndaelMode, P: Padding, S: Streamer<M, P>> Incremental for Decryptor<M, P, S> {
+    const BLOCK: usize = M::NB_WORDS * 4;
+
+    fn update(&mut self, data: &[u8], out: &mut [u8]) -> usize {
+        Decryptor::update(self, data, out)
+    }
+
+    fn finalize(&mut self, out: &mut [u8]) -> Result<usize> {
+        Decryptor::finalize(self, out)
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+macro_rules! impl_test_incremental {
+    ($st: ident, $pad: ident) => {{
+        use crate::aes::AES128;
+        use crate::padding::$pad;
+        use crate::stream::$st;
+
+        let iv = [0x00; 16];
+        let key = [0x2b; 16];
+        for len in [0, 1, 15, 16, 17, 64, 100, 1000] {
+            // no trailing zeros, which zero padding would lose
+            let plain = (0..len).map(|i| i as u8 | 0x80).collect::<Vec<_>>();
+            let expected = $st::<AES128, $pad>::new(iv, key)
+                .stream_encrypt(&plain)
+                .unwrap();
+            for chunk in [1, 3, 16, 17, 100] {
+                let mut enc = Encryptor::new($st::<AES128, $pad>::new(iv, key));
+                let mut cipher = Vec::new();
+                let mut out = [0u8; 128];
+                for part in plain.chunks(chunk) {
+                    let n = enc.update(part, &mut out);
+                    cipher.extend_from_slice(&out[..n]);
+                }
+                let n = enc.finalize(&mut out).unwrap();
+                cipher.extend_from_slice(&out[..n]);
+                assert_eq!(cipher, expected);
+
+                let mut dec = Decryptor::new($st::<AES128, $pad>::new(iv, key));
+                let mut decrypted = Vec::new();
+                for part in cipher.chunks(chunk) {
+                    let n = dec.update(part, &mut out);
+                    decrypted.extend_from_slice(&out[..n]);
+                }
+                let n = dec.finalize(&mut out).unwrap();
+                decrypted.extend_from_slice(&out[..n]);
+                assert_eq!(decrypted, plain);
+            }
+        }
+    }};
+}
+
+#[cfg(all(test, feature = "alloc"))]
+#[test]
+fn test_incremental() {
+    impl_test_incremental!(CipherBlockChaining, PKCS7);
+    impl_test_incremental!(ElectronicCodeBook, X923);
+    impl_test_incremental!(CipherBlockChaining, ZeroPadding);
+}
+
+#[cfg(test)]
+#[test]
+fn test_truncated() {
+    use crate::aes::AES128;
+    use crate::padding::PKCS7;
+    use crate::stream::CipherBlockChaining;
+
+    let mut out = [0u8; 64];
+    let mut dec = Decryptor::new(CipherBlockChaining::<AES128, PKCS7>::new([0; 16], [0; 16]));
+    dec.update(&[0; 40], &mut out);
+    assert_eq!(
+        dec.finalize(&mut out),
+        Err(Error::InvalidCiphertextLength {
+            block: 16,
+            actual: 40
+        })
+    );
+    assert_eq!(dec.finalize(&mut out), Err(Error::BadPadding));
+}
@@ -0,0 +1,274 @@
+use super::{Decryptor, Encryptor, Incremental};
+use std::io::{self, ErrorKind, Read, Write};
+
+/// size of the chunks read from or handed to the inner reader or writer
+const CHUNK: usize = 1 << 16;
+
+/// Encrypts or decrypts everything written to it with `C` into `W`.
+///
+/// [`Self::finish`] must be called once all data is written, to process the
+/// last block, and to learn about errors in it; dropping the writer instead
+/// loses the end of the message.
+pub struct CipherWriter<W: Write, C: Incremental> {
+    inner: W,
+    cipher: C,
+    /// output not yet accepted by `inner`
+    pending: Vec<u8>,
+}
+
+/// Writes the ciphertext of the plaintext written to it
+pub type EncryptWriter<W, M, P, S> = CipherWriter<W, Encryptor<M, P, S>>;
+/// Writes the plaintext of the ciphertext written to it
+pub type DecryptWriter<W, M, P, S> = CipherWriter<W, Decryptor<M, P, S>>;
+
+impl<W: Write, C: Incremental> CipherWriter<W, C> {
+    pub fn new(inner: W, cipher: C) -> Self {
+        Self {
+            inner,
+            cipher,
+            pending: Vec::with_capacity(CHUNK + C::BLOCK),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Hands the pending output to `inner`, keeping whatever it did not
+    /// accept when it fails.
+    fn write_pending(&mut self) -> io::Result<()> {
+        let mut written = 0;
+        let res = loop {
+            if written == self.pending.len() {
+                break Ok(());
+            }
+            match self.inner.write(&self.pending[written..]) {
+                Ok(0) => break Err(ErrorKind::WriteZero.into()),
+                Ok(n) => written += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => break Err(e),
+            }
+        };
+        self.pending.drain(..written);
+        res
+    }
+
+    /// Processes the last block and writes everything out, returning the
+    /// inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_pending()?;
+        self.pending.resize(C::BLOCK, 0);
+        let res = self.cipher.finalize(&mut self.pending);
+        self.pending.truncate(*res.as_ref().unwrap_or(&0));
+        res?;
+        self.write_pending()?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write, C: Incremental> Write for CipherWriter<W, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // nothing is accepted before the previous output is written, which
+        // bounds the buffered output to a chunk
+        self.write_pending()?;
+        let data = &buf[..buf.len().min(CHUNK)];
+        self.pending.resize(data.len() + C::BLOCK, 0);
+        let n = self.cipher.update(data, &mut self.pending);
+        self.pending.truncate(n);
+        Ok(data.len())
+    }
+
+    /// Writes out the output so far. The last, incomplete, block is only
+    /// written by [`CipherWriter::finish`].
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_pending()?;
+        self.inner.flush()
+    }
+}
+
+/// Encrypts or decrypts everything read from `R` with `C`.
+///
+/// Errors in the last block, such as bad padding, are returned by the read
+/// reaching the end of `R`.
+pub struct CipherReader<R: Read, C: Incremental> {
+    inner: R,
+    cipher: C,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+/// Reads the ciphertext of the plaintext read from `R`
+pub type EncryptReader<R, M, P, S> = CipherReader<R, Encryptor<M, P, S>>;
+/// Reads the plaintext of the ciphertext read from `R`
+pub type DecryptReader<R, M, P, S> = CipherReader<R, Decryptor<M, P, S>>;
+
+impl<R: Read, C: Incremental> CipherReader<R, C> {
+    pub fn new(inner: R, cipher: C) -> Self {
+        Self {
+            inner,
+            cipher,
+            input: vec![0; CHUNK],
+            output: Vec::with_capacity(CHUNK + C::BLOCK),
+            pos: 0,
+            done: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads and processes input until there is output or the input ends.
+    fn fill(&mut self) -> io::Result<()> {
+        while self.pos == self.output.len() && !self.done {
+            let n = self.inner.read(&mut self.input)?;
+            self.pos = 0;
+            self.output.resize(n + C::BLOCK, 0);
+            let written = match n {
+                0 => {
+                    self.done = true;
+                    let res = self.cipher.finalize(&mut self.output);
+                    if res.is_err() {
+                        self.output.clear();
+                    }
+                    res?
+                }
+                n => self.cipher.update(&self.input[..n], &mut self.output),
+            };
+            self.output.truncate(written);
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read, C: Incremental> Read for CipherReader<R, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.fill()?;
+        let n = buf.len().min(self.output.len() - self.pos);
+        buf[..n].copy_from_slice(&self.output[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+fn plain(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7) as u8).collect()
+}
+
+/// A writer accepting at most 5 bytes per call, interrupted every other one
+#[cfg(test)]
+struct Trickle(Vec<u8>, bool);
+
+#[cfg(test)]
+impl Write for Trickle {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.1 = !self.1;
+        if self.1 {
+            return Err(ErrorKind::Interrupted.into());
+        }
+        let n = buf.len().min(5);
+        self.0.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_writer() {
+    use crate::aes::AES128;
+    use crate::padding::PKCS7;
+    use crate::stream::{CipherBlockChaining, Streamer};
+
+    let cbc = || CipherBlockChaining::<AES128, PKCS7>::new([0x01; 16], [0x2b; 16]);
+    for len in [0, 1, 15, 16, 17, 100, 1000] {
+        let plain = plain(len);
+        let expected = cbc().stream_encrypt(&plain).unwrap();
+        for chunk in [1, 3, 17, 1000] {
+            let mut w = EncryptWriter::new(Trickle(Vec::new(), false), Encryptor::new(cbc()));
+            for part in plain.chunks(chunk) {
+                w.write_all(part).unwrap();
+            }
+            let cipher = w.finish().unwrap().0;
+            assert_eq!(cipher, expected);
+
+            let mut w = DecryptWriter::new(Vec::new(), Decryptor::new(cbc()));
+            for part in cipher.chunks(chunk) {
+                w.write_all(part).unwrap();
+            }
+            assert_eq!(w.finish().unwrap(), plain);
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_reader() {
+    use crate::aes::AES128;
+    use crate::padding::X923;
+    use crate::stream::{ElectronicCodeBook, Streamer};
+    use std::io::Cursor;
+
+    let ecb = || ElectronicCodeBook::<AES128, X923>::new([0x00; 16], [0x2b; 16]);
+    for len in [0, 1, 15, 16, 17, 100, 1000] {
+        let plain = plain(len);
+        let expected = ecb().stream_encrypt(&plain).unwrap();
+        for chunk in [1, 3, 17, 1000] {
+            let mut r = EncryptReader::new(Cursor::new(&plain), Encryptor::new(ecb()));
+            let mut cipher = Vec::new();
+            let mut buf = vec![0; chunk];
+            loop {
+                match r.read(&mut buf).unwrap() {
+                    0 => break,
+                    n => cipher.extend_from_slice(&buf[..n]),
+                }
+            }
+            assert_eq!(cipher, expected);
+
+            let mut r = DecryptReader::new(Cursor::new(&cipher), Decryptor::new(ecb()));
+            let mut decrypted = Vec::new();
+            r.read_to_end(&mut decrypted).unwrap();
+            assert_eq!(decrypted, plain);
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_errors() {
+    use crate::aes::AES128;
+    use crate::padding::PKCS7;
+    use crate::stream::{CipherBlockChaining, Streamer};
+    use std::io::Cursor;
+
+    let cbc = || CipherBlockChaining::<AES128, PKCS7>::new([0x01; 16], [0x2b; 16]);
+    let mut cipher = cbc().stream_encrypt(&plain(40)).unwrap();
+
+    let mut r = DecryptReader::new(Cursor::new(&cipher[..40]), Decryptor::new(cbc()));
+    let err = r.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    *cipher.last_mut().unwrap() ^= 1;
+    let mut w = DecryptWriter::new(Vec::new(), Decryptor::new(cbc()));
+    w.write_all(&cipher).unwrap();
+    assert_eq!(w.finish().unwrap_err().kind(), ErrorKind::InvalidData);
+}
@@ -1,57 +1,291 @@
-use std::convert::TryInto;
-
-use crate::{aes::RijndaelMode, padding::Padding};
-
-#[allow(type_alias_bounds)]
-type Block<M: RijndaelMode> = [u8; <M as RijndaelMode>::NB_WORDS * 4];
-#[allow(type_alias_bounds)]
-type KeyBlock<M: RijndaelMode> = [u8; <M as RijndaelMode>::NK_WORDS * 4];
-
-pub trait Streamer<M: RijndaelMode, P: Padding>
-where
-    [(); <M as RijndaelMode>::NB_WORDS * 4]:,
-    [(); M::NR_KEY * M::NB_WORDS]:,
-    [(); M::NK_WORDS]:,
-    [(); M::NK_WORDS * 4]:,
-{
-    fn new(iv: Block<M>, key: KeyBlock<M>) -> Self
+use crate::{
+    aes::{Array, RijndaelMode},
+    error::{Error, Result},
+    padding::Padding,
+};
+#[cfg(feature = "alloc")]
+use alloc::{borrow::ToOwned, vec::Vec};
+
+/// a block of `M`
+pub type Block<M> = <M as RijndaelMode>::Block;
+/// a raw key of `M`
+pub type KeyBlock<M> = <M as RijndaelMode>::Key;
+
+pub trait Streamer<M: RijndaelMode, P: Padding> {
+    fn new(iv: Block<M>, mut key: KeyBlock<M>) -> Self
     where
         Self: Sized,
     {
-        Self::new_with_ext_key(
-            iv,
-            crate::aes::KeyExpander::<M>::key_expansion(
-                &crate::aes::KeyExpander::<M>::convert_key(&key),
-            ),
-        )
+        let mut words = crate::aes::KeyExpander::<M>::convert_key(&key);
+        let streamer =
+            Self::new_with_ext_key(iv, crate::aes::KeyExpander::<M>::key_expansion(&words));
+        crate::secret::zeroize(words.as_mut());
+        crate::secret::zeroize(key.as_mut());
+        streamer
     }
-    fn new_with_ext_key(iv: Block<M>, key: [u32; M::NR_KEY * M::NB_WORDS]) -> Self;
+    fn new_with_ext_key(iv: Block<M>, key: M::KeySchedule) -> Self;
 
     fn stream_encrypt_iter(&mut self, data: &Block<M>) -> Block<M>;
     fn stream_decrypt_iter(&mut self, data: &Block<M>) -> Block<M>;
 
-    fn stream_encrypt(&mut self, data: &[u8]) -> Vec<u8> {
-        let data = P::pad_eat(data.to_owned(), M::NB_WORDS * 4);
-        let mut result = Vec::new();
-        for bid in (0..data.len()).step_by(M::NB_WORDS * 4) {
-            let bout =
-                self.stream_encrypt_iter(&data[bid..(bid + M::NB_WORDS * 4)].try_into().unwrap());
-            result = [result, bout.to_vec()].concat();
+    /// Encrypts consecutive blocks in place, modes which can process blocks
+    /// independently may override this to use the batch block cipher API.
+    fn stream_encrypt_blocks(&mut self, data: &mut [Block<M>]) {
+        for block in data.iter_mut() {
+            *block = self.stream_encrypt_iter(block);
         }
-        result
-    }
-    fn stream_decrypt(&mut self, data: &[u8]) -> Vec<u8> {
-        let mut result = Vec::new();
-        for bid in (0..data.len()).step_by(M::NB_WORDS * 4) {
-            let bout =
-                self.stream_decrypt_iter(&data[bid..(bid + M::NB_WORDS * 4)].try_into().unwrap());
-            result = [result, bout.to_vec()].concat();
+    }
+    /// Decrypts consecutive blocks in place, see [`Self::stream_encrypt_blocks`].
+    fn stream_decrypt_blocks(&mut self, data: &mut [Block<M>]) {
+        for block in data.iter_mut() {
+            *block = self.stream_decrypt_iter(block);
         }
-        P::unpad_eat(result, M::NB_WORDS * 4)
+    }
+
+    /// Creates a streamer which carries on where `self` would be once it had
+    /// encrypted the blocks up to `prev`, the last of them, or from the start
+    /// if `prev` is `None`. Returns `None` if the mode cannot be split for
+    /// encryption, which needs the state before `prev` as well.
+    #[allow(unused_variables)]
+    fn fork_encrypt(&self, prev: Option<&Block<M>>) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
+    /// Decryption counterpart of [`Self::fork_encrypt`].
+    #[allow(unused_variables)]
+    fn fork_decrypt(&self, prev: Option<&Block<M>>) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
+    /// Encrypts block-aligned `data` in place, without allocating.
+    fn encrypt_blocks_in_place(&mut self, data: &mut [u8]) {
+        in_place::<M>(data, |blocks| self.stream_encrypt_blocks(blocks));
+    }
+    /// Decrypts block-aligned `data` in place, without allocating.
+    fn decrypt_blocks_in_place(&mut self, data: &mut [u8]) -> Result<()> {
+        check_aligned::<M>(data)?;
+        in_place::<M>(data, |blocks| self.stream_decrypt_blocks(blocks));
+        Ok(())
+    }
+    /// Pads the `len` bytes of data at the start of `buf` and encrypts them in
+    /// place, returning the length of the ciphertext. `buf` must have room for
+    /// the padding, see [`Padding::pad_slice`].
+    fn encrypt_padded_in_place(&mut self, buf: &mut [u8], len: usize) -> Result<usize> {
+        let len = P::pad_slice(buf, len, M::NB_WORDS * 4)?;
+        self.encrypt_blocks_in_place(&mut buf[..len]);
+        Ok(len)
+    }
+    /// Decrypts `data` in place, returning the length of the plaintext left
+    /// at its start once the padding is removed.
+    fn decrypt_padded_in_place(&mut self, data: &mut [u8]) -> Result<usize> {
+        self.decrypt_blocks_in_place(data)?;
+        P::unpad_slice(data, M::NB_WORDS * 4)
+    }
+
+    /// Pads `data` and encrypts it in place, growing it by the padding.
+    #[cfg(feature = "alloc")]
+    fn encrypt_in_place(&mut self, data: &mut Vec<u8>) -> Result<()> {
+        let len = data.len();
+        data.resize(P::padded_len(len, M::NB_WORDS * 4)?, 0);
+        self.encrypt_padded_in_place(data, len)?;
+        Ok(())
+    }
+    /// Decrypts `data` in place and truncates the padding. `data` is left
+    /// unchanged if it is not block-aligned, and partially decrypted on other
+    /// errors.
+    #[cfg(feature = "alloc")]
+    fn decrypt_in_place(&mut self, data: &mut Vec<u8>) -> Result<()> {
+        let len = self.decrypt_padded_in_place(data)?;
+        data.truncate(len);
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
+    fn stream_encrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(P::padded_len(data.len(), M::NB_WORDS * 4)?);
+        buf.extend_from_slice(data);
+        self.encrypt_in_place(&mut buf)?;
+        Ok(buf)
+    }
+    #[cfg(feature = "alloc")]
+    fn stream_decrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut buf = data.to_owned();
+        self.decrypt_in_place(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+/// Checks that ciphertext `data` is a whole number of blocks.
+fn check_aligned<M: RijndaelMode>(data: &[u8]) -> Result<()> {
+    match data.len() % (M::NB_WORDS * 4) {
+        0 => Ok(()),
+        _ => Err(Error::InvalidCiphertextLength {
+            block: M::NB_WORDS * 4,
+            actual: data.len(),
+        }),
     }
 }
 
+/// number of blocks copied onto the stack at a time by the slice APIs
+const SLICE_BATCH: usize = 64;
+
+/// Runs `op` over block-aligned `data`, a batch of blocks at a time.
+fn in_place<M: RijndaelMode>(data: &mut [u8], mut op: impl FnMut(&mut [Block<M>])) {
+    let bs = M::NB_WORDS * 4;
+    assert_eq!(
+        data.len() % bs,
+        0,
+        "data is not a multiple of the block size"
+    );
+    let mut batch = [Block::<M>::zeroed(); SLICE_BATCH];
+    for part in data.chunks_mut(SLICE_BATCH * bs) {
+        let blocks = &mut batch[..part.len() / bs];
+        for (block, bytes) in blocks.iter_mut().zip(part.chunks_exact(bs)) {
+            block.as_mut().copy_from_slice(bytes);
+        }
+        op(blocks);
+        for (block, bytes) in blocks.iter().zip(part.chunks_exact_mut(bs)) {
+            bytes.copy_from_slice(block.as_ref());
+        }
+    }
+}
+
+mod incremental;
+pub use incremental::{Decryptor, Encryptor, Incremental};
+#[cfg(feature = "std")]
+mod io;
+#[cfg(feature = "std")]
+pub use io::{
+    CipherReader, CipherWriter, DecryptReader, DecryptWriter, EncryptReader, EncryptWriter,
+};
+#[cfg(feature = "std")]
+mod parallel;
+#[cfg(feature = "std")]
+pub use parallel::ParallelEngine;
+
 mod ecb;
 pub use ecb::ElectronicCodeBook;
 mod cbc;
 pub use cbc::CipherBlockChaining;
+
+/// Checks the batched block methods of `S` against the single block ones,
+/// with the batch split in two for the modes which chain blocks together.
+#[cfg(test)]
+fn check_blocks<S: Streamer<crate::aes::AES128, crate::padding::PKCS7>>() {
+    use rand::prelude::*;
+    use std::convert::TryInto;
+
+    let iv = hex::decode("000102030405060708090a0b0c0d0e0f")
+        .unwrap()
+        .try_into()
+        .unwrap();
+    let key = hex::decode("2b7e151628aed2a6abf7158809cf4f3c")
+        .unwrap()
+        .try_into()
+        .unwrap();
+    for n in 0..10 {
+        let mut plain = vec![[0u8; 16]; n];
+        plain
+            .iter_mut()
+            .for_each(|b| rand::thread_rng().fill_bytes(b));
+
+        let mut single = S::new(iv, key);
+        let expected = plain
+            .iter()
+            .map(|b| single.stream_encrypt_iter(b))
+            .collect::<Vec<_>>();
+        let mut blocks = plain.clone();
+        S::new(iv, key).stream_encrypt_blocks(&mut blocks);
+        assert_eq!(blocks, expected);
+
+        let mut single = S::new(iv, key);
+        let expected = blocks
+            .iter()
+            .map(|b| single.stream_decrypt_iter(b))
+            .collect::<Vec<_>>();
+        assert_eq!(expected, plain);
+        let mut batch = S::new(iv, key);
+        let (head, tail) = blocks.split_at_mut(n / 2);
+        batch.stream_decrypt_blocks(head);
+        batch.stream_decrypt_blocks(tail);
+        assert_eq!(blocks, plain);
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+#[test]
+fn test_in_place() {
+    use crate::{aes::AES128, padding::PKCS7};
+
+    let iv = [0x00; 16];
+    let key = [0x2b; 16];
+    for len in [0, 1, 16, 17, 1000, SLICE_BATCH * 16 + 3] {
+        let plain = (0..len).map(|i| i as u8).collect::<Vec<_>>();
+        let expected = CipherBlockChaining::<AES128, PKCS7>::new(iv, key)
+            .stream_encrypt(&plain)
+            .unwrap();
+
+        let mut buf = plain.clone();
+        buf.resize(len + 16, 0xFF);
+        let mut cbc = CipherBlockChaining::<AES128, PKCS7>::new(iv, key);
+        let n = cbc.encrypt_padded_in_place(&mut buf, len).unwrap();
+        assert_eq!(&buf[..n], &expected[..]);
+
+        let mut cbc = CipherBlockChaining::<AES128, PKCS7>::new(iv, key);
+        let n = cbc.decrypt_padded_in_place(&mut buf[..n]).unwrap();
+        assert_eq!(&buf[..n], &plain[..]);
+
+        let mut buf = plain.clone();
+        let mut cbc = CipherBlockChaining::<AES128, PKCS7>::new(iv, key);
+        cbc.encrypt_in_place(&mut buf).unwrap();
+        assert_eq!(buf, expected);
+        let mut cbc = CipherBlockChaining::<AES128, PKCS7>::new(iv, key);
+        cbc.decrypt_in_place(&mut buf).unwrap();
+        assert_eq!(buf, plain);
+
+        // whole blocks, carrying the chaining state between calls
+        let whole = len - len % 16;
+        let mut buf = plain[..whole].to_vec();
+        let mut cbc = CipherBlockChaining::<AES128, PKCS7>::new(iv, key);
+        let (head, tail) = buf.split_at_mut(whole / 32 * 16);
+        cbc.encrypt_blocks_in_place(head);
+        cbc.encrypt_blocks_in_place(tail);
+        assert_eq!(buf, expected[..whole]);
+
+        let mut buf = plain.clone();
+        let mut ecb = ElectronicCodeBook::<AES128, PKCS7>::new(iv, key);
+        ecb.encrypt_in_place(&mut buf).unwrap();
+        let mut ecb = ElectronicCodeBook::<AES128, PKCS7>::new(iv, key);
+        assert_eq!(ecb.stream_decrypt(&buf).unwrap(), plain);
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+#[test]
+fn test_in_place_errors() {
+    use crate::{aes::AES128, padding::NoPadding};
+
+    let mut ecb = ElectronicCodeBook::<AES128, NoPadding>::new([0; 16], [0; 16]);
+    let mut buf = vec![1; 17];
+    assert_eq!(
+        ecb.encrypt_in_place(&mut buf),
+        Err(Error::InvalidPlaintextLength {
+            block: 16,
+            actual: 17
+        })
+    );
+    assert_eq!(buf, [1; 17]);
+    assert_eq!(
+        ecb.decrypt_in_place(&mut buf),
+        Err(Error::InvalidCiphertextLength {
+            block: 16,
+            actual: 17
+        })
+    );
+    assert_eq!(buf, [1; 17]);
+}
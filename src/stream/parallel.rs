@@ -0,0 +1,357 @@
+use super::{check_aligned, Block, Streamer};
+use crate::{
+    aes::{Array, RijndaelMode},
+    error::Result,
+    padding::Padding,
+};
+use std::{
+    iter::once,
+    mem,
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Arc, Mutex, PoisonError},
+    thread::{self, JoinHandle},
+};
+
+/// default minimum number of blocks handed to a single thread (64 KiB for AES)
+const MIN_BLOCKS: usize = 4096;
+
+/// Splits large inputs of the parallelisable modes across threads.
+///
+/// The output is byte-identical to [`Streamer::stream_encrypt`] and
+/// [`Streamer::stream_decrypt`]. Inputs that are too small, and modes that
+/// cannot be split (see [`Streamer::fork_encrypt`]), are processed on the
+/// calling thread. The other threads are started on first use and kept for
+/// the calls after, until the engine is dropped.
+pub struct ParallelEngine {
+    /// number of threads, `0` for one per available core
+    pub threads: usize,
+    /// minimum number of blocks handed to a single thread
+    pub min_blocks: usize,
+    /// the workers, one fewer than the threads as the caller takes a chunk
+    pool: Mutex<Option<Pool>>,
+}
+
+impl ParallelEngine {
+    pub fn new(threads: usize) -> Self {
+        Self {
+            threads,
+            min_blocks: MIN_BLOCKS,
+            pool: Mutex::new(None),
+        }
+    }
+
+    fn thread_count(&self) -> usize {
+        match self.threads {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        }
+    }
+
+    pub fn encrypt<M, P, S>(&self, streamer: &mut S, data: &[u8]) -> Result<Vec<u8>>
+    where
+        M: RijndaelMode,
+        P: Padding,
+        S: Streamer<M, P> + Send,
+    {
+        let bs = M::NB_WORDS * 4;
+        let mut buf = Vec::with_capacity(P::padded_len(data.len(), bs)?);
+        buf.extend_from_slice(data);
+        buf.resize(P::padded_len(data.len(), bs)?, 0);
+        let len = P::pad_slice(&mut buf, data.len(), bs)?;
+        buf.truncate(len);
+        self.encrypt_in_place(streamer, &mut buf);
+        Ok(buf)
+    }
+
+    pub fn decrypt<M, P, S>(&self, streamer: &mut S, data: &[u8]) -> Result<Vec<u8>>
+    where
+        M: RijndaelMode,
+        P: Padding,
+        S: Streamer<M, P> + Send,
+    {
+        let mut buf = data.to_owned();
+        self.decrypt_in_place(streamer, &mut buf)?;
+        buf.truncate(P::unpad_slice(&buf, M::NB_WORDS * 4)?);
+        Ok(buf)
+    }
+
+    /// Encrypts block-aligned `data` in place, see
+    /// [`Streamer::encrypt_blocks_in_place`].
+    pub fn encrypt_in_place<M, P, S>(&self, streamer: &mut S, data: &mut [u8])
+    where
+        M: RijndaelMode,
+        P: Padding,
+        S: Streamer<M, P> + Send,
+    {
+        match self.fork::<M, S>(data, |prev| streamer.fork_encrypt(prev)) {
+            Some((chunk, forks)) => {
+                *streamer = self.run(chunk, forks, data, S::encrypt_blocks_in_place)
+            }
+            None => streamer.encrypt_blocks_in_place(data),
+        }
+    }
+
+    /// Decrypts block-aligned `data` in place, see
+    /// [`Streamer::decrypt_blocks_in_place`].
+    pub fn decrypt_in_place<M, P, S>(&self, streamer: &mut S, data: &mut [u8]) -> Result<()>
+    where
+        M: RijndaelMode,
+        P: Padding,
+        S: Streamer<M, P> + Send,
+    {
+        check_aligned::<M>(data)?;
+        match self.fork::<M, S>(data, |prev| streamer.fork_decrypt(prev)) {
+            Some((chunk, forks)) => {
+                // the chunks are whole blocks, which cannot fail
+                *streamer = self.run(chunk, forks, data, |s, part| {
+                    s.decrypt_blocks_in_place(part).unwrap()
+                })
+            }
+            None => streamer.decrypt_blocks_in_place(data)?,
+        }
+        Ok(())
+    }
+
+    /// Forks one streamer per chunk of the blocks of `data`, plus one holding
+    /// the state after the last block, and returns them along with the
+    /// length of the chunks in bytes. Returns `None` if the input is a single
+    /// chunk or cannot be split.
+    fn fork<M: RijndaelMode, S>(
+        &self,
+        data: &[u8],
+        fork: impl Fn(Option<&Block<M>>) -> Option<S>,
+    ) -> Option<(usize, Vec<S>)> {
+        let bs = M::NB_WORDS * 4;
+        let blocks = data.len() / bs;
+        let chunk = self
+            .min_blocks
+            .max(blocks.div_ceil(self.thread_count()))
+            .max(1);
+        if blocks <= chunk {
+            return None;
+        }
+        let prev = |offset: usize| Block::<M>::from_slice(&data[(offset - 1) * bs..offset * bs]);
+        once(fork(None))
+            .chain(
+                (chunk..blocks)
+                    .step_by(chunk)
+                    .chain(once(blocks))
+                    .map(|offset| fork(Some(&prev(offset)))),
+            )
+            .collect::<Option<Vec<_>>>()
+            .map(|forks| (chunk * bs, forks))
+    }
+
+    /// Runs `op` on every chunk of `data` with its own streamer, the first on
+    /// the calling thread and the rest on the workers, and returns the final
+    /// streamer.
+    fn run<S: Send>(
+        &self,
+        chunk: usize,
+        mut forks: Vec<S>,
+        data: &mut [u8],
+        op: impl Fn(&mut S, &mut [u8]) + Sync,
+    ) -> S {
+        let last = forks.pop().unwrap();
+        let op = &op;
+        let mut parts = forks.into_iter().zip(data.chunks_mut(chunk));
+        let (mut first, head) = parts.next().unwrap();
+
+        let workers = self.thread_count() - 1;
+        let mut pool = self.pool.lock().unwrap_or_else(PoisonError::into_inner);
+        if pool.as_ref().is_none_or(|pool| pool.size != workers) {
+            *pool = Some(Pool::new(workers));
+        }
+        pool.as_ref().unwrap().scope(
+            parts.map(|(mut streamer, part)| {
+                Box::new(move || op(&mut streamer, part)) as Box<dyn FnOnce() + Send + '_>
+            }),
+            || op(&mut first, head),
+        );
+        last
+    }
+}
+
+/// a job handed to a worker
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Threads waiting for jobs, which exit once it is dropped
+struct Pool {
+    /// number of threads asked for, some of which may have failed to start
+    size: usize,
+    jobs: Option<mpsc::Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Pool {
+    fn new(size: usize) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let queue = Arc::new(Mutex::new(queue));
+        let workers = (0..size)
+            .filter_map(|_| {
+                let queue = queue.clone();
+                thread::Builder::new()
+                    .name("aes-worker".into())
+                    .spawn(move || loop {
+                        // the lock is only held while waiting for a job
+                        let job = queue.lock().unwrap_or_else(PoisonError::into_inner).recv();
+                        match job {
+                            Ok(job) => job(),
+                            Err(_) => break,
+                        }
+                    })
+                    .ok()
+            })
+            .collect();
+        Self {
+            size,
+            jobs: Some(jobs),
+            workers,
+        }
+    }
+
+    /// Runs the `jobs` on the workers and `local` on the calling thread, and
+    /// returns once every one of them is done, so they may borrow from the
+    /// caller. A panic in any of them is resumed here, after the others.
+    fn scope<'a>(
+        &self,
+        jobs: impl Iterator<Item = Box<dyn FnOnce() + Send + 'a>>,
+        local: impl FnOnce(),
+    ) {
+        let (done, finished) = mpsc::channel();
+        for job in jobs {
+            let done = done.clone();
+            let job: Box<dyn FnOnce() + Send + 'a> = Box::new(move || {
+                let result = panic::catch_unwind(AssertUnwindSafe(job));
+                let _ = done.send(result);
+            });
+            // SAFETY: the job only outlives `'a` as far as the type system can
+            // tell. Each holds a sender of `done`, dropped once it has run or
+            // been dropped itself, and this waits below for every sender to be
+            // gone before returning, so no job is left to use what it borrows.
+            let job = unsafe { mem::transmute::<Box<dyn FnOnce() + Send + 'a>, Job>(job) };
+            match &self.jobs {
+                Some(jobs) => {
+                    if let Err(mpsc::SendError(job)) = jobs.send(job) {
+                        // no worker started
+                        job();
+                    }
+                }
+                None => job(),
+            }
+        }
+        drop(done);
+
+        let mut panicked = panic::catch_unwind(AssertUnwindSafe(local)).err();
+        // ends once the senders held by the jobs are all dropped
+        for result in finished {
+            if let Err(payload) = result {
+                panicked.get_or_insert(payload);
+            }
+        }
+        if let Some(payload) = panicked {
+            panic::resume_unwind(payload);
+        }
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        // closing the queue stops the workers once they are idle
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+macro_rules! impl_test_parallel {
+    ($st: ident) => {{
+        use crate::aes::AES128;
+        use crate::padding::PKCS7;
+        use crate::stream::$st;
+        use rand::prelude::*;
+        use std::convert::TryInto;
+
+        let iv = hex::decode("000102030405060708090a0b0c0d0e0f")
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let key = hex::decode("2b7e151628aed2a6abf7158809cf4f3c")
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let mut engine = ParallelEngine::new(3);
+        engine.min_blocks = 2;
+        for len in [0, 1, 15, 16, 17, 48, 100, 160, 1000, 4096] {
+            let mut plain = vec![0u8; len];
+            rand::thread_rng().fill_bytes(&mut plain);
+
+            let expected = $st::<AES128, PKCS7>::new(iv, key)
+                .stream_encrypt(&plain)
+                .unwrap();
+            let cipher = engine
+                .encrypt(&mut $st::<AES128, PKCS7>::new(iv, key), &plain)
+                .unwrap();
+            assert_eq!(cipher, expected);
+
+            let expected = $st::<AES128, PKCS7>::new(iv, key)
+                .stream_decrypt(&cipher)
+                .unwrap();
+            assert_eq!(expected, plain);
+            assert_eq!(
+                engine
+                    .decrypt(&mut $st::<AES128, PKCS7>::new(iv, key), &cipher)
+                    .unwrap(),
+                expected
+            );
+
+            // the streamer must carry on from the end of a parallel run
+            let mut buf = cipher.clone();
+            let mut streamer = $st::<AES128, PKCS7>::new(iv, key);
+            let (head, tail) = buf.split_at_mut(cipher.len() / 32 * 16);
+            engine.decrypt_in_place(&mut streamer, head).unwrap();
+            engine.decrypt_in_place(&mut streamer, tail).unwrap();
+            buf.truncate(PKCS7::unpad_slice(&buf, 16).unwrap());
+            assert_eq!(buf, plain);
+        }
+    }};
+}
+
+#[cfg(test)]
+#[test]
+fn test_ecb() {
+    impl_test_parallel!(ElectronicCodeBook);
+}
+
+#[cfg(test)]
+#[test]
+fn test_cbc() {
+    impl_test_parallel!(CipherBlockChaining);
+}
+
+#[cfg(test)]
+#[test]
+fn test_pool_kept() {
+    use crate::{aes::AES128, padding::NoPadding, stream::ElectronicCodeBook};
+
+    let mut engine = ParallelEngine::new(3);
+    engine.min_blocks = 1;
+    let workers = |engine: &ParallelEngine| {
+        let pool = engine.pool.lock().unwrap();
+        let pool = pool.as_ref().unwrap();
+        pool.workers
+            .iter()
+            .map(|worker| worker.thread().id())
+            .collect::<Vec<_>>()
+    };
+    let mut ecb = ElectronicCodeBook::<AES128, NoPadding>::new([0; 16], [0; 16]);
+    let mut data = vec![0; 16 * 9];
+    engine.encrypt_in_place(&mut ecb, &mut data);
+    let started = workers(&engine);
+    assert_eq!(started.len(), 2);
+    engine.decrypt_in_place(&mut ecb, &mut data).unwrap();
+    assert_eq!(workers(&engine), started);
+    assert_eq!(data, vec![0; 16 * 9]);
+}
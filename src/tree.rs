@@ -0,0 +1,107 @@
+//! Walking directory trees, and replacing the files in them
+
+use anyhow::{anyhow, Context};
+use std::{
+    fs::{self, DirEntry, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// The files among `paths`, and the regular files in the directories among
+/// them, recursively and in name order.
+pub fn files(paths: &[String]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths.iter().map(Path::new) {
+        let metadata =
+            fs::metadata(path).with_context(|| format!("Cannot read {}", path.display()))?;
+        if metadata.is_dir() {
+            walk(path, &mut files).with_context(|| format!("Cannot read {}", path.display()))?;
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+/// Adds the regular files under `dir` to `files`. Symbolic links are not
+/// followed, which keeps the walk inside `dir`.
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in entries(dir)? {
+        let ty = entry.file_type()?;
+        if ty.is_dir() {
+            walk(&entry.path(), files)?;
+        } else if ty.is_file() {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// The entries of `dir`, in name order.
+pub fn entries(dir: &Path) -> io::Result<Vec<DirEntry>> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    Ok(entries)
+}
+
+/// Whether `a` and `b` are the same regular file, under whatever paths.
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(meta_a), Ok(meta_b)) if meta_a.is_file() && meta_b.is_file() => {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                meta_a.dev() == meta_b.dev() && meta_a.ino() == meta_b.ino()
+            }
+            #[cfg(not(unix))]
+            {
+                a.canonicalize().ok() == b.canonicalize().ok()
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Replaces the file at `path` with what `write` writes, with the same
+/// permissions, or creates it like `File::create` if there is none. It is
+/// written to a temporary file next to it first, which is renamed over it
+/// once complete, so the file is never left half written.
+pub fn replace(
+    path: &Path,
+    write: impl FnOnce(&mut File) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("{} is not a file", path.display()))?;
+    let tmp = path.with_file_name(format!(
+        ".{}.{}.tmp",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+    let result = (|| -> anyhow::Result<()> {
+        let permissions = match fs::metadata(path) {
+            Ok(metadata) => Some(metadata.permissions()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        // readable by the owner only until the permissions are copied
+        #[cfg(unix)]
+        if permissions.is_some() {
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        }
+        let mut file = options.open(&tmp)?;
+        write(&mut file)?;
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions)?;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
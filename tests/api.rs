@@ -0,0 +1,238 @@
+use aes::{
+    aes::{Array, BlockCipher, Rijndael, RijndaelMode, AES128, AES192, AES256},
+    cipher::CipherSpec,
+    error::Error,
+    padding::{NoPadding, Padding, ISO10126, ISO7816, PKCS7, X923},
+    stream::{CipherBlockChaining, ElectronicCodeBook, ParallelEngine, Streamer},
+};
+
+fn key<M: RijndaelMode>(s: &str) -> M::Key {
+    M::Key::from_slice(&hex::decode(s).unwrap())
+}
+
+fn block<M: RijndaelMode>(s: &str) -> M::Block {
+    M::Block::from_slice(&hex::decode(s).unwrap())
+}
+
+const PLAIN: &str = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e5130c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710";
+
+#[test]
+fn block_cipher() {
+    // the following is from https://nvlpubs.nist.gov/nistpubs/FIPS/NIST.FIPS.197.pdf
+    macro_rules! check {
+        ($mode: ty, $key: literal, $plain: literal, $cipher: literal) => {
+            let cipher = Rijndael::<$mode>::new(&key::<$mode>($key));
+            let mut b = block::<$mode>($plain);
+            cipher.encrypt_block(&mut b);
+            assert_eq!(hex::encode(b), $cipher);
+            cipher.decrypt_block(&mut b);
+            assert_eq!(hex::encode(b), $plain);
+        };
+    }
+    check!(
+        AES128,
+        "000102030405060708090a0b0c0d0e0f",
+        "00112233445566778899aabbccddeeff",
+        "69c4e0d86a7b0430d8cdb78070b4c55a"
+    );
+    check!(
+        AES192,
+        "000102030405060708090a0b0c0d0e0f1011121314151617",
+        "00112233445566778899aabbccddeeff",
+        "dda97ca4864cdfe06eaf70a0ec0d7191"
+    );
+    check!(
+        AES256,
+        "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+        "00112233445566778899aabbccddeeff",
+        "8ea2b7ca516745bfeafc49904b496089"
+    );
+}
+
+#[test]
+fn stream_modes() {
+    // the following is from https://nvlpubs.nist.gov/nistpubs/Legacy/SP/nistspecialpublication800-38a.pdf
+    let plain = hex::decode(PLAIN).unwrap();
+    let k = key::<AES128>("2b7e151628aed2a6abf7158809cf4f3c");
+    let iv = block::<AES128>("000102030405060708090a0b0c0d0e0f");
+
+    let mut ecb = ElectronicCodeBook::<AES128, PKCS7>::new(iv, k);
+    let cipher = ecb.stream_encrypt(&plain).unwrap();
+    // a whole block of padding follows the aligned plaintext
+    assert_eq!(cipher.len(), plain.len() + 16);
+    assert_eq!(
+        hex::encode(&cipher[..plain.len()]),
+        "3ad77bb40d7a3660a89ecaf32466ef97f5d3d58503b9699de785895a96fdbaaf43b1cd7f598ece23881b00e3ed0306887b0c785e27e8ad3f8223207104725dd4"
+    );
+    let mut ecb = ElectronicCodeBook::<AES128, PKCS7>::new(iv, k);
+    assert_eq!(ecb.stream_decrypt(&cipher).unwrap(), plain);
+
+    let mut cbc = CipherBlockChaining::<AES128, PKCS7>::new(iv, k);
+    let cipher = cbc.stream_encrypt(&plain).unwrap();
+    assert_eq!(
+        hex::encode(&cipher[..plain.len()]),
+        "7649abac8119b246cee98e9b12e9197d5086cb9b507219ee95db113a917678b273bed6b8e3c1743b7116e69e222295163ff1caa1681fac09120eca307586e1a7"
+    );
+    let mut cbc = CipherBlockChaining::<AES128, PKCS7>::new(iv, k);
+    assert_eq!(cbc.stream_decrypt(&cipher).unwrap(), plain);
+
+    let mut buf = plain.clone();
+    CipherBlockChaining::<AES128, PKCS7>::new(iv, k)
+        .encrypt_in_place(&mut buf)
+        .unwrap();
+    assert_eq!(buf, cipher);
+    CipherBlockChaining::<AES128, PKCS7>::new(iv, k)
+        .decrypt_in_place(&mut buf)
+        .unwrap();
+    assert_eq!(buf, plain);
+}
+
+#[test]
+fn padding() {
+    for len in 0..=16 {
+        let data = vec![0xAA; len];
+        let padded = X923::pad_eat(data.clone(), 16).unwrap();
+        assert_eq!(padded.len() % 16, 0);
+        assert_eq!(X923::unpad_eat(padded, 16).unwrap(), data);
+        assert_eq!(
+            PKCS7::unpad_eat(PKCS7::pad_eat(data.clone(), 16).unwrap(), 16).unwrap(),
+            data
+        );
+        assert_eq!(
+            ISO10126::unpad_eat(ISO10126::pad_eat(data.clone(), 16).unwrap(), 16).unwrap(),
+            data
+        );
+        assert_eq!(
+            ISO7816::unpad_eat(ISO7816::pad_eat(data.clone(), 16).unwrap(), 16).unwrap(),
+            data
+        );
+        if len % 16 == 0 {
+            assert_eq!(NoPadding::pad_eat(data.clone(), 16).unwrap(), data);
+        } else {
+            assert!(NoPadding::pad_eat(data.clone(), 16).is_err());
+        }
+    }
+}
+
+#[test]
+fn cipher_spec() {
+    let plain = hex::decode(PLAIN).unwrap();
+    let k =
+        hex::decode("603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4").unwrap();
+    let iv = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+
+    let mut cipher = "aes-256-cbc-x923"
+        .parse::<CipherSpec>()
+        .unwrap()
+        .build(&k, &iv)
+        .unwrap();
+    let encrypted = cipher.encrypt(&plain).unwrap();
+    let mut typed =
+        CipherBlockChaining::<AES256, X923>::new(Array::from_slice(&iv), Array::from_slice(&k));
+    assert_eq!(encrypted, typed.stream_encrypt(&plain).unwrap());
+
+    let mut cipher = "aes-256-cbc-x923"
+        .parse::<CipherSpec>()
+        .unwrap()
+        .build(&k, &iv)
+        .unwrap();
+    assert_eq!(cipher.decrypt(&encrypted).unwrap(), plain);
+    for name in [
+        "aes-128-cbc-iso7816",
+        "aes-192-ecb-zero",
+        "aes-256-cbc-nopad",
+    ] {
+        assert!(name.parse::<CipherSpec>().is_ok());
+    }
+    assert!("aes-512-cbc".parse::<CipherSpec>().is_err());
+}
+
+#[test]
+fn parallel() {
+    let plain = (0..100_000).map(|i| i as u8).collect::<Vec<_>>();
+    let k = key::<AES192>("8e73b0f7da0e6452c810f32b809079e562f8ead2522c6b7b");
+    let iv = block::<AES192>("000102030405060708090a0b0c0d0e0f");
+    let engine = ParallelEngine::new(4);
+
+    let expected = CipherBlockChaining::<AES192, PKCS7>::new(iv, k)
+        .stream_encrypt(&plain)
+        .unwrap();
+    let cipher = engine
+        .encrypt(
+            &mut CipherBlockChaining::<AES192, PKCS7>::new(iv, k),
+            &plain,
+        )
+        .unwrap();
+    assert_eq!(cipher, expected);
+    let decrypted = engine
+        .decrypt(
+            &mut CipherBlockChaining::<AES192, PKCS7>::new(iv, k),
+            &cipher,
+        )
+        .unwrap();
+    assert_eq!(decrypted, plain);
+
+    let expected = ElectronicCodeBook::<AES192, PKCS7>::new(iv, k)
+        .stream_encrypt(&plain)
+        .unwrap();
+    let cipher = engine
+        .encrypt(&mut ElectronicCodeBook::<AES192, PKCS7>::new(iv, k), &plain)
+        .unwrap();
+    assert_eq!(cipher, expected);
+    let decrypted = engine
+        .decrypt(
+            &mut ElectronicCodeBook::<AES192, PKCS7>::new(iv, k),
+            &cipher,
+        )
+        .unwrap();
+    assert_eq!(decrypted, plain);
+}
+
+#[test]
+fn errors() {
+    let k = [0x2b; 16];
+    let iv = [0x00; 16];
+    let aes128 = "aes-128-cbc".parse::<CipherSpec>().unwrap();
+
+    assert_eq!(
+        aes128.build(&k[..15], &iv).err(),
+        Some(Error::InvalidKeyLength {
+            expected: 16,
+            actual: 15
+        })
+    );
+    assert_eq!(
+        aes128.build(&k, &[]).err(),
+        Some(Error::InvalidIvLength {
+            expected: 16,
+            actual: 0
+        })
+    );
+
+    let mut cipher = aes128.build(&k, &iv).unwrap();
+    assert_eq!(
+        cipher.decrypt(&[0x00; 17]),
+        Err(Error::InvalidCiphertextLength {
+            block: 16,
+            actual: 17
+        })
+    );
+    let mut cbc = CipherBlockChaining::<AES128, PKCS7>::new(iv, k);
+    assert_eq!(
+        cbc.decrypt_padded_in_place(&mut [0x00; 8]),
+        Err(Error::InvalidCiphertextLength {
+            block: 16,
+            actual: 8
+        })
+    );
+
+    // an empty ciphertext has no padding block at all
+    assert_eq!(cipher.decrypt(&[]), Err(Error::BadPadding));
+    // the last plaintext byte is 0x00, which is no valid padding length
+    let mut block = [0x00; 16];
+    Rijndael::<AES128>::new(&k).encrypt_block(&mut block);
+    let mut ecb = ElectronicCodeBook::<AES128, PKCS7>::new(iv, k);
+    assert_eq!(ecb.stream_decrypt(&block), Err(Error::BadPadding));
+    assert_eq!(PKCS7::unpad_eat(vec![0x11; 16], 16), Err(Error::BadPadding));
+    assert_eq!(X923::unpad_eat(vec![], 16), Err(Error::BadPadding));
+}
@@ -0,0 +1,955 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+const KEY: &str = "2b7e151628aed2a6abf7158809cf4f3c";
+
+/// Runs the `aes` binary with `args`, feeding it `input`.
+fn aes(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_aes"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // the child may fail and exit before reading all of it
+    match child.stdin.take().unwrap().write_all(input) {
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {}
+        result => result.unwrap(),
+    }
+    child.wait_with_output().unwrap()
+}
+
+fn run(args: &[&str], input: &[u8]) -> Vec<u8> {
+    let out = aes(args, input);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    out.stdout
+}
+
+#[test]
+fn random_iv() {
+    let plain = b"attack at dawn, bring snacks".to_vec();
+    let enc = run(
+        &["--raw", "-m", "aes-128-cbc", "-k", KEY, "-o", "enc"],
+        &plain,
+    );
+    let again = run(
+        &["--raw", "-m", "aes-128-cbc", "-k", KEY, "-o", "enc"],
+        &plain,
+    );
+    // the IV is prepended, and differs between runs
+    assert_eq!(enc.len(), 16 + 32);
+    assert_ne!(enc[..16], again[..16]);
+    assert_eq!(
+        run(
+            &["--raw", "-m", "aes-128-cbc", "-k", KEY, "-o", "dec"],
+            &enc
+        ),
+        plain
+    );
+
+    // the same as passing the prefix explicitly
+    let iv = hex::encode(&enc[..16]);
+    let explicit = run(
+        &[
+            "--raw",
+            "-m",
+            "aes-128-cbc",
+            "-k",
+            KEY,
+            "-i",
+            &iv,
+            "-o",
+            "enc",
+        ],
+        &plain,
+    );
+    assert_eq!(explicit, enc[16..]);
+
+    let hex = run(
+        &["--raw", "-a", "-m", "aes-128-cbc", "-k", KEY, "-o", "enc"],
+        b"00ff",
+    );
+    assert_eq!(hex.len(), 2 * (16 + 16));
+    assert_eq!(
+        run(
+            &["--raw", "-a", "-m", "aes-128-cbc", "-k", KEY, "-o", "dec"],
+            &hex
+        ),
+        b"00ff"
+    );
+
+    let out = aes(
+        &["--raw", "-m", "aes-128-cbc", "-k", KEY, "-o", "dec"],
+        &enc[..10],
+    );
+    assert!(!out.status.success());
+}
+
+#[test]
+fn ecb_without_iv() {
+    let plain = b"no iv needed".to_vec();
+    let enc = run(
+        &["--raw", "-m", "aes-128-ecb", "-k", KEY, "-o", "enc"],
+        &plain,
+    );
+    assert_eq!(enc.len(), 16);
+    assert_eq!(
+        run(
+            &["--raw", "-m", "aes-128-ecb", "-k", KEY, "-o", "dec"],
+            &enc
+        ),
+        plain
+    );
+}
+
+/// Containers checked in under `tests/data`, with the key and IV they were
+/// written with
+const GOLDEN: &[(&str, &str, &str)] = &[
+    ("aes-128-cbc-pkcs7", KEY, "000102030405060708090a0b0c0d0e0f"),
+    (
+        "aes-192-cbc-iso7816",
+        "8e73b0f7da0e6452c810f32b809079e562f8ead2522c6b7b",
+        "000102030405060708090a0b0c0d0e0f",
+    ),
+    (
+        "aes-256-ecb-x923",
+        "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4",
+        "-",
+    ),
+];
+
+fn data(name: &str) -> Vec<u8> {
+    std::fs::read(format!(
+        "{}/tests/data/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    ))
+    .unwrap()
+}
+
+#[test]
+fn container() {
+    let plain = data("plain.txt");
+    for (spec, key, iv) in GOLDEN {
+        let golden = data(&format!("{}.aesc", spec));
+        let enc = run(&["-m", spec, "-k", key, "-i", iv, "-o", "enc"], &plain);
+        assert_eq!(enc, golden, "{}", spec);
+        // everything but the key is read from the container
+        assert_eq!(run(&["-k", key, "-o", "dec"], &golden), plain);
+        assert_eq!(run(&["-m", spec, "-k", key, "-o", "dec"], &golden), plain);
+
+        let hex = run(
+            &["-a", "-k", key, "-o", "dec"],
+            hex::encode(&golden).as_bytes(),
+        );
+        assert_eq!(hex, hex::encode(&plain).as_bytes());
+    }
+
+    let (spec, key, _) = GOLDEN[0];
+    let enc = run(&["-m", spec, "-k", key, "-o", "enc"], &plain);
+    assert_eq!(run(&["-k", key, "-o", "dec"], &enc), plain);
+
+    let golden = data("aes-128-cbc-pkcs7.aesc");
+    let out = aes(&["-m", "aes-128-cbc-x923", "-k", key, "-o", "dec"], &golden);
+    assert!(!out.status.success());
+    let out = aes(&["-k", key, "-o", "dec"], &golden[..20]);
+    assert!(!out.status.success());
+}
+
+#[test]
+fn openssl() {
+    let plain = data("plain.txt");
+    for (name, spec, kdf) in [
+        ("aes-256-cbc-md5", "aes-256-cbc", &["--md", "md5"][..]),
+        ("aes-256-cbc-sha256", "aes-256-cbc", &[][..]),
+        ("aes-128-cbc-pbkdf2", "aes-128-cbc", &["--pbkdf2"][..]),
+        (
+            "aes-192-ecb-pbkdf2-md5",
+            "aes-192-ecb",
+            &["--iter", "1000", "--md", "md5"][..],
+        ),
+    ] {
+        let fixture = data(&format!("openssl/{}.enc", name));
+        let mut args = vec!["--openssl", "--passphrase", "hunter2", "-m", spec];
+        args.extend_from_slice(kdf);
+        let dec = [&args[..], &["-o", "dec"]].concat();
+        assert_eq!(run(&dec, &fixture), plain, "{}", name);
+
+        // the same salt reproduces the fixture
+        let salt = hex::encode(&fixture[8..16]);
+        let enc = [&args[..], &["--salt", &salt, "-o", "enc"]].concat();
+        assert_eq!(run(&enc, &plain), fixture, "{}", name);
+    }
+
+    let fixture = data("openssl/aes-256-cbc-sha256.enc");
+    let out = aes(
+        &[
+            "--openssl",
+            "--passphrase",
+            "hunter3",
+            "-m",
+            "aes-256-cbc",
+            "-o",
+            "dec",
+        ],
+        &fixture,
+    );
+    assert!(!out.status.success());
+    let out = aes(&["--openssl", "-m", "aes-256-cbc", "-o", "dec"], &fixture);
+    assert!(!out.status.success());
+}
+
+#[test]
+fn encodings() {
+    let plain = data("plain.txt");
+    let (spec, key, iv) = GOLDEN[0];
+    let golden = data(&format!("{}.aesc", spec));
+    let enc = |args: &[&str]| {
+        let args = [&["-m", spec, "-k", key, "-i", iv, "-o", "enc"], args].concat();
+        String::from_utf8(run(&args, &plain)).unwrap()
+    };
+
+    let hex = enc(&["-e", "hex"]);
+    assert_eq!(hex, hex::encode(&golden));
+    let base64 = enc(&["-e", "base64"]);
+    assert_eq!(base64.lines().next().unwrap().len(), 64);
+    assert_eq!(
+        base64.lines().map(str::len).sum::<usize>(),
+        golden.len() / 3 * 4
+    );
+    let url = enc(&["-e", "base64url", "--no-pad", "--wrap", "0"]);
+    assert_eq!(url.lines().count(), 1);
+    let armor = enc(&["-e", "armor"]);
+    assert!(armor.starts_with(concat!(
+        "-----BEGIN AES ENCRYPTED MESSAGE-----\n",
+        "Cipher: aes-128-cbc-pkcs7\n",
+        "IV: 000102030405060708090a0b0c0d0e0f\n\n"
+    )));
+    assert!(armor.ends_with("-----END AES ENCRYPTED MESSAGE-----\n"));
+
+    // decryption detects the encoding
+    for text in [&hex, &base64, &url, &armor] {
+        assert_eq!(run(&["-k", key, "-o", "dec"], text.as_bytes()), plain);
+    }
+    assert_eq!(
+        run(&["-e", "base64", "-k", key, "-o", "dec"], base64.as_bytes()),
+        plain
+    );
+    let out = aes(&["-e", "hex", "-k", key, "-o", "dec"], base64.as_bytes());
+    assert!(!out.status.success());
+
+    // armor without a container header
+    let raw = enc(&["--raw", "-e", "armor"]);
+    assert!(raw.starts_with("-----BEGIN AES ENCRYPTED MESSAGE-----\n"));
+    assert_eq!(
+        run(
+            &["--raw", "-m", spec, "-k", key, "-i", iv, "-o", "dec"],
+            raw.as_bytes()
+        ),
+        plain
+    );
+}
+
+#[test]
+fn key_sources() {
+    let (spec, key, iv) = GOLDEN[0];
+    let plain = data("plain.txt");
+    let golden = data(&format!("{}.aesc", spec));
+    let dir = std::env::temp_dir().join(format!("aes-key-sources-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = |name: &str, content: &[u8]| {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_string()
+    };
+
+    let raw = file("raw.key", &hex::decode(key).unwrap());
+    let hex = file("hex.key", format!("{}\n", key).as_bytes());
+    let base64 = file("base64.key", b"K34VFiiu0qar9xWICc9PPA==\n");
+    let iv_file = file("iv", iv.as_bytes());
+    for path in [&raw, &hex, &base64] {
+        assert_eq!(run(&["--key-file", path, "-o", "dec"], &golden), plain);
+        let args = [
+            "-m",
+            spec,
+            "--key-file",
+            path,
+            "--iv-file",
+            &iv_file,
+            "-o",
+            "enc",
+        ];
+        assert_eq!(run(&args, &plain), golden);
+    }
+
+    let input = file("golden", &golden);
+    let out = Command::new(env!("CARGO_BIN_EXE_aes"))
+        .args(["--key-env", "AES_TEST_KEY", "-o", "dec", &input])
+        .env("AES_TEST_KEY", key)
+        .output()
+        .unwrap();
+    assert_eq!(out.stdout, plain);
+    // the key on standard input, the ciphertext from a file
+    if cfg!(unix) {
+        assert_eq!(
+            run(&["--key-fd", "0", "-o", "dec", &input], key.as_bytes()),
+            plain
+        );
+    }
+
+    let fails = |args: &[&str]| {
+        let out = aes(args, &golden);
+        assert!(!out.status.success());
+        String::from_utf8(out.stderr).unwrap()
+    };
+    let short = file("short.key", &[0; 15]);
+    assert!(fails(&["--key-file", &short, "-o", "dec"])
+        .contains("The key must be 16 bytes for aes-128-cbc-pkcs7"));
+    assert!(fails(&["-k", "00", "-o", "dec"]).contains("The key must be 16 bytes"));
+    assert!(fails(&["-k", key, "--key-file", &raw, "-o", "dec"]).contains("Only one"));
+    assert!(fails(&["-o", "dec"]).contains("A key is required"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn passphrase() {
+    use aes::{container::Header, kdf::Kdf};
+
+    let plain = data("plain.txt");
+    for (kdf, cost) in [("scrypt", "4"), ("pbkdf2", "1000")] {
+        let args = ["--passphrase", "hunter2", "--kdf", kdf, "--kdf-cost", cost];
+        let enc = run(
+            &[&args[..], &["-m", "aes-256-cbc", "-o", "enc"]].concat(),
+            &plain,
+        );
+        let header = Header::read_from(&enc[..]).unwrap();
+        assert_eq!(header.salt.as_ref().map(Vec::len), Some(16));
+        let expected = kdf.parse::<Kdf>().unwrap().with_cost(cost.parse().unwrap());
+        assert_eq!(Kdf::from_bytes(header.kdf.as_ref().unwrap()), expected);
+
+        // the salt and cost are read from the container
+        let dec = run(&["--passphrase", "hunter2", "-o", "dec"], &enc);
+        assert_eq!(dec, plain, "{}", kdf);
+        let out = aes(&["--passphrase", "hunter3", "-o", "dec"], &enc);
+        // a wrong key rarely makes valid padding, but never the plaintext
+        assert!(!out.status.success() || out.stdout != plain);
+        let out = aes(&["-k", KEY, "-o", "dec"], &enc);
+        assert!(String::from_utf8_lossy(&out.stderr).contains("encrypted with a passphrase"));
+    }
+
+    let armor = run(
+        &[
+            "--passphrase",
+            "hunter2",
+            "--kdf-cost",
+            "4",
+            "-m",
+            "aes-128-cbc",
+            "-e",
+            "armor",
+            "-o",
+            "enc",
+        ],
+        &plain,
+    );
+    let text = String::from_utf8(armor.clone()).unwrap();
+    assert!(text.contains("\nSalt: ") && text.contains("\nKDF: 01040000000800000001\n"));
+    assert_eq!(
+        run(&["--passphrase", "hunter2", "-o", "dec"], &armor),
+        plain
+    );
+
+    let out = aes(
+        &[
+            "--passphrase",
+            "hunter2",
+            "-k",
+            KEY,
+            "-m",
+            "aes-128-cbc",
+            "-o",
+            "enc",
+        ],
+        &plain,
+    );
+    assert!(!out.status.success());
+    let out = aes(
+        &[
+            "--passphrase",
+            "hunter2",
+            "--raw",
+            "-m",
+            "aes-128-cbc",
+            "-o",
+            "enc",
+        ],
+        &plain,
+    );
+    assert!(!out.status.success());
+    let out = aes(
+        &[
+            "--passphrase",
+            "hunter2",
+            "--kdf-cost",
+            "64",
+            "-m",
+            "aes-128-cbc",
+            "-o",
+            "enc",
+        ],
+        &plain,
+    );
+    assert!(String::from_utf8_lossy(&out.stderr).contains("scrypt cost out of range"));
+}
+
+#[test]
+fn keygen_and_kcv() {
+    let key = run(&["keygen", "-m", "aes-192-cbc"], b"");
+    let key = String::from_utf8(key).unwrap();
+    assert_eq!(key.trim_end().len(), 48);
+    assert_ne!(run(&["keygen", "-m", "aes-192-cbc"], b""), key.as_bytes());
+    let both = run(
+        &["keygen", "--iv", "-f", "base64", "-m", "aes-128-cbc"],
+        b"",
+    );
+    let lines = String::from_utf8(both).unwrap();
+    assert_eq!(lines.lines().map(str::len).collect::<Vec<_>>(), [24, 24]);
+    assert_eq!(run(&["keygen", "-f", "raw"], b"").len(), 32);
+    assert!(!aes(&["keygen", "--iv", "-m", "aes-128-ecb"], b"")
+        .status
+        .success());
+
+    // the generated key encrypts
+    let plain = data("plain.txt");
+    let enc = run(
+        &["-m", "aes-192-cbc", "-k", key.trim_end(), "-o", "enc"],
+        &plain,
+    );
+    assert_eq!(run(&["-k", key.trim_end(), "-o", "dec"], &enc), plain);
+
+    assert_eq!(
+        run(&["kcv", "-k", KEY], b""),
+        b"KCV:      7DF76B\nCMAC KCV: 7AD386C376\n"
+    );
+    if cfg!(unix) {
+        assert_eq!(
+            run(&["kcv", "--key-fd", "0"], b"K34VFiiu0qar9xWICc9PPA==\n"),
+            b"KCV:      7DF76B\nCMAC KCV: 7AD386C376\n"
+        );
+        // a descriptor that is not open is an error, not undefined behaviour
+        let out = aes(&["kcv", "--key-fd", "1000"], b"");
+        let err = String::from_utf8_lossy(&out.stderr);
+        assert!(err.contains("descriptor 1000") && err.contains("Bad file descriptor"));
+    }
+    // hex text is a key of half its length, not raw bytes
+    let out = Command::new(env!("CARGO_BIN_EXE_aes"))
+        .args(["kcv", "--key-env", "AES_TEST_KCV_KEY"])
+        .env("AES_TEST_KCV_KEY", KEY)
+        .output()
+        .unwrap();
+    assert_eq!(out.stdout, b"KCV:      7DF76B\nCMAC KCV: 7AD386C376\n");
+    let path = std::env::temp_dir().join(format!("aes-kcv-{}", std::process::id()));
+    std::fs::write(&path, KEY).unwrap();
+    assert_eq!(
+        run(&["kcv", "--key-file", path.to_str().unwrap()], b""),
+        b"KCV:      7DF76B\nCMAC KCV: 7AD386C376\n"
+    );
+    std::fs::remove_file(&path).unwrap();
+    let out = aes(&["kcv", "-k", "00"], b"");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("16, 24 or 32 bytes"));
+}
+
+#[test]
+fn keystore() {
+    let dir = std::env::temp_dir().join(format!("aes-keystore-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("keys");
+    let path = path.to_str().unwrap();
+    let master = "000102030405060708090a0b0c0d0e0f";
+    let key = |args: &[&str]| {
+        let mut all = vec!["key"];
+        all.extend_from_slice(args);
+        all.extend_from_slice(&["--keystore", path, "--master-key", master]);
+        String::from_utf8(run(&all, b"")).unwrap()
+    };
+
+    assert!(key(&["add", "db", "-m", "aes-128-cbc"]).starts_with("Added db version 1"));
+    key(&["add", "imported", "-m", "aes-128-ecb", "-k", KEY]);
+    assert_eq!(key(&["export", "imported"]), format!("{}\n", KEY));
+    let first = key(&["export", "db"]);
+
+    // the container records the key, which decryption finds by its id
+    let plain = data("plain.txt");
+    let store = ["--keystore", path, "--master-key", master];
+    let enc = run(
+        &[&["-o", "enc", "--key-name", "db"][..], &store].concat(),
+        &plain,
+    );
+    assert_eq!(run(&[&["-o", "dec"][..], &store].concat(), &enc), plain);
+    assert_eq!(run(&["-o", "dec", "-k", first.trim_end()], &enc), plain);
+
+    // retired versions still decrypt, but only the active one encrypts
+    assert!(key(&["rotate", "db"]).starts_with("Rotated db to version 2"));
+    assert_ne!(key(&["export", "db"]), first);
+    assert_eq!(key(&["export", "db", "--version", "1"]), first);
+    assert_eq!(run(&[&["-o", "dec"][..], &store].concat(), &enc), plain);
+    let list = key(&["list"]);
+    let rows = list.lines().skip(1).collect::<Vec<_>>();
+    assert_eq!(rows.len(), 3);
+    assert!(rows[0].contains(" db ") && rows[0].ends_with("retired"));
+    assert!(rows[0].contains("aes-128-cbc-pkcs7"));
+    assert!(rows[2].contains(" db ") && rows[2].ends_with("active"));
+    // the master key in hex from the environment and from a file
+    let out = Command::new(env!("CARGO_BIN_EXE_aes"))
+        .args(["key", "list", "--keystore", path])
+        .args(["--master-key-env", "AES_TEST_MASTER_KEY"])
+        .env("AES_TEST_MASTER_KEY", master)
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), list);
+    let master_file = dir.join("master");
+    std::fs::write(&master_file, master).unwrap();
+    let from_file = run(
+        &[
+            "key",
+            "list",
+            "--keystore",
+            path,
+            "--master-key-file",
+            master_file.to_str().unwrap(),
+        ],
+        b"",
+    );
+    assert_eq!(String::from_utf8(from_file).unwrap(), list);
+    key(&["retire", "db"]);
+    let out = aes(
+        &[&["-o", "enc", "--key-name", "db"][..], &store].concat(),
+        &plain,
+    );
+    assert!(String::from_utf8_lossy(&out.stderr).contains("no active key named db"));
+
+    let out = aes(
+        &[&["-o", "dec", "--key-name", "imported"][..], &store].concat(),
+        &enc,
+    );
+    assert!(String::from_utf8_lossy(&out.stderr).contains("encrypted with the key db"));
+    let out = aes(
+        &["-o", "dec", "--keystore", path, "--master-key", KEY],
+        &enc,
+    );
+    assert!(String::from_utf8_lossy(&out.stderr).contains("authentication failed"));
+
+    // a keystore protected by a passphrase
+    let other = dir.join("other");
+    let other = other.to_str().unwrap();
+    let pass = ["--keystore", other, "--keystore-passphrase", "hunter2"];
+    let add = [
+        &["key", "add", "app", "--kdf", "pbkdf2", "--kdf-cost", "1000"][..],
+        &pass,
+    ]
+    .concat();
+    run(&add, b"");
+    let enc = run(
+        &[&["-o", "enc", "--key-name", "app"][..], &pass].concat(),
+        &plain,
+    );
+    assert_eq!(run(&[&["-o", "dec"][..], &pass].concat(), &enc), plain);
+    let out = aes(
+        &["key", "list", "--keystore", other, "--master-key", master],
+        b"",
+    );
+    assert!(String::from_utf8_lossy(&out.stderr).contains("protected by a passphrase"));
+
+    // saving leaves nothing beside the keystores, and they and exported keys
+    // are private, even over a readable file
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path: &str| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(path), 0o600);
+        assert_eq!(mode(other), 0o600);
+        let exported = dir.join("exported");
+        let exported = exported.to_str().unwrap();
+        std::fs::write(exported, b"").unwrap();
+        std::fs::set_permissions(exported, PermissionsExt::from_mode(0o644)).unwrap();
+        key(&["export", "imported", exported]);
+        assert_eq!(mode(exported), 0o600);
+        assert_eq!(
+            std::fs::read(exported).unwrap(),
+            format!("{}\n", KEY).as_bytes()
+        );
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reencrypt() {
+    let dir = std::env::temp_dir().join(format!("aes-reencrypt-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    let plain = data("plain.txt");
+    let new = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+    let enc = run(&["-m", "aes-128-cbc", "-k", KEY, "-o", "enc"], &plain);
+    std::fs::write(dir.join("a.aesc"), &enc).unwrap();
+    std::fs::write(dir.join("sub/b.aesc"), &enc).unwrap();
+    std::fs::write(dir.join("sub/notes.txt"), b"left alone").unwrap();
+    let root = dir.to_str().unwrap();
+
+    // a dry run changes nothing
+    let report = run(
+        &[
+            "reencrypt",
+            root,
+            "-n",
+            "--old-key",
+            KEY,
+            "-k",
+            new,
+            "-m",
+            "aes-256-ecb",
+        ],
+        b"",
+    );
+    let report = String::from_utf8(report).unwrap();
+    assert!(report.contains("Would re-encrypt"));
+    assert!(report.contains("from aes-128-cbc-pkcs7 to aes-256-ecb"));
+    assert!(report.ends_with("2 to re-encrypt, 1 skipped, 0 failed\n"));
+    assert_eq!(std::fs::read(dir.join("a.aesc")).unwrap(), enc);
+
+    let report = run(
+        &[
+            "reencrypt",
+            root,
+            "--old-key",
+            KEY,
+            "-k",
+            new,
+            "-m",
+            "aes-256-ecb",
+        ],
+        b"",
+    );
+    assert!(String::from_utf8(report)
+        .unwrap()
+        .ends_with("2 re-encrypted, 1 skipped, 0 failed\n"));
+    for name in &["a.aesc", "sub/b.aesc"] {
+        let enc = std::fs::read(dir.join(name)).unwrap();
+        assert_eq!(run(&["-k", new, "-o", "dec"], &enc), plain);
+        assert!(!aes(&["-k", KEY, "-o", "dec"], &enc).status.success());
+    }
+    assert_eq!(
+        std::fs::read(dir.join("sub/notes.txt")).unwrap(),
+        b"left alone"
+    );
+
+    // a wrong old key leaves the file as it was, without a temporary file
+    let before = std::fs::read(dir.join("a.aesc")).unwrap();
+    let path = dir.join("a.aesc");
+    let out = aes(
+        &[
+            "reencrypt",
+            path.to_str().unwrap(),
+            "--old-key",
+            KEY,
+            "-k",
+            new,
+        ],
+        b"",
+    );
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("the old key is not for"));
+    assert_eq!(std::fs::read(&path).unwrap(), before);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+    // onto a keystore key, found by its id from then on
+    let keys = dir.join("keys");
+    let store = ["--keystore", keys.to_str().unwrap(), "--master-key", KEY];
+    run(
+        &[&["key", "add", "disk", "-m", "aes-192-cbc"][..], &store].concat(),
+        b"",
+    );
+    run(
+        &[
+            &["reencrypt", root, "--old-key", new, "--key-name", "disk"][..],
+            &store,
+        ]
+        .concat(),
+        b"",
+    );
+    let enc = std::fs::read(dir.join("a.aesc")).unwrap();
+    assert_eq!(run(&[&["-o", "dec"][..], &store].concat(), &enc), plain);
+    run(&[&["key", "rotate", "disk"][..], &store].concat(), b"");
+    let report = run(
+        &[&["reencrypt", root, "--key-name", "disk"][..], &store].concat(),
+        b"",
+    );
+    assert!(String::from_utf8(report)
+        .unwrap()
+        .ends_with("2 re-encrypted, 2 skipped, 0 failed\n"));
+    let report = run(
+        &[&["reencrypt", root, "--key-name", "disk"][..], &store].concat(),
+        b"",
+    );
+    assert!(String::from_utf8(report)
+        .unwrap()
+        .contains("already encrypted with disk"));
+    let enc = std::fs::read(dir.join("sub/b.aesc")).unwrap();
+    assert_eq!(run(&[&["-o", "dec"][..], &store].concat(), &enc), plain);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reencrypt_unchecked_padding() {
+    let dir = std::env::temp_dir().join(format!("aes-reencrypt-pad-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let plain = b"sixteen byte blk".repeat(4);
+    let wrong = "000102030405060708090a0b0c0d0e0f";
+    let new = "f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff";
+    let mut files = Vec::new();
+    for spec in &[
+        "aes-128-cbc-nopad",
+        "aes-128-ecb-zero",
+        "aes-128-cbc-iso10126",
+    ] {
+        let path = dir.join(format!("{}.aesc", spec));
+        std::fs::write(&path, run(&["-m", spec, "-k", KEY, "-o", "enc"], &plain)).unwrap();
+        files.push((path.clone(), std::fs::read(&path).unwrap()));
+    }
+    let root = dir.to_str().unwrap();
+
+    // nothing but the padding checks the old key, so a wrong one is refused
+    // rather than decrypting into garbage
+    let out = aes(&["reencrypt", root, "--old-key", wrong, "-k", new], b"");
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("cannot tell a wrong old key"));
+    for (path, before) in &files {
+        assert_eq!(&std::fs::read(path).unwrap(), before);
+    }
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), files.len());
+
+    // unless forced, with the right key
+    let report = run(
+        &["reencrypt", root, "--old-key", KEY, "-k", new, "--force"],
+        b"",
+    );
+    assert!(String::from_utf8(report)
+        .unwrap()
+        .ends_with("3 re-encrypted, 0 skipped, 0 failed\n"));
+    for (path, _) in &files {
+        let enc = std::fs::read(path).unwrap();
+        assert_eq!(run(&["-k", new, "-o", "dec"], &enc), plain);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// The paths under `root`, relative to it and in order.
+fn listing(root: &std::path::Path) -> Vec<std::path::PathBuf> {
+    let mut paths = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                dirs.push(path.clone());
+            }
+            paths.push(path.strip_prefix(root).unwrap().to_path_buf());
+        }
+    }
+    paths.sort();
+    paths
+}
+
+#[test]
+fn recursive() {
+    use std::fs::{self, File};
+    use std::time::{Duration, SystemTime};
+
+    let dir = std::env::temp_dir().join(format!("aes-recursive-{}", std::process::id()));
+    let src = dir.join("src");
+    fs::create_dir_all(src.join("sub/deep")).unwrap();
+    fs::create_dir_all(src.join("empty")).unwrap();
+    fs::write(src.join("plain.txt"), data("plain.txt")).unwrap();
+    fs::write(src.join("sub/deep/empty file"), b"").unwrap();
+    fs::write(src.join("sub/ünïcödé.bin"), [0, 1, 2]).unwrap();
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    for path in &["plain.txt", "sub"] {
+        File::open(src.join(path))
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode =
+            |path, mode| fs::set_permissions(src.join(path), PermissionsExt::from_mode(mode));
+        mode("plain.txt", 0o640).unwrap();
+        mode("sub", 0o700).unwrap();
+    }
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+    let src_path = path("src");
+
+    for names in &[false, true] {
+        let (enc, dec) = (
+            path(&format!("enc-{}", names)),
+            path(&format!("dec-{}", names)),
+        );
+        let names: &[&str] = match names {
+            true => &["--encrypt-names"],
+            false => &[],
+        };
+        let enc_args = [
+            "-r",
+            "-o",
+            "enc",
+            "-m",
+            "aes-128-cbc",
+            "-k",
+            KEY,
+            &src_path,
+            &enc,
+        ];
+        run(&[&enc_args[..], names].concat(), b"");
+        let encrypted = listing(dir.join(&enc).as_ref());
+        assert_eq!(encrypted.len(), 6);
+        assert_eq!(encrypted == listing(&src), names.is_empty());
+
+        let dec_args = ["-r", "-o", "dec", "-k", KEY, &enc, &dec];
+        run(&[&dec_args[..], names].concat(), b"");
+        assert_eq!(listing(dir.join(&dec).as_ref()), listing(&src));
+        for file in listing(&src) {
+            let (before, after) = (src.join(&file), dir.join(&dec).join(&file));
+            let (meta, meta_after) = (
+                fs::metadata(&before).unwrap(),
+                fs::metadata(&after).unwrap(),
+            );
+            assert_eq!(meta.permissions(), meta_after.permissions(), "{:?}", file);
+            assert_eq!(
+                meta.modified().unwrap(),
+                meta_after.modified().unwrap(),
+                "{:?}",
+                file
+            );
+            if meta.is_file() {
+                assert_eq!(fs::read(&before).unwrap(), fs::read(&after).unwrap());
+            }
+        }
+    }
+
+    // names encrypt the same every time, and only decrypt with their key
+    let again = path("again");
+    run(
+        &[
+            "-r",
+            "-o",
+            "enc",
+            "-m",
+            "aes-128-cbc",
+            "-k",
+            KEY,
+            &src_path,
+            &again,
+            "--encrypt-names",
+        ],
+        b"",
+    );
+    assert_eq!(
+        listing(dir.join("again").as_ref()),
+        listing(dir.join("enc-true").as_ref())
+    );
+    let other = "000102030405060708090a0b0c0d0e0f";
+    let out = aes(
+        &[
+            "-r",
+            "-o",
+            "dec",
+            "-k",
+            other,
+            &again,
+            &path("bad"),
+            "--encrypt-names",
+        ],
+        b"",
+    );
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("encrypted with another key"));
+
+    // a failed decryption leaves the files it would have replaced as they were
+    let (enc, dec) = (dir.join("enc-false"), dir.join("dec-false"));
+    let truncated = fs::read(enc.join("plain.txt")).unwrap();
+    fs::write(enc.join("plain.txt"), &truncated[..truncated.len() - 1]).unwrap();
+    fs::write(dec.join("plain.txt"), b"before").unwrap();
+    let dec_args = ["-r", "-o", "dec", "-k", KEY];
+    let out = aes(
+        &[
+            &dec_args[..],
+            &[enc.to_str().unwrap(), dec.to_str().unwrap()],
+        ]
+        .concat(),
+        b"",
+    );
+    assert!(!out.status.success());
+    assert_eq!(fs::read(dec.join("plain.txt")).unwrap(), b"before");
+    assert_eq!(listing(&dec), listing(&src));
+
+    let out = aes(
+        &[
+            "-r",
+            "-o",
+            "enc",
+            "-m",
+            "aes-128-cbc",
+            "-k",
+            KEY,
+            &src_path,
+            &path("src/out"),
+        ],
+        b"",
+    );
+    assert!(String::from_utf8_lossy(&out.stderr).contains("cannot be inside the input"));
+    assert!(!src.join("out").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn output_file() {
+    let dir = std::env::temp_dir().join(format!("aes-output-file-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    let (input, output) = (input.to_str().unwrap(), output.to_str().unwrap());
+    let plain = data("plain.txt");
+    std::fs::write(input, &plain).unwrap();
+
+    // encrypting a file onto itself would read back its own output
+    let out = aes(
+        &["-o", "enc", "-m", "aes-128-cbc", "-k", KEY, input, input],
+        b"",
+    );
+    assert!(String::from_utf8_lossy(&out.stderr).contains("is the input file"));
+    assert_eq!(std::fs::read(input).unwrap(), plain);
+
+    // a failed decryption leaves the output as it was
+    std::fs::write(output, b"before").unwrap();
+    let out = aes(&["-o", "dec", "-k", KEY, input, output], b"");
+    assert!(!out.status.success());
+    assert_eq!(std::fs::read(output).unwrap(), b"before");
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+    run(
+        &["-o", "enc", "-m", "aes-128-cbc", "-k", KEY, input, output],
+        b"",
+    );
+    assert_eq!(run(&["-o", "dec", "-k", KEY, output], b""), plain);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,40 @@
+use aes::cipher::CipherSpec;
+use aes::container::Header;
+
+fn data(name: &str) -> Vec<u8> {
+    std::fs::read(format!(
+        "{}/tests/data/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    ))
+    .unwrap()
+}
+
+#[test]
+fn golden() {
+    let plain = data("plain.txt");
+    for (name, key) in [
+        ("aes-128-cbc-pkcs7", "2b7e151628aed2a6abf7158809cf4f3c"),
+        (
+            "aes-192-cbc-iso7816",
+            "8e73b0f7da0e6452c810f32b809079e562f8ead2522c6b7b",
+        ),
+        (
+            "aes-256-ecb-x923",
+            "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4",
+        ),
+    ] {
+        let golden = data(&format!("{}.aesc", name));
+        let mut body = &golden[..];
+        let header = Header::read_from(&mut body).unwrap();
+        assert_eq!(header.spec, name.parse::<CipherSpec>().unwrap());
+        assert_eq!(header.iv.len(), header.spec.iv_len());
+        assert_eq!(header.tag_len, 0);
+        // writing the header back reproduces the file
+        assert_eq!(header.to_bytes(), golden[..golden.len() - body.len()]);
+
+        let key = hex::decode(key).unwrap();
+        let mut cipher = header.spec.build(&key, &header.iv).unwrap();
+        assert_eq!(cipher.decrypt(body).unwrap(), plain);
+    }
+}
@@ -0,0 +1,151 @@
+use aes::cipher::CipherSpec;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
+
+const CHUNK: usize = 1 << 16;
+
+/// An endless stream of pseudo random bytes, produced on the fly
+struct Synthetic(u64);
+
+impl Read for Synthetic {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        for b in buf.iter_mut() {
+            // xorshift64
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            *b = self.0 as u8;
+        }
+        Ok(buf.len())
+    }
+}
+
+/// Encrypts `len` synthetic bytes chunk by chunk, decrypts the ciphertext as
+/// it is produced and compares it with the regenerated plaintext, so that
+/// nothing proportional to `len` is ever held in memory.
+fn round_trip(spec: &str, len: u64, threads: usize) {
+    let spec = spec.parse::<CipherSpec>().unwrap();
+    let key = [0x42; 32];
+    let key = &key[..spec.key_size.name().parse::<usize>().unwrap() / 8];
+    let iv = [0x24; 16];
+    let mut enc = spec.build(key, &iv).unwrap();
+    let mut dec = spec.build(key, &iv).unwrap();
+    enc.set_threads(threads);
+    dec.set_threads(threads);
+
+    let mut source = Synthetic(0x1234_5678_9abc_def0).take(len);
+    let mut expected = Synthetic(0x1234_5678_9abc_def0);
+    let mut chunk = vec![0; CHUNK];
+    let mut cipher = Vec::with_capacity(CHUNK + 32);
+    let mut plain = Vec::with_capacity(CHUNK + 32);
+    let mut check = vec![0; CHUNK + 32];
+    let mut total = 0;
+
+    let mut compare = |plain: &[u8]| {
+        expected.read_exact(&mut check[..plain.len()]).unwrap();
+        assert!(
+            plain == &check[..plain.len()],
+            "mismatch after {} bytes",
+            total
+        );
+        total += plain.len() as u64;
+    };
+    loop {
+        let n = source.read(&mut chunk).unwrap();
+        if n == 0 {
+            break;
+        }
+        cipher.clear();
+        enc.encrypt_update(&chunk[..n], &mut cipher);
+        plain.clear();
+        dec.decrypt_update(&cipher, &mut plain);
+        compare(&plain);
+    }
+    cipher.clear();
+    enc.encrypt_finalize(&mut cipher).unwrap();
+    plain.clear();
+    dec.decrypt_update(&cipher, &mut plain);
+    dec.decrypt_finalize(&mut plain).unwrap();
+    compare(&plain);
+
+    assert_eq!(total, len);
+    // the buffers never grew beyond a chunk
+    assert!(cipher.capacity() < 2 * CHUNK && plain.capacity() < 2 * CHUNK);
+}
+
+#[test]
+fn streaming() {
+    round_trip("aes-128-cbc", 4 * CHUNK as u64 + 7, 1);
+    round_trip("aes-256-ecb-x923", 2 * CHUNK as u64 + 16, 4);
+}
+
+/// Run with `cargo test --release -- --ignored`.
+#[test]
+#[ignore]
+fn streaming_multi_gigabyte() {
+    round_trip("aes-128-cbc", (5 << 30) + 3, 0);
+    round_trip("aes-256-ecb-pkcs7", 3 << 30, 0);
+}
+
+/// the size of the chunks the `aes` binary reads its input in
+const CLI_CHUNK: u64 = 1 << 20;
+
+/// the most memory the `aes` binary may use, whatever the size of its input
+const CLI_MEMORY: u64 = 32 << 20;
+
+/// The peak resident memory of the process, or 0 where it cannot be read.
+fn peak_memory(pid: u32) -> u64 {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).unwrap_or_default();
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .and_then(|kb| kb.trim().trim_end_matches(" kB").parse::<u64>().ok())
+        .map_or(0, |kb| kb << 10)
+}
+
+/// Pipes synthetic bytes through `aes`, which has to write out ciphertext
+/// before its input ends, and stay within a fixed amount of memory. A debug
+/// build only gets a few chunks, as it encrypts slowly.
+#[test]
+fn streaming_cli() {
+    let len = match cfg!(debug_assertions) {
+        true => 3 * CLI_CHUNK,
+        false => 256 * CLI_CHUNK,
+    };
+    let mut child = Command::new(env!("CARGO_BIN_EXE_aes"))
+        .args(["-o", "enc", "-m", "aes-128-ecb", "-k"])
+        .arg("2b7e151628aed2a6abf7158809cf4f3c")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let (mut stdin, mut stdout) = (child.stdin.take().unwrap(), child.stdout.take().unwrap());
+
+    // the input is held open until enough of the output arrives, which it
+    // would never do if `aes` read all of it first
+    let (close, closed) = mpsc::channel();
+    let writer = std::thread::spawn(move || {
+        std::io::copy(&mut Synthetic(0x1234_5678_9abc_def0).take(len), &mut stdin).unwrap();
+        closed.recv_timeout(Duration::from_secs(120)).is_ok()
+    });
+    let (mut total, mut peak) = (0, 0);
+    let mut buf = vec![0; CHUNK];
+    loop {
+        let n = stdout.read(&mut buf).unwrap();
+        if n == 0 {
+            break;
+        }
+        total += n as u64;
+        if total + 2 * CLI_CHUNK >= len {
+            let _ = close.send(());
+        }
+        peak = peak.max(peak_memory(child.id()));
+    }
+    assert!(writer.join().unwrap(), "no output before the input ended");
+    assert!(child.wait().unwrap().success());
+    // a header, and the padding of the last block
+    assert!(total > len && total < len + 1024);
+    assert!(peak < CLI_MEMORY, "{} bytes of memory", peak);
+}
@@ -0,0 +1,52 @@
+//! Checks that key material is wiped before its memory is freed, by looking
+//! for it in every block handed back to the allocator.
+
+use aes::secret::SecretKey;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// the bytes to look for, unlikely to turn up in freed memory by chance
+const MARKER: &[u8] = b"\x8b\x1d secret key marker \x8b\x1d";
+
+static LEAKED: AtomicBool = AtomicBool::new(false);
+
+struct Watch;
+
+unsafe impl GlobalAlloc for Watch {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let freed = std::slice::from_raw_parts(ptr, layout.size());
+        if freed.windows(MARKER.len()).any(|w| w == MARKER) {
+            LEAKED.store(true, Ordering::SeqCst);
+        }
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Watch = Watch;
+
+/// Whether the marker was in any memory freed by `f`.
+fn leaks(f: impl FnOnce()) -> bool {
+    LEAKED.store(false, Ordering::SeqCst);
+    f();
+    LEAKED.swap(false, Ordering::SeqCst)
+}
+
+#[test]
+fn drop_wipes_secret_key() {
+    // a plain `Vec` is freed as is, which shows the check works
+    assert!(leaks(|| drop(MARKER.to_vec())));
+
+    assert!(!leaks(|| drop(SecretKey::new(MARKER.to_vec()))));
+    assert!(!leaks(|| drop(SecretKey::new(MARKER.to_vec()).clone())));
+    // including what was truncated off, which is still allocated
+    assert!(!leaks(|| {
+        let mut key = MARKER.to_vec();
+        key.truncate(4);
+        drop(SecretKey::new(key));
+    }));
+}